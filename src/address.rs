@@ -0,0 +1,557 @@
+//! `address` type: a structured replacement for the JSONB returned by `to_address`.
+//!
+//! The text form of the type is the same JSON object as the one built by `to_address`, extended by
+//! the optional keys `region`, `houseNumber`, `apartment` and `buildingName`. Stored blobs are
+//! converted with a cast, and converted back for clients expecting JSONB:
+//!
+//! ```sql
+//! alter table site alter column adr type bfn.address using adr::bfn.address;
+//! select bfn.address_city(adr), adr::jsonb from site;
+//! ```
+#![allow(non_camel_case_types)]
+use crate::gps::{check_coordinates, normalize_coordinates};
+use crate::parse_strict::ParseError;
+#[allow(unused_imports)]
+use pgrx::prelude::{
+    default, extension_sql, pg_extern, pg_schema, pg_test, AnyNumeric, InOutFuncs, PostgresEq,
+    PostgresHash, PostgresOrd, PostgresType,
+};
+use pgrx::StringInfo;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::cmp::Ordering;
+use std::ffi::CStr;
+use std::hash::{Hash, Hasher};
+
+/// Postal address.
+///
+/// Text fields are trimmed, empty text is stored as `null`. Addresses are equal if all fields
+/// are equal.
+#[derive(
+    Clone,
+    Debug,
+    Default,
+    Serialize,
+    Deserialize,
+    PostgresType,
+    PostgresEq,
+    PostgresOrd,
+    PostgresHash,
+)]
+#[inoutfuncs]
+pub struct address {
+    pub street: Option<String>,
+    pub city: Option<String>,
+    pub postal_code: Option<String>,
+    pub country: Option<String>,
+    /// Latitude and longitude.
+    pub gps: Option<[f64; 2]>,
+    pub adr_type: Option<String>,
+    pub region: Option<String>,
+    pub house_number: Option<String>,
+    pub apartment: Option<String>,
+    pub building_name: Option<String>,
+}
+
+/// Trims text, returning `None` for empty text.
+fn clean(value: Option<&str>) -> Option<String> {
+    value
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+        .map(str::to_string)
+}
+
+impl address {
+    /// Returns the text fields in the order they are compared.
+    fn text_fields(&self) -> [&Option<String>; 9] {
+        [
+            &self.country,
+            &self.postal_code,
+            &self.city,
+            &self.street,
+            &self.house_number,
+            &self.apartment,
+            &self.building_name,
+            &self.region,
+            &self.adr_type,
+        ]
+    }
+
+    /// Converts an object with the keys of `to_address` and the extra keys to an address.
+    ///
+    /// Numbers are accepted for text fields (e.g. `"postalCode": 10115`), `gps` must be `null` or
+    /// an array of latitude and longitude within range, as checked by `to_address`. Other keys are
+    /// rejected, so no data is lost silently.
+    pub fn from_json(value: &Value) -> Result<address, ParseError> {
+        let invalid = |detail: String| {
+            ParseError::invalid(
+                format!("invalid input syntax for type address: \"{}\"", value),
+                detail,
+            )
+        };
+        let object = value
+            .as_object()
+            .ok_or_else(|| invalid("An address must be a JSON object.".to_string()))?;
+        let mut adr = address::default();
+        for (key, v) in object {
+            let text = match v {
+                Value::Null => None,
+                Value::String(s) => clean(Some(s)),
+                Value::Number(n) => Some(n.to_string()),
+                _ if key == "gps" => None,
+                _ => return Err(invalid(format!("Key \"{}\" must be text.", key))),
+            };
+            match key.as_str() {
+                "address" => adr.street = text,
+                "city" => adr.city = text,
+                "postalCode" => adr.postal_code = text,
+                "country" => adr.country = text,
+                "type" => adr.adr_type = text,
+                "region" => adr.region = text,
+                "houseNumber" => adr.house_number = text,
+                "apartment" => adr.apartment = text,
+                "buildingName" => adr.building_name = text,
+                "gps" => {
+                    adr.gps = match v {
+                        Value::Null => None,
+                        Value::Array(a) => match a.as_slice() {
+                            [lat, lon] => {
+                                let (Some(lat), Some(lon)) = (lat.as_f64(), lon.as_f64()) else {
+                                    return Err(invalid(GPS_DETAIL.to_string()));
+                                };
+                                Some(
+                                    check_coordinates(lat, lon, false)
+                                        .map_err(|e| invalid(e.detail))?,
+                                )
+                            }
+                            _ => return Err(invalid(GPS_DETAIL.to_string())),
+                        },
+                        _ => return Err(invalid(GPS_DETAIL.to_string())),
+                    }
+                }
+                _ => return Err(invalid(format!("Unknown key \"{}\".", key))),
+            }
+        }
+        Ok(adr)
+    }
+
+    /// Converts the address to the object returned by `to_address`.
+    ///
+    /// The keys of `to_address` are always present, the extra keys only if they are set,
+    /// so addresses without extra fields convert to the same JSONB as `to_address` returns.
+    pub fn to_json(&self) -> Value {
+        let mut object = json!({
+            "address": self.street,
+            "city": self.city,
+            "postalCode": self.postal_code,
+            "country": self.country,
+            "gps": self.gps,
+            "type": self.adr_type
+        });
+        let map: &mut Map<String, Value> = object.as_object_mut().unwrap();
+        for (key, value) in [
+            ("region", &self.region),
+            ("houseNumber", &self.house_number),
+            ("apartment", &self.apartment),
+            ("buildingName", &self.building_name),
+        ] {
+            if let Some(value) = value {
+                map.insert(key.to_string(), json!(value));
+            }
+        }
+        object
+    }
+}
+
+/// Detail of the error for an invalid `gps` key.
+const GPS_DETAIL: &str = "Key \"gps\" must be null or an array of latitude and longitude.";
+
+impl PartialEq for address {
+    fn eq(&self, other: &address) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for address {}
+
+impl PartialOrd for address {
+    fn partial_cmp(&self, other: &address) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Orders by country, postal code, city, street, the other text fields and coordinates.
+impl Ord for address {
+    fn cmp(&self, other: &address) -> Ordering {
+        self.text_fields()
+            .cmp(&other.text_fields())
+            .then_with(|| match (self.gps, other.gps) {
+                (Some(a), Some(b)) => a[0].total_cmp(&b[0]).then(a[1].total_cmp(&b[1])),
+                (a, b) => a.is_some().cmp(&b.is_some()),
+            })
+    }
+}
+
+impl Hash for address {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.text_fields().hash(state);
+        self.gps.map(|gps| gps.map(f64::to_bits)).hash(state);
+    }
+}
+
+impl InOutFuncs for address {
+    fn input(input: &CStr) -> address {
+        let input = input.to_string_lossy();
+        let value: Value = serde_json::from_str(&input).unwrap_or_else(|e| {
+            ParseError::invalid(
+                format!("invalid input syntax for type address: \"{}\"", input),
+                e.to_string(),
+            )
+            .report()
+        });
+        address::from_json(&value).unwrap_or_else(|e| e.report())
+    }
+
+    fn output(&self, buffer: &mut StringInfo) {
+        buffer.push_str(&self.to_json().to_string());
+    }
+}
+
+/// Creates an address.
+///
+/// # Parameters
+/// - `street`: The street address, e.g. `Narva mnt` or `Narva mnt 7`. Defaults to `null`.
+/// - `city`: The city name. Defaults to `null`.
+/// - `postal_code`: The postal or ZIP code. Defaults to `null`.
+/// - `country`: The country name or code. Defaults to `null`.
+/// - `gps`: Latitude and longitude. If fewer than two values are present, or the coordinates
+///   are out of range, the coordinates are `null`, as in `to_address`. Defaults to `null`.
+/// - `adr_type`: The type of address (e.g., "home", "work"). Defaults to `null`.
+/// - `region`: The region, state or county. Defaults to `null`.
+/// - `house_number`: The house number, if not part of `street`. Defaults to `null`.
+/// - `apartment`: The apartment or unit number. Defaults to `null`.
+/// - `building_name`: The name of the building. Defaults to `null`.
+///
+/// # Returns
+/// The address, with text trimmed and empty text stored as `null`.
+#[allow(clippy::too_many_arguments)]
+#[pg_extern(immutable, parallel_safe, create_or_replace)]
+pub fn new_address(
+    street: default!(Option<&str>, "NULL"),
+    city: default!(Option<&str>, "NULL"),
+    postal_code: default!(Option<&str>, "NULL"),
+    country: default!(Option<&str>, "NULL"),
+    gps: default!(Option<Vec<Option<AnyNumeric>>>, "NULL"),
+    adr_type: default!(Option<&str>, "NULL"),
+    region: default!(Option<&str>, "NULL"),
+    house_number: default!(Option<&str>, "NULL"),
+    apartment: default!(Option<&str>, "NULL"),
+    building_name: default!(Option<&str>, "NULL"),
+) -> address {
+    let gps = gps.and_then(|vec| normalize_coordinates(&vec, false, None).ok().flatten());
+    address {
+        street: clean(street),
+        city: clean(city),
+        postal_code: clean(postal_code),
+        country: clean(country),
+        gps,
+        adr_type: clean(adr_type),
+        region: clean(region),
+        house_number: clean(house_number),
+        apartment: clean(apartment),
+        building_name: clean(building_name),
+    }
+}
+
+/// Converts an address to JSONB. Used as the cast `address::jsonb`.
+///
+/// # Returns
+/// A `pgrx::JsonB` object with the keys of `to_address` (`address`, `city`, `postalCode`,
+/// `country`, `gps`, `type`), and `region`, `houseNumber`, `apartment` and `buildingName`
+/// if they are set.
+#[pg_extern(immutable, parallel_safe, create_or_replace)]
+pub fn address_to_jsonb(value: address) -> pgrx::JsonB {
+    pgrx::JsonB(value.to_json())
+}
+
+/// Converts JSONB to an address. Used as the cast `jsonb::address`.
+///
+/// # Parameters
+/// - `value`: An object as returned by `to_address`, optionally with the keys `region`,
+///   `houseNumber`, `apartment` and `buildingName`. Missing keys are `null`.
+///
+/// # Returns
+/// The address.
+///
+/// # Errors
+/// - `22P02` (`invalid_text_representation`) if `value` is not an object, has other keys,
+///   or a key has a value of the wrong type.
+#[pg_extern(immutable, parallel_safe, create_or_replace)]
+pub fn jsonb_to_address(value: pgrx::JsonB) -> address {
+    address::from_json(&value.0).unwrap_or_else(|e| e.report())
+}
+
+extension_sql!(
+    r#"
+CREATE CAST (address AS jsonb) WITH FUNCTION address_to_jsonb(address) AS ASSIGNMENT;
+CREATE CAST (jsonb AS address) WITH FUNCTION jsonb_to_address(jsonb);
+"#,
+    name = "address_casts",
+    requires = [address_to_jsonb, jsonb_to_address]
+);
+
+/// Returns the street address of an address, the `address` key of `to_address`.
+#[pg_extern(immutable, parallel_safe, create_or_replace)]
+pub fn address_street(value: address) -> Option<String> {
+    value.street
+}
+
+/// Returns the city of an address.
+#[pg_extern(immutable, parallel_safe, create_or_replace)]
+pub fn address_city(value: address) -> Option<String> {
+    value.city
+}
+
+/// Returns the postal code of an address.
+#[pg_extern(immutable, parallel_safe, create_or_replace)]
+pub fn address_postal_code(value: address) -> Option<String> {
+    value.postal_code
+}
+
+/// Returns the country of an address.
+#[pg_extern(immutable, parallel_safe, create_or_replace)]
+pub fn address_country(value: address) -> Option<String> {
+    value.country
+}
+
+/// Returns the coordinates of an address as an array of latitude and longitude.
+#[pg_extern(immutable, parallel_safe, create_or_replace)]
+pub fn address_gps(value: address) -> Option<Vec<f64>> {
+    value.gps.map(Vec::from)
+}
+
+/// Returns the type of an address (e.g., "home", "work").
+#[pg_extern(immutable, parallel_safe, create_or_replace)]
+pub fn address_type(value: address) -> Option<String> {
+    value.adr_type
+}
+
+/// Returns the region of an address.
+#[pg_extern(immutable, parallel_safe, create_or_replace)]
+pub fn address_region(value: address) -> Option<String> {
+    value.region
+}
+
+/// Returns the house number of an address.
+#[pg_extern(immutable, parallel_safe, create_or_replace)]
+pub fn address_house_number(value: address) -> Option<String> {
+    value.house_number
+}
+
+/// Returns the apartment of an address.
+#[pg_extern(immutable, parallel_safe, create_or_replace)]
+pub fn address_apartment(value: address) -> Option<String> {
+    value.apartment
+}
+
+/// Returns the building name of an address.
+#[pg_extern(immutable, parallel_safe, create_or_replace)]
+pub fn address_building_name(value: address) -> Option<String> {
+    value.building_name
+}
+
+#[cfg(any(test, feature = "pg_test"))]
+#[pg_schema]
+mod tests {
+    use super::*;
+    use crate::to_address;
+
+    fn tallinn() -> address {
+        new_address(
+            Some("Narva mnt"),
+            Some(" Tallinn "),
+            Some("10117"),
+            Some("Estonia"),
+            Some(vec![
+                Some(AnyNumeric::try_from(59.437).unwrap()),
+                Some(AnyNumeric::try_from(24.745).unwrap()),
+            ]),
+            Some("work"),
+            Some("Harju maakond"),
+            Some("7"),
+            Some(""),
+            Some("Foorum"),
+        )
+    }
+
+    /// Tests `address` text input and output
+    #[pg_test]
+    fn test_address() {
+        let adr = tallinn();
+        let text = std::ffi::CString::new(adr.to_json().to_string()).unwrap();
+        assert_eq!(adr, address::input(&text));
+        assert!(address::from_json(&json!({"address": "Narva mnt", "floor": 2})).is_err());
+        assert!(address::from_json(&json!({"gps": [59.4]})).is_err());
+        assert!(address::from_json(&json!({"gps": [999, -999]})).is_err());
+        assert!(address::from_json(&json!({"gps": [24.745, 181]})).is_err());
+        assert!(address::from_json(&json!(["Tallinn"])).is_err());
+        assert_ne!(
+            adr,
+            new_address(
+                None,
+                Some("Tallinn"),
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None
+            )
+        );
+    }
+
+    /// Tests `new_address`
+    #[pg_test]
+    fn test_new_address() {
+        let adr = tallinn();
+        assert_eq!(Some("Tallinn".to_string()), adr.city);
+        assert_eq!(None, adr.apartment);
+        assert_eq!(Some([59.437, 24.745]), adr.gps);
+        let adr = new_address(
+            None,
+            None,
+            None,
+            None,
+            Some(vec![Some(AnyNumeric::from(59))]),
+            None,
+            None,
+            None,
+            None,
+            None,
+        );
+        assert_eq!(None, adr.gps);
+    }
+
+    /// Tests `address_to_jsonb`
+    #[pg_test]
+    fn test_address_to_jsonb() {
+        let gps = vec![
+            Some(AnyNumeric::try_from(1.23).unwrap()),
+            Some(AnyNumeric::try_from(4.56).unwrap()),
+        ];
+        let adr = new_address(
+            Some("Main St."),
+            Some("New York"),
+            Some("12345"),
+            Some("USA"),
+            Some(gps.clone()),
+            Some("home"),
+            None,
+            None,
+            None,
+            None,
+        );
+        let legacy = to_address(
+            Some("Main St."),
+            Some("New York"),
+            Some("12345"),
+            Some("USA"),
+            Some(gps),
+            Some("home"),
+            false,
+            None,
+            false,
+            None,
+        );
+        assert_eq!(legacy.0, address_to_jsonb(adr).0);
+        let json = address_to_jsonb(tallinn()).0;
+        assert_eq!(json!("7"), json["houseNumber"]);
+        assert_eq!(json!("Foorum"), json["buildingName"]);
+        assert!(json.get("apartment").is_none());
+    }
+
+    /// Tests `jsonb_to_address`
+    #[pg_test]
+    fn test_jsonb_to_address() {
+        let adr = jsonb_to_address(address_to_jsonb(tallinn()));
+        assert_eq!(tallinn(), adr);
+        let adr = jsonb_to_address(pgrx::JsonB(json!({"postalCode": 10117, "city": ""})));
+        assert_eq!(Some("10117".to_string()), adr.postal_code);
+        assert_eq!(None, adr.city);
+    }
+
+    /// Tests `jsonb_to_address` with an unknown key
+    #[pg_test(error = "invalid input syntax for type address: \"{\"floor\":2}\"")]
+    fn test_jsonb_to_address_error() {
+        jsonb_to_address(pgrx::JsonB(json!({"floor": 2})));
+    }
+
+    /// Tests `jsonb_to_address` with coordinates out of range
+    #[pg_test(error = "invalid input syntax for type address: \"{\"gps\":[999,-999]}\"")]
+    fn test_jsonb_to_address_gps_error() {
+        jsonb_to_address(pgrx::JsonB(json!({"gps": [999, -999]})));
+    }
+
+    /// Tests `address_street`
+    #[pg_test]
+    fn test_address_street() {
+        assert_eq!(Some("Narva mnt".to_string()), address_street(tallinn()));
+    }
+
+    /// Tests `address_city`
+    #[pg_test]
+    fn test_address_city() {
+        assert_eq!(Some("Tallinn".to_string()), address_city(tallinn()));
+    }
+
+    /// Tests `address_postal_code`
+    #[pg_test]
+    fn test_address_postal_code() {
+        assert_eq!(Some("10117".to_string()), address_postal_code(tallinn()));
+    }
+
+    /// Tests `address_country`
+    #[pg_test]
+    fn test_address_country() {
+        assert_eq!(Some("Estonia".to_string()), address_country(tallinn()));
+    }
+
+    /// Tests `address_gps`
+    #[pg_test]
+    fn test_address_gps() {
+        assert_eq!(Some(vec![59.437, 24.745]), address_gps(tallinn()));
+        assert_eq!(None, address_gps(address::default()));
+    }
+
+    /// Tests `address_type`
+    #[pg_test]
+    fn test_address_type() {
+        assert_eq!(Some("work".to_string()), address_type(tallinn()));
+    }
+
+    /// Tests `address_region`
+    #[pg_test]
+    fn test_address_region() {
+        assert_eq!(Some("Harju maakond".to_string()), address_region(tallinn()));
+    }
+
+    /// Tests `address_house_number`
+    #[pg_test]
+    fn test_address_house_number() {
+        assert_eq!(Some("7".to_string()), address_house_number(tallinn()));
+    }
+
+    /// Tests `address_apartment`
+    #[pg_test]
+    fn test_address_apartment() {
+        assert_eq!(None, address_apartment(tallinn()));
+    }
+
+    /// Tests `address_building_name`
+    #[pg_test]
+    fn test_address_building_name() {
+        assert_eq!(Some("Foorum".to_string()), address_building_name(tallinn()));
+    }
+}
@@ -0,0 +1,413 @@
+//! Printable address labels following the postal conventions of the country of the address.
+//!
+//! ```sql
+//! select bfn.format_address(adr) from site;
+//! select bfn.format_address(adr, 'singleline') from site;
+//! ```
+use crate::address::address;
+use crate::countries::find_country;
+use crate::parse_strict::ParseError;
+#[allow(unused_imports)]
+use pgrx::prelude::{default, pg_extern, pg_schema, pg_test, PgSqlErrorCode};
+
+/// Address layout of a country.
+///
+/// Layouts are lines separated by `%n` of the fields `%B` (building name), `%S` (street line),
+/// `%Z` (postal code), `%C` (city) and `%R` (region). The street line is built from `%s`
+/// (street), `%h` (house number) and `%a` (apartment). Text before a missing field is left out.
+pub struct CountryFormat {
+    /// ISO 3166-1 alpha-2 code.
+    pub code: &'static str,
+    pub layout: &'static str,
+    pub street: &'static str,
+    /// Fields printed in upper case.
+    pub upper: &'static str,
+}
+
+/// Layout of countries not in `COUNTRY_FORMATS`.
+const DEFAULT_FORMAT: CountryFormat = CountryFormat {
+    code: "",
+    layout: "%B%n%S%n%Z %C%n%R",
+    street: "%s %h, %a",
+    upper: "",
+};
+
+/// Layouts of the EU and EEA countries, Switzerland, the United Kingdom and the United States.
+const COUNTRY_FORMATS: &[CountryFormat] = &[
+    CountryFormat {
+        code: "AT",
+        layout: "%B%n%S%n%Z %C",
+        street: "%s %h/%a",
+        upper: "",
+    },
+    CountryFormat {
+        code: "BE",
+        layout: "%B%n%S%n%Z %C",
+        street: "%s %h, %a",
+        upper: "",
+    },
+    CountryFormat {
+        code: "BG",
+        layout: "%B%n%S%n%Z %C",
+        street: "%s %h, %a",
+        upper: "",
+    },
+    CountryFormat {
+        code: "CH",
+        layout: "%B%n%S%n%Z %C",
+        street: "%s %h, %a",
+        upper: "",
+    },
+    CountryFormat {
+        code: "CY",
+        layout: "%B%n%S%n%Z %C",
+        street: "%s %h, %a",
+        upper: "",
+    },
+    CountryFormat {
+        code: "CZ",
+        layout: "%B%n%S%n%Z %C",
+        street: "%s %h, %a",
+        upper: "",
+    },
+    CountryFormat {
+        code: "DE",
+        layout: "%B%n%S%n%Z %C",
+        street: "%s %h, %a",
+        upper: "",
+    },
+    CountryFormat {
+        code: "DK",
+        layout: "%B%n%S%n%Z %C",
+        street: "%s %h, %a",
+        upper: "",
+    },
+    CountryFormat {
+        code: "EE",
+        layout: "%B%n%S%n%Z %C%n%R",
+        street: "%s %h-%a",
+        upper: "",
+    },
+    CountryFormat {
+        code: "ES",
+        layout: "%B%n%S%n%Z %C %R",
+        street: "%s %h, %a",
+        upper: "CR",
+    },
+    CountryFormat {
+        code: "FI",
+        layout: "%B%n%S%n%Z %C",
+        street: "%s %h %a",
+        upper: "",
+    },
+    CountryFormat {
+        code: "FR",
+        layout: "%B%n%S%n%Z %C",
+        street: "%h %s, %a",
+        upper: "C",
+    },
+    CountryFormat {
+        code: "GB",
+        layout: "%B%n%S%n%C%n%Z",
+        street: "%a, %h %s",
+        upper: "CZ",
+    },
+    CountryFormat {
+        code: "GR",
+        layout: "%B%n%S%n%Z %C",
+        street: "%s %h, %a",
+        upper: "",
+    },
+    CountryFormat {
+        code: "HR",
+        layout: "%B%n%S%n%Z %C",
+        street: "%s %h, %a",
+        upper: "",
+    },
+    CountryFormat {
+        code: "HU",
+        layout: "%B%n%C%n%S%n%Z",
+        street: "%s %h, %a",
+        upper: "",
+    },
+    CountryFormat {
+        code: "IE",
+        layout: "%B%n%S%n%C%n%R%n%Z",
+        street: "%a, %h %s",
+        upper: "",
+    },
+    CountryFormat {
+        code: "IS",
+        layout: "%B%n%S%n%Z %C",
+        street: "%s %h, %a",
+        upper: "",
+    },
+    CountryFormat {
+        code: "IT",
+        layout: "%B%n%S%n%Z %C %R",
+        street: "%s %h, %a",
+        upper: "CR",
+    },
+    CountryFormat {
+        code: "LI",
+        layout: "%B%n%S%n%Z %C",
+        street: "%s %h, %a",
+        upper: "",
+    },
+    CountryFormat {
+        code: "LT",
+        layout: "%B%n%S%n%Z %C",
+        street: "%s %h-%a",
+        upper: "",
+    },
+    CountryFormat {
+        code: "LU",
+        layout: "%B%n%S%n%Z %C",
+        street: "%h, %s, %a",
+        upper: "",
+    },
+    CountryFormat {
+        code: "LV",
+        layout: "%B%n%S%n%R%n%C, %Z",
+        street: "%s %h-%a",
+        upper: "",
+    },
+    CountryFormat {
+        code: "MT",
+        layout: "%B%n%S%n%C %Z",
+        street: "%h, %s, %a",
+        upper: "CZ",
+    },
+    CountryFormat {
+        code: "NL",
+        layout: "%B%n%S%n%Z %C",
+        street: "%s %h-%a",
+        upper: "",
+    },
+    CountryFormat {
+        code: "NO",
+        layout: "%B%n%S%n%Z %C",
+        street: "%s %h, %a",
+        upper: "",
+    },
+    CountryFormat {
+        code: "PL",
+        layout: "%B%n%S%n%Z %C",
+        street: "%s %h/%a",
+        upper: "",
+    },
+    CountryFormat {
+        code: "PT",
+        layout: "%B%n%S%n%Z %C",
+        street: "%s %h, %a",
+        upper: "",
+    },
+    CountryFormat {
+        code: "RO",
+        layout: "%B%n%S%n%Z %C%n%R",
+        street: "%s %h, %a",
+        upper: "",
+    },
+    CountryFormat {
+        code: "SE",
+        layout: "%B%n%S%n%Z %C",
+        street: "%s %h, %a",
+        upper: "C",
+    },
+    CountryFormat {
+        code: "SI",
+        layout: "%B%n%S%n%Z %C",
+        street: "%s %h, %a",
+        upper: "",
+    },
+    CountryFormat {
+        code: "SK",
+        layout: "%B%n%S%n%Z %C",
+        street: "%s %h, %a",
+        upper: "",
+    },
+    CountryFormat {
+        code: "US",
+        layout: "%B%n%S%n%C, %R %Z",
+        street: "%h %s, %a",
+        upper: "CR",
+    },
+];
+
+/// Returns the layout of a country by its alpha-2 code, or the default layout.
+pub fn country_format(code: &str) -> &'static CountryFormat {
+    COUNTRY_FORMATS
+        .iter()
+        .find(|f| f.code == code)
+        .unwrap_or(&DEFAULT_FORMAT)
+}
+
+/// Renders one line of a layout, leaving out missing fields and the text before them.
+fn render_line(template: &str, value: &dyn Fn(char) -> Option<String>) -> String {
+    let mut line = String::new();
+    let mut literal = String::new();
+    let mut chars = template.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            literal.push(c);
+            continue;
+        }
+        if let Some(v) = chars.next().and_then(value).filter(|v| !v.is_empty()) {
+            if !line.is_empty() {
+                line.push_str(&literal);
+            }
+            line.push_str(&v);
+        }
+        literal.clear();
+    }
+    line
+}
+
+/// Returns the lines of an address label, the country line in upper case.
+pub fn address_lines(adr: &address) -> Vec<String> {
+    let country = adr.country.as_deref().and_then(find_country);
+    let format = country_format(country.map_or("", |c| c.alpha2));
+    let street = render_line(format.street, &|field| match field {
+        's' => adr.street.clone(),
+        'h' => adr.house_number.clone(),
+        'a' => adr.apartment.clone(),
+        _ => None,
+    });
+    let field = |field: char| {
+        let value = match field {
+            'B' => adr.building_name.clone(),
+            'S' => Some(street.clone()),
+            'Z' => adr.postal_code.clone(),
+            'C' => adr.city.clone(),
+            'R' => adr.region.clone(),
+            _ => None,
+        };
+        if format.upper.contains(field) {
+            value.map(|v| v.to_uppercase())
+        } else {
+            value
+        }
+    };
+    let mut lines: Vec<String> = format
+        .layout
+        .split("%n")
+        .map(|line| render_line(line, &field))
+        .filter(|line| !line.is_empty())
+        .collect();
+    if let Some(name) = country.map(|c| c.name).or(adr.country.as_deref()) {
+        lines.push(name.to_uppercase());
+    }
+    lines
+}
+
+/// Formats an address as a printable label following the postal conventions of its country.
+///
+/// The country is recognised as by `normalize_country` and printed by its English name. Layouts
+/// are known for the EU and EEA countries, Switzerland, the United Kingdom and the United States;
+/// other addresses are printed with the postal code before the city.
+///
+/// # Parameters
+/// - `addr`: An address as returned by `to_address`, optionally with the keys `region`,
+///   `houseNumber`, `apartment` and `buildingName`.
+/// - `style`: `multiline` (default) for one field group per line with the country name in upper
+///   case on the last line, or `singleline` for the same lines separated by commas.
+///
+/// # Returns
+/// - The label, e.g. `Narva mnt 5-12\n10117 Tallinn\nESTONIA`.
+/// - `null` if the address has no printable fields.
+///
+/// # Errors
+/// - `22P02` (`invalid_text_representation`) if `addr` is not an address.
+/// - `22023` (`invalid_parameter_value`) if `style` is unknown.
+#[pg_extern(immutable, parallel_safe, create_or_replace)]
+pub fn format_address(addr: pgrx::JsonB, style: default!(&str, "'multiline'")) -> Option<String> {
+    let separator = match style.trim().to_lowercase().as_str() {
+        "multiline" => "\n",
+        "singleline" => ", ",
+        _ => ParseError {
+            errcode: PgSqlErrorCode::ERRCODE_INVALID_PARAMETER_VALUE,
+            message: format!("unknown address style: \"{}\"", style),
+            detail: "Valid styles are multiline and singleline.".to_string(),
+        }
+        .report(),
+    };
+    let adr = address::from_json(&addr.0).unwrap_or_else(|e| e.report());
+    let lines = address_lines(&adr);
+    (!lines.is_empty()).then(|| lines.join(separator))
+}
+
+#[cfg(any(test, feature = "pg_test"))]
+#[pg_schema]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn label(value: serde_json::Value, style: &str) -> Option<String> {
+        format_address(pgrx::JsonB(value), style)
+    }
+
+    /// Tests `format_address`
+    #[pg_test]
+    fn test_format_address() {
+        let adr = json!({"address": "Narva mnt 5-12", "city": "Tallinn", "postalCode": "10117",
+            "country": "EE", "gps": null, "type": null});
+        assert_eq!(
+            Some("Narva mnt 5-12\n10117 Tallinn\nESTONIA".to_string()),
+            label(adr.clone(), "multiline")
+        );
+        assert_eq!(
+            Some("Narva mnt 5-12, 10117 Tallinn, ESTONIA".to_string()),
+            label(adr, "singleline")
+        );
+        let adr = json!({"address": "Narva mnt", "houseNumber": "5", "apartment": "12",
+            "city": "Tallinn", "postalCode": "10117", "country": "Eesti"});
+        assert_eq!(
+            Some("Narva mnt 5-12\n10117 Tallinn\nESTONIA".to_string()),
+            label(adr, "multiline")
+        );
+        let adr = json!({"address": "rue de Rivoli", "houseNumber": "99", "city": "Paris",
+            "postalCode": "75001", "country": "France"});
+        assert_eq!(
+            Some("99 rue de Rivoli\n75001 PARIS\nFRANCE".to_string()),
+            label(adr, "multiline")
+        );
+        let adr = json!({"address": "Downing Street", "houseNumber": "10", "apartment": "Flat 1",
+            "city": "London", "postalCode": "SW1A 2AA", "country": "gbr"});
+        assert_eq!(
+            Some("Flat 1, 10 Downing Street\nLONDON\nSW1A 2AA\nUNITED KINGDOM".to_string()),
+            label(adr, "multiline")
+        );
+        let adr = json!({"address": "Fő utca 1.", "city": "Budapest", "postalCode": "1011",
+            "country": "HU"});
+        assert_eq!(
+            Some("Budapest, Fő utca 1., 1011, HUNGARY".to_string()),
+            label(adr, "singleline")
+        );
+        let adr = json!({"address": "Brīvības iela 1", "city": "Rīga", "postalCode": "LV-1050",
+            "country": "Latvija", "buildingName": "Laimas pulkstenis"});
+        assert_eq!(
+            Some("Laimas pulkstenis\nBrīvības iela 1\nRīga, LV-1050\nLATVIA".to_string()),
+            label(adr, "multiline")
+        );
+    }
+
+    /// Tests `format_address` with unknown countries and missing fields
+    #[pg_test]
+    fn test_format_address_partial() {
+        let adr = json!({"address": "Main Road 1", "city": "Nairobi", "postalCode": "00100",
+            "country": "Kenya", "region": "Nairobi County"});
+        assert_eq!(
+            Some("Main Road 1\n00100 Nairobi\nNairobi County\nKENYA".to_string()),
+            label(adr, "multiline")
+        );
+        let adr = json!({"address": null, "city": "Tallinn", "postalCode": null, "country": null});
+        assert_eq!(Some("Tallinn".to_string()), label(adr, "MultiLine"));
+        assert_eq!(None, label(json!({"address": null}), "singleline"));
+    }
+
+    /// Tests `format_address` with an unknown style
+    #[pg_test(error = "unknown address style: \"envelope\"")]
+    fn test_format_address_error() {
+        label(json!({"city": "Tallinn"}), "envelope");
+    }
+}
@@ -0,0 +1,347 @@
+//! Similarity and fingerprints of addresses for finding duplicates.
+//!
+//! Addresses are compared after normalizing case, whitespace, diacritics and punctuation, street
+//! type abbreviations (e.g. `maantee` and `mnt`, `Street` and `St`), country names (as by
+//! `normalize_country`) and postal codes (as by `normalize_postal_code`):
+//!
+//! ```sql
+//! select bfn.address_fingerprint(adr) as fp, count(*) from customer
+//!  group by fp having count(*) > 1;
+//! create unique index on site (bfn.address_fingerprint(adr));
+//! select * from customer a join customer b on a.id < b.id
+//!  where bfn.address_similarity(a.adr, b.adr) > 0.9;
+//! ```
+use crate::address::address;
+use crate::address_format::country_format;
+use crate::address_parse::split_street;
+use crate::countries::find_country;
+use crate::postal_codes::{canonical_postal_code, postal_country};
+#[allow(unused_imports)]
+use pgrx::prelude::{pg_extern, pg_schema, pg_test};
+use std::collections::HashMap;
+
+/// Street type abbreviations and the words they stand for, after folding diacritics.
+const STREET_ABBREVIATIONS: &[(&str, &[&str])] = &[
+    ("st", &["street"]),
+    ("str", &["strasse"]),
+    ("ave", &["avenue", "av", "avenida"]),
+    ("rd", &["road"]),
+    ("blvd", &["boulevard", "bd", "bvd"]),
+    ("dr", &["drive"]),
+    ("ln", &["lane"]),
+    ("pl", &["place", "platz", "plac"]),
+    ("sq", &["square"]),
+    ("ct", &["court"]),
+    ("hwy", &["highway"]),
+    ("mnt", &["maantee"]),
+    ("tn", &["tanav"]),
+    ("pst", &["puiestee"]),
+    ("pk", &["poik"]),
+    ("ul", &["ulica", "ulitsa"]),
+    ("al", &["aleja", "allee"]),
+    ("pr", &["prospekt", "prospektas"]),
+    ("g", &["gatve"]),
+    ("gt", &["gata", "gatan"]),
+];
+
+/// Folds a lower-case letter with a diacritic to its base letters.
+fn fold_char(c: char) -> Option<&'static str> {
+    Some(match c {
+        'à' | 'á' | 'â' | 'ã' | 'ä' | 'å' | 'ā' | 'ă' | 'ą' => "a",
+        'ç' | 'ć' | 'č' => "c",
+        'ď' | 'đ' | 'ð' => "d",
+        'è' | 'é' | 'ê' | 'ë' | 'ē' | 'ė' | 'ę' | 'ě' => "e",
+        'ğ' | 'ģ' => "g",
+        'ì' | 'í' | 'î' | 'ï' | 'ī' | 'į' | 'ı' => "i",
+        'ķ' => "k",
+        'ĺ' | 'ļ' | 'ľ' | 'ł' => "l",
+        'ñ' | 'ń' | 'ņ' | 'ň' => "n",
+        'ò' | 'ó' | 'ô' | 'õ' | 'ö' | 'ø' | 'ō' | 'ő' => "o",
+        'ŕ' | 'ř' => "r",
+        'ś' | 'š' | 'ş' | 'ș' => "s",
+        'ť' | 'ţ' | 'ț' => "t",
+        'ù' | 'ú' | 'û' | 'ü' | 'ū' | 'ů' | 'ű' | 'ų' => "u",
+        'ý' | 'ÿ' => "y",
+        'ź' | 'ż' | 'ž' => "z",
+        'ß' => "ss",
+        'æ' => "ae",
+        'œ' => "oe",
+        'þ' => "th",
+        _ => return None,
+    })
+}
+
+/// Normalizes text for comparison: lower case without diacritics, punctuation replaced by
+/// spaces, and whitespace collapsed as by `san_trim`.
+pub fn fold(value: &str) -> String {
+    let mut folded = String::with_capacity(value.len());
+    for c in value.to_lowercase().chars() {
+        match fold_char(c) {
+            Some(s) => folded.push_str(s),
+            None if c.is_alphanumeric() => folded.push(c),
+            None => folded.push(' '),
+        }
+    }
+    folded.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Returns the abbreviation of a street type word, e.g. `mnt` for `maantee`.
+fn abbreviate(word: &str) -> String {
+    for (abbreviation, words) in STREET_ABBREVIATIONS {
+        if words.contains(&word) {
+            return abbreviation.to_string();
+        }
+    }
+    // German compounds, e.g. `hauptstrasse` to `hauptstr`
+    match word.strip_suffix("strasse") {
+        Some(stem) if !stem.is_empty() => format!("{}str", stem),
+        _ => word.to_string(),
+    }
+}
+
+/// Normalized fields of an address.
+struct AddressKey {
+    country: Option<String>,
+    postal_code: Option<String>,
+    city: Option<String>,
+    /// Words of the street name, sorted.
+    street: Option<String>,
+    house_number: Option<String>,
+    apartment: Option<String>,
+}
+
+impl AddressKey {
+    fn new(adr: &address) -> AddressKey {
+        let known = adr.country.as_deref().and_then(find_country);
+        let country = adr.country.as_deref().map(|c| match known {
+            Some(c) => c.alpha2.to_lowercase(),
+            None => fold(c),
+        });
+        let postal_code = adr.postal_code.as_deref().map(|code| {
+            let canonical = adr
+                .country
+                .as_deref()
+                .and_then(postal_country)
+                .and_then(|c| canonical_postal_code(code, c));
+            fold(canonical.as_deref().unwrap_or(code)).replace(' ', "")
+        });
+        // House number and apartment within the street, e.g. `Narva mnt 5-12`
+        let (street, house_number, apartment) = match (&adr.street, &adr.house_number) {
+            (Some(street), None) => {
+                let template = country_format(known.map_or("", |c| c.alpha2)).street;
+                split_street(street, template)
+            }
+            (street, house_number) => (
+                street.clone().unwrap_or_default(),
+                house_number.clone(),
+                None,
+            ),
+        };
+        let mut words: Vec<String> = fold(&street)
+            .split(' ')
+            .map(abbreviate)
+            .filter(|w| !w.is_empty())
+            .collect();
+        words.sort();
+        let number = |v: Option<&str>| {
+            v.map(|v| fold(v).replace(' ', ""))
+                .filter(|v| !v.is_empty())
+        };
+        AddressKey {
+            country: country.filter(|v| !v.is_empty()),
+            postal_code: postal_code.filter(|v| !v.is_empty()),
+            city: adr.city.as_deref().map(fold).filter(|v| !v.is_empty()),
+            street: Some(words.join(" ")).filter(|v| !v.is_empty()),
+            house_number: number(house_number.as_deref()),
+            apartment: number(adr.apartment.as_deref().or(apartment.as_deref())),
+        }
+    }
+}
+
+/// Returns the Sørensen–Dice coefficient of the character bigrams of two strings.
+fn dice(a: &str, b: &str) -> f64 {
+    if a == b {
+        return 1.0;
+    }
+    let bigrams = |s: &str| {
+        let chars: Vec<char> = s.chars().collect();
+        let mut counts: HashMap<(char, char), i32> = HashMap::new();
+        for pair in chars.windows(2) {
+            *counts.entry((pair[0], pair[1])).or_default() += 1;
+        }
+        counts
+    };
+    let (x, y) = (bigrams(a), bigrams(b));
+    let total: i32 = x.values().sum::<i32>() + y.values().sum::<i32>();
+    if total == 0 {
+        return 0.0;
+    }
+    let common: i32 = x
+        .iter()
+        .map(|(k, n)| (*n).min(*y.get(k).unwrap_or(&0)))
+        .sum();
+    2.0 * common as f64 / total as f64
+}
+
+/// Returns the similarity of two addresses between 0 and 1.
+pub fn similarity(a: &address, b: &address) -> f64 {
+    let (a, b) = (AddressKey::new(a), AddressKey::new(b));
+    // Weight, values and whether they are compared by bigrams (or must be equal)
+    let fields = [
+        (0.3, &a.street, &b.street, true),
+        (0.1, &a.house_number, &b.house_number, false),
+        (0.05, &a.apartment, &b.apartment, false),
+        (0.2, &a.city, &b.city, true),
+        (0.2, &a.postal_code, &b.postal_code, false),
+        (0.15, &a.country, &b.country, false),
+    ];
+    let (mut score, mut weight) = (0.0, 0.0);
+    for (w, x, y, fuzzy) in fields {
+        if let (Some(x), Some(y)) = (x, y) {
+            score += w * if fuzzy {
+                dice(x, y)
+            } else {
+                f64::from(u8::from(x == y))
+            };
+            weight += w;
+        }
+    }
+    if weight == 0.0 {
+        return 0.0;
+    }
+    (score / weight * 1000.0).round() / 1000.0
+}
+
+/// Returns the similarity of two addresses.
+///
+/// Fields are compared after normalizing case, whitespace, diacritics, punctuation, street type
+/// abbreviations, country names and postal codes. A house number and apartment in the street are
+/// split off as by `parse_address`, so `Narva mnt 5-12` and `Narva maantee` with `houseNumber`
+/// `5` and `apartment` `12` are equal, but `Narva mnt 12-5` is not. Street and city are compared
+/// by character bigrams, which tolerates typos; house number, apartment, postal code and country
+/// must be equal.
+///
+/// # Parameters
+/// - `a`, `b`: Addresses as returned by `to_address`, optionally with the keys `region`,
+///   `houseNumber`, `apartment` and `buildingName`.
+///
+/// # Returns
+/// A score between 0 and 1, weighting street (0.3), house number (0.1), apartment (0.05), city
+/// (0.2), postal code (0.2) and country (0.15) for the fields present in both addresses. `0` if no field can be compared.
+///
+/// # Errors
+/// - `22P02` (`invalid_text_representation`) if `a` or `b` is not an address.
+#[pg_extern(immutable, parallel_safe, create_or_replace)]
+pub fn address_similarity(a: pgrx::JsonB, b: pgrx::JsonB) -> f64 {
+    let a = address::from_json(&a.0).unwrap_or_else(|e| e.report());
+    let b = address::from_json(&b.0).unwrap_or_else(|e| e.report());
+    similarity(&a, &b)
+}
+
+/// Returns a fingerprint of an address, equal for addresses that differ only in case,
+/// whitespace, diacritics, punctuation, street type abbreviations, order of the street words,
+/// country names and postal code formatting.
+///
+/// Suitable for a unique index or `GROUP BY` to find duplicates.
+///
+/// # Parameters
+/// - `addr`: An address as returned by `to_address`, optionally with the keys `region`,
+///   `houseNumber`, `apartment` and `buildingName`.
+///
+/// # Returns
+/// - The normalized country, postal code, city, street words, house number and apartment
+///   separated by `|`, e.g. `ee|10117|tallinn|mnt narva|5|12`.
+/// - `null` if the address has none of these fields.
+///
+/// # Errors
+/// - `22P02` (`invalid_text_representation`) if `addr` is not an address.
+#[pg_extern(immutable, parallel_safe, create_or_replace)]
+pub fn address_fingerprint(addr: pgrx::JsonB) -> Option<String> {
+    let adr = address::from_json(&addr.0).unwrap_or_else(|e| e.report());
+    let key = AddressKey::new(&adr);
+    let fields = [
+        key.country,
+        key.postal_code,
+        key.city,
+        key.street,
+        key.house_number,
+        key.apartment,
+    ];
+    if fields.iter().all(Option::is_none) {
+        return None;
+    }
+    Some(fields.map(Option::unwrap_or_default).join("|"))
+}
+
+#[cfg(any(test, feature = "pg_test"))]
+#[pg_schema]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    fn jsonb(value: Value) -> pgrx::JsonB {
+        pgrx::JsonB(value)
+    }
+
+    /// Tests `address_similarity`
+    #[pg_test]
+    fn test_address_similarity() {
+        let a = json!({"address": "Narva mnt 5-12", "city": "Tallinn", "postalCode": "10117",
+            "country": "EE"});
+        let b = json!({"address": "NARVA  MAANTEE", "houseNumber": "5", "apartment": "12",
+            "city": "tallinn", "postalCode": "10 117", "country": "Eesti"});
+        assert_eq!(1.0, address_similarity(jsonb(a.clone()), jsonb(b)));
+        let typo = json!({"address": "Narva mnt 5-21", "city": "Talinn", "postalCode": "10117",
+            "country": "Estonia"});
+        let score = address_similarity(jsonb(a.clone()), jsonb(typo));
+        assert!(score > 0.8 && score < 1.0, "{}", score);
+        let swapped = json!({"address": "Narva mnt 12-5", "city": "Tallinn",
+            "postalCode": "10117", "country": "EE"});
+        let score = address_similarity(jsonb(a.clone()), jsonb(swapped));
+        assert!(score < 1.0, "{}", score);
+        let other = json!({"address": "Hauptstraße 5", "city": "Berlin", "postalCode": "10115",
+            "country": "DE"});
+        let score = address_similarity(jsonb(a.clone()), jsonb(other));
+        assert!(score < 0.3, "{}", score);
+        assert_eq!(0.0, address_similarity(jsonb(a), jsonb(json!({}))));
+        let de = json!({"address": "Hauptstrasse 5", "city": "Berlin"});
+        let de_abbreviated = json!({"address": "Hauptstr. 5", "city": "BERLIN"});
+        assert_eq!(1.0, address_similarity(jsonb(de), jsonb(de_abbreviated)));
+    }
+
+    /// Tests `address_fingerprint`
+    #[pg_test]
+    fn test_address_fingerprint() {
+        let a = json!({"address": "Narva mnt 5-12", "city": "Tallinn", "postalCode": "10117",
+            "country": "EE"});
+        let b = json!({"address": "Narva maantee", "houseNumber": "5", "apartment": "12",
+            "city": " TALLINN ", "postalCode": "EE-10117", "country": "Estonia"});
+        assert_eq!(
+            Some("ee|10117|tallinn|mnt narva|5|12".to_string()),
+            address_fingerprint(jsonb(a.clone()))
+        );
+        assert_eq!(
+            address_fingerprint(jsonb(a.clone())),
+            address_fingerprint(jsonb(b))
+        );
+        let swapped = json!({"address": "Narva mnt 12-5", "city": "Tallinn",
+            "postalCode": "10117", "country": "EE"});
+        assert_ne!(
+            address_fingerprint(jsonb(a)),
+            address_fingerprint(jsonb(swapped))
+        );
+        let gb = json!({"address": "10 Downing Street", "city": "London", "postalCode": "sw1a2aa",
+            "country": "United Kingdom"});
+        let gb_other = json!({"address": "Downing St. 10", "city": "London",
+            "postalCode": "SW1A 2AA", "country": "GB"});
+        assert_eq!(
+            address_fingerprint(jsonb(gb)),
+            address_fingerprint(jsonb(gb_other))
+        );
+        assert_eq!(
+            Some("||torva|||".to_string()),
+            address_fingerprint(jsonb(json!({"city": "Tõrva"})))
+        );
+        assert_eq!(None, address_fingerprint(jsonb(json!({"address": " "}))));
+    }
+}
@@ -0,0 +1,397 @@
+//! Rule-based parser of one-line addresses, e.g. from partner CSV files.
+//!
+//! Parts separated by commas or semicolons are recognised as country, postal code and city,
+//! apartment and street with house number, using the country, postal code and street layout
+//! rules of `normalize_country`, `normalize_postal_code` and `format_address`:
+//!
+//! ```sql
+//! select * from bfn.parse_address('Narva mnt 5-12, 10117 Tallinn, Estonia');
+//! select p.address, p.confidence from import, bfn.parse_address(import.adr, 'EE') p;
+//! ```
+use crate::address::address;
+use crate::address_format::country_format;
+use crate::countries::find_country;
+use crate::postal_codes::{canonical_postal_code, postal_country};
+#[allow(unused_imports)]
+use pgrx::prelude::{default, name, pg_extern, pg_schema, pg_test, TableIterator};
+use regex::Regex;
+use std::sync::OnceLock;
+
+/// Regular expressions used by the parser.
+struct Patterns {
+    /// Postal code of an unknown country, e.g. `10117` or `LV-1050`.
+    postal: Regex,
+    /// Postal code of two tokens of an unknown country, e.g. `114 21`.
+    postal_pair: Regex,
+    /// Apartment, e.g. `Apt 4` or `korter 12`.
+    apartment: Regex,
+    /// Street followed by house number, e.g. `Narva mnt 5-12`.
+    house_last: Regex,
+    /// House number followed by street, e.g. `10 Downing Street`.
+    house_first: Regex,
+}
+
+fn patterns() -> &'static Patterns {
+    static PATTERNS: OnceLock<Patterns> = OnceLock::new();
+    PATTERNS.get_or_init(|| {
+        let apartment = concat!(
+            r"(?i)(?:^|\s)",
+            r"(?:apt|apartment|flat|unit|suite|korter|kv|wohnung|whg|lgh|app|appartement)",
+            r"(?:\.\s*|\s+)(\d[0-9a-z]*(?:-[0-9a-z]+)?|[a-z]\d*)$"
+        );
+        let house = r"\d+ ?[a-z]?(?:[-/]\d+[a-z]?)?";
+        Patterns {
+            postal: Regex::new(r"(?i)^(?:[a-z]{1,2}-)?\d{4,6}$").unwrap(),
+            postal_pair: Regex::new(r"^\d{3} \d{2}$").unwrap(),
+            apartment: Regex::new(apartment).unwrap(),
+            house_last: Regex::new(&format!(r"(?i)^(.*[^\d\s,])\s*,?\s+({})$", house)).unwrap(),
+            house_first: Regex::new(&format!(r"(?i)^({}),?\s+(\D.*)$", house)).unwrap(),
+        }
+    })
+}
+
+/// Parsed address and confidence between 0 and 1.
+pub struct ParsedAddress {
+    pub address: address,
+    pub confidence: f64,
+}
+
+/// Finds a postal code at the start or the end of a part.
+///
+/// Returns the postal code and the rest of the part before and after it.
+fn find_postal_code(part: &str, country: Option<&str>) -> Option<(String, String, String)> {
+    let tokens: Vec<&str> = part.split_whitespace().collect();
+    let n = tokens.len();
+    let valid = |candidate: &str| match country {
+        Some(c) => canonical_postal_code(candidate, c),
+        None => {
+            let p = patterns();
+            (p.postal.is_match(candidate) || p.postal_pair.is_match(candidate))
+                .then(|| candidate.to_uppercase())
+        }
+    };
+    let windows = [
+        (n.saturating_sub(2), n),
+        (n.saturating_sub(1), n),
+        (0, 2.min(n)),
+        (0, 1.min(n)),
+    ];
+    windows
+        .iter()
+        .filter(|(start, end)| start < end)
+        .find_map(|&(start, end)| {
+            valid(&tokens[start..end].join(" "))
+                .map(|code| (code, tokens[..start].join(" "), tokens[end..].join(" ")))
+        })
+}
+
+/// Splits a street part into street, house number and apartment by the layout of the country.
+pub(crate) fn split_street(part: &str, template: &str) -> (String, Option<String>, Option<String>) {
+    let p = patterns();
+    let mut street = part.to_string();
+    let mut apartment = None;
+    if let Some(caps) = p.apartment.captures(part) {
+        apartment = Some(caps[1].to_string());
+        street = part[..caps.get(0).unwrap().start()]
+            .trim_end_matches([' ', ','])
+            .to_string();
+    }
+    let last = |s: &str| {
+        p.house_last
+            .captures(s)
+            .map(|c| (c[1].to_string(), c[2].to_string()))
+    };
+    let first = |s: &str| {
+        p.house_first
+            .captures(s)
+            .map(|c| (c[2].to_string(), c[1].to_string()))
+    };
+    let house_first = template.find("%h") < template.find("%s");
+    let split = if house_first {
+        first(&street).or_else(|| last(&street))
+    } else {
+        last(&street).or_else(|| first(&street))
+    };
+    let Some((name, mut house)) = split else {
+        return (street, None, apartment);
+    };
+    // The separator of house number and apartment in the layout, e.g. `5-12` or `10/12`
+    let separator = template
+        .split_once("%h")
+        .and_then(|(_, rest)| rest.split_once("%a"))
+        .map(|(separator, _)| separator)
+        .filter(|separator| *separator == "-" || *separator == "/");
+    if let Some((h, a)) = separator.and_then(|s| house.split_once(s)) {
+        if apartment.is_none() {
+            apartment = Some(a.to_string());
+            house = h.to_string();
+        }
+    }
+    (name, Some(house.replace(' ', "")), apartment)
+}
+
+/// Parses a one-line address.
+pub fn parse(value: &str, country_hint: Option<&str>) -> ParsedAddress {
+    let p = patterns();
+    let mut parts: Vec<String> = value
+        .split([',', ';', '\n'])
+        .map(|part| part.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|part| !part.is_empty())
+        .collect();
+    let mut adr = address::default();
+    let mut confidence = 0.0;
+
+    // Country: the last part, or its last words
+    if let Some(last) = parts.last().cloned() {
+        if find_country(&last).is_some() {
+            adr.country = parts.pop();
+        } else {
+            let words: Vec<&str> = last.split(' ').collect();
+            for i in words.len().saturating_sub(4).max(1)..words.len() {
+                let suffix = words[i..].join(" ");
+                if suffix.chars().count() > 3 && find_country(&suffix).is_some() {
+                    adr.country = Some(suffix);
+                    *parts.last_mut().unwrap() = words[..i].join(" ");
+                    break;
+                }
+            }
+        }
+    }
+    if adr.country.is_some() {
+        confidence += 0.25;
+    } else if let Some(hint) = country_hint.filter(|h| !h.trim().is_empty()) {
+        adr.country = Some(hint.trim().to_string());
+        confidence += 0.1;
+    }
+    let country = adr.country.as_deref().and_then(find_country);
+    let postal = adr.country.as_deref().and_then(postal_country);
+    let format = country_format(country.map_or("", |c| c.alpha2));
+
+    // Without a postal code at the start or end of a part, a part is split before a postal code
+    // inside it, e.g. `Narva mnt 7 10117 Tallinn`
+    let edge = |i: usize, part: &str| {
+        (i > 0 || parts.len() == 1) && find_postal_code(part, postal).is_some()
+    };
+    if !parts.iter().enumerate().any(|(i, part)| edge(i, part)) {
+        for i in (0..parts.len()).rev() {
+            let tokens: Vec<&str> = parts[i].split(' ').collect();
+            let split = (1..tokens.len()).find(|&t| {
+                (1..=2).any(|len| {
+                    tokens.get(t..t + len).is_some_and(|window| {
+                        find_postal_code(&window.join(" "), postal)
+                            .is_some_and(|(_, before, after)| before.is_empty() && after.is_empty())
+                    })
+                })
+            });
+            if let Some(t) = split {
+                let (head, tail) = (tokens[..t].join(" "), tokens[t..].join(" "));
+                parts[i] = head;
+                parts.insert(i + 1, tail);
+                break;
+            }
+        }
+    }
+    let mut used = vec![false; parts.len()];
+
+    // Postal code with city (or region), searched from the end, the first part is the street
+    let mut postal_index = None;
+    for i in (0..parts.len())
+        .rev()
+        .filter(|&i| i > 0 || parts.len() == 1)
+    {
+        if let Some((code, before, after)) = find_postal_code(&parts[i], postal) {
+            confidence += if postal.is_some() { 0.25 } else { 0.15 };
+            adr.postal_code = Some(code);
+            let rest = [before.as_str(), after.as_str()]
+                .join(" ")
+                .trim()
+                .to_string();
+            if !rest.is_empty() {
+                if !before.is_empty() && format.layout.contains("%R %Z") {
+                    adr.region = Some(rest);
+                } else {
+                    adr.city = Some(rest);
+                }
+            }
+            used[i] = true;
+            postal_index = Some(i);
+            break;
+        }
+    }
+
+    // Apartment given as a part of its own
+    for i in 0..parts.len() {
+        if !used[i] && adr.apartment.is_none() {
+            if let Some(caps) = p
+                .apartment
+                .captures(&parts[i])
+                .filter(|c| c[0].trim() == parts[i])
+            {
+                adr.apartment = Some(caps[1].to_string());
+                used[i] = true;
+            }
+        }
+    }
+
+    // Street: the first part with a digit, or the first part
+    let street_index = (0..parts.len())
+        .filter(|&i| !used[i])
+        .find(|&i| parts[i].chars().any(|c| c.is_ascii_digit()))
+        .or_else(|| (0..parts.len()).find(|&i| !used[i]));
+    // City: next to the postal code, or the last part after the street
+    if adr.city.is_none() {
+        let free = |i: &usize| !used[*i] && Some(*i) != street_index;
+        let city = match postal_index {
+            Some(pi) => (0..pi)
+                .rev()
+                .find(free)
+                .or_else(|| (pi + 1..parts.len()).find(free)),
+            None => (0..parts.len()).rev().find(free),
+        };
+        if let Some(i) = city {
+            adr.city = Some(parts[i].clone());
+            used[i] = true;
+        }
+    }
+    if adr.city.is_some() {
+        confidence += 0.2;
+    }
+    if let Some(i) = street_index {
+        let (street, house, apartment) = split_street(&parts[i], format.street);
+        confidence += if house.is_some() { 0.3 } else { 0.15 };
+        adr.street = Some(street);
+        adr.house_number = house;
+        adr.apartment = adr.apartment.or(apartment);
+        used[i] = true;
+    }
+
+    // Other parts: building name before the street, region after it
+    for i in 0..parts.len() {
+        if used[i] {
+            continue;
+        }
+        if street_index.is_some_and(|s| i < s) && adr.building_name.is_none() {
+            adr.building_name = Some(parts[i].clone());
+        } else if street_index.is_some_and(|s| i > s) && adr.region.is_none() {
+            adr.region = Some(parts[i].clone());
+        } else {
+            confidence -= 0.1;
+        }
+    }
+    ParsedAddress {
+        address: adr,
+        confidence: (f64::max(confidence, 0.0) * 100.0).round() / 100.0,
+    }
+}
+
+/// Parses a one-line address into the fields of `to_address`.
+///
+/// Parts separated by commas or semicolons are recognised from the end: the country, the postal
+/// code with the city (in the order of the country), an apartment, and the street with the house
+/// number, e.g. `Narva mnt 5-12, 10117 Tallinn, Estonia`. Remaining parts before the street are
+/// taken as the building name, after it as the region.
+///
+/// # Parameters
+/// - `value`: The address.
+/// - `country_hint`: The country if it is not part of the address, recognised as by
+///   `normalize_country`. Defaults to `null`.
+///
+/// # Returns
+/// A row with:
+/// - `address`: An object with the keys of `to_address` (`address` for the street name, `city`,
+///   `postalCode`, `country`, `gps` and `type` being `null`), and `houseNumber`, `apartment`,
+///   `buildingName` and `region` if they are found. Postal codes valid in the country are
+///   normalized as by `normalize_postal_code`. Castable to `address`.
+/// - `confidence`: A score between 0 (nothing recognised) and 1 (street with house number,
+///   valid postal code, city and country found).
+#[pg_extern(immutable, parallel_safe, create_or_replace)]
+pub fn parse_address(
+    value: &str,
+    country_hint: default!(Option<&str>, "NULL"),
+) -> TableIterator<'static, (name!(address, pgrx::JsonB), name!(confidence, f64))> {
+    let parsed = parse(value, country_hint);
+    TableIterator::once((pgrx::JsonB(parsed.address.to_json()), parsed.confidence))
+}
+
+#[cfg(any(test, feature = "pg_test"))]
+#[pg_schema]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    fn parsed(value: &str, country_hint: Option<&str>) -> (Value, f64) {
+        let (adr, confidence) = parse_address(value, country_hint).next().unwrap();
+        (adr.0, confidence)
+    }
+
+    /// Tests `parse_address`
+    #[pg_test]
+    fn test_parse_address() {
+        let (adr, confidence) = parsed("Narva mnt 5-12, 10117 Tallinn, Estonia", None);
+        assert_eq!(
+            json!({"address": "Narva mnt", "houseNumber": "5", "apartment": "12",
+                "postalCode": "10117", "city": "Tallinn", "country": "Estonia",
+                "gps": null, "type": null}),
+            adr
+        );
+        assert_eq!(1.0, confidence);
+        let (adr, _) = parsed("Flat 1, 10 Downing Street, London SW1A 2AA, UK", None);
+        assert_eq!(json!("Downing Street"), adr["address"]);
+        assert_eq!(json!("10"), adr["houseNumber"]);
+        assert_eq!(json!("1"), adr["apartment"]);
+        assert_eq!(json!("London"), adr["city"]);
+        assert_eq!(json!("SW1A 2AA"), adr["postalCode"]);
+        let (adr, _) = parsed("ul. Marszałkowska 10/12; 00950 Warszawa", Some("PL"));
+        assert_eq!(json!("ul. Marszałkowska"), adr["address"]);
+        assert_eq!(
+            json!(["10", "12"]),
+            json!([adr["houseNumber"], adr["apartment"]])
+        );
+        assert_eq!(json!("00-950"), adr["postalCode"]);
+        assert_eq!(json!("PL"), adr["country"]);
+        let (adr, _) = parsed("Brīvības iela 1, Rīga, LV-1050, Latvija", None);
+        assert_eq!(json!("Rīga"), adr["city"]);
+        assert_eq!(json!("LV-1050"), adr["postalCode"]);
+        let (adr, _) = parsed("123 Main St Apt 4, Springfield, IL 62701, USA", None);
+        assert_eq!(json!("Main St"), adr["address"]);
+        assert_eq!(
+            json!(["123", "4"]),
+            json!([adr["houseNumber"], adr["apartment"]])
+        );
+        assert_eq!(
+            json!(["Springfield", "IL"]),
+            json!([adr["city"], adr["region"]])
+        );
+        let (adr, _) = parsed("Foorum, Narva mnt 7 10117 Tallinn Eesti", None);
+        assert_eq!(json!("Foorum"), adr["buildingName"]);
+        assert_eq!(json!("Tallinn"), adr["city"]);
+        assert_eq!(json!("Eesti"), adr["country"]);
+        let (adr, confidence) = parsed("Hauptstraße 5a 10115 Berlin", Some("DE"));
+        assert_eq!(
+            json!(["Hauptstraße", "5a"]),
+            json!([adr["address"], adr["houseNumber"]])
+        );
+        assert_eq!(
+            json!(["10115", "Berlin"]),
+            json!([adr["postalCode"], adr["city"]])
+        );
+        assert_eq!(0.85, confidence);
+    }
+
+    /// Tests `parse_address` with incomplete addresses
+    #[pg_test]
+    fn test_parse_address_partial() {
+        let (adr, confidence) = parsed("somewhere", None);
+        assert_eq!(json!("somewhere"), adr["address"]);
+        assert_eq!(0.15, confidence);
+        let (adr, confidence) = parsed("  ", None);
+        assert_eq!(json!(null), adr["address"]);
+        assert_eq!(0.0, confidence);
+        let (adr, _) = parsed("Tartu mnt 1, Tallinn", Some("Estonia"));
+        assert_eq!(
+            json!(["Tartu mnt", "1", "Tallinn"]),
+            json!([adr["address"], adr["houseNumber"], adr["city"]])
+        );
+        assert_eq!(json!(null), adr["postalCode"]);
+    }
+}
@@ -0,0 +1,533 @@
+//! ISO 3166-1 country catalogue and normalization of country names.
+//!
+//! Countries are recognised by their alpha-2, alpha-3 or numeric code, English or native name
+//! and common aliases, ignoring case:
+//!
+//! ```sql
+//! select * from bfn.iso_countries();
+//! select bfn.normalize_country('Eesti'); -- EE
+//! select bfn.normalize_country('EST', 'name'); -- Estonia
+//! ```
+use crate::parse_strict::ParseError;
+#[allow(unused_imports)]
+use pgrx::prelude::{default, name, pg_extern, pg_schema, pg_test, PgSqlErrorCode, TableIterator};
+
+/// Country of ISO 3166-1.
+pub struct Country {
+    pub alpha2: &'static str,
+    pub alpha3: &'static str,
+    /// Numeric code with leading zeros, e.g. `040`.
+    pub numeric: &'static str,
+    /// English short name.
+    pub name: &'static str,
+    /// Name in the (first) official language.
+    pub native: &'static str,
+    /// Other English and native names.
+    pub aliases: &'static [&'static str],
+}
+
+const fn country(
+    alpha2: &'static str,
+    alpha3: &'static str,
+    numeric: &'static str,
+    name: &'static str,
+    native: &'static str,
+    aliases: &'static [&'static str],
+) -> Country {
+    Country {
+        alpha2,
+        alpha3,
+        numeric,
+        name,
+        native,
+        aliases,
+    }
+}
+
+/// ISO 3166-1 countries, ordered by alpha-2 code.
+#[rustfmt::skip]
+pub const COUNTRIES: &[Country] = &[
+    country("AD", "AND", "020", "Andorra", "Andorra", &[]),
+    country("AE", "ARE", "784", "United Arab Emirates", "الإمارات", &["UAE"]),
+    country("AF", "AFG", "004", "Afghanistan", "افغانستان", &[]),
+    country("AG", "ATG", "028", "Antigua and Barbuda", "Antigua and Barbuda", &[]),
+    country("AI", "AIA", "660", "Anguilla", "Anguilla", &[]),
+    country("AL", "ALB", "008", "Albania", "Shqipëria", &["Shqipëri"]),
+    country("AM", "ARM", "051", "Armenia", "Հայաստան", &[]),
+    country("AO", "AGO", "024", "Angola", "Angola", &[]),
+    country("AQ", "ATA", "010", "Antarctica", "Antarctica", &[]),
+    country("AR", "ARG", "032", "Argentina", "Argentina", &[]),
+    country("AS", "ASM", "016", "American Samoa", "American Samoa", &[]),
+    country("AT", "AUT", "040", "Austria", "Österreich", &[]),
+    country("AU", "AUS", "036", "Australia", "Australia", &[]),
+    country("AW", "ABW", "533", "Aruba", "Aruba", &[]),
+    country("AX", "ALA", "248", "Åland Islands", "Åland", &["Aland Islands"]),
+    country("AZ", "AZE", "031", "Azerbaijan", "Azərbaycan", &[]),
+    country("BA", "BIH", "070", "Bosnia and Herzegovina", "Bosna i Hercegovina", &["Bosnia"]),
+    country("BB", "BRB", "052", "Barbados", "Barbados", &[]),
+    country("BD", "BGD", "050", "Bangladesh", "বাংলাদেশ", &[]),
+    country("BE", "BEL", "056", "Belgium", "België", &["Belgique", "Belgien"]),
+    country("BF", "BFA", "854", "Burkina Faso", "Burkina Faso", &[]),
+    country("BG", "BGR", "100", "Bulgaria", "България", &[]),
+    country("BH", "BHR", "048", "Bahrain", "البحرين", &[]),
+    country("BI", "BDI", "108", "Burundi", "Uburundi", &[]),
+    country("BJ", "BEN", "204", "Benin", "Bénin", &[]),
+    country("BL", "BLM", "652", "Saint Barthélemy", "Saint-Barthélemy", &["Saint Barthelemy"]),
+    country("BM", "BMU", "060", "Bermuda", "Bermuda", &[]),
+    country("BN", "BRN", "096", "Brunei", "Brunei Darussalam", &["Negara Brunei Darussalam"]),
+    country("BO", "BOL", "068", "Bolivia", "Bolivia", &["Bolivia (Plurinational State of)"]),
+    country("BQ", "BES", "535", "Bonaire, Sint Eustatius and Saba", "Caribisch Nederland", &[
+        "Caribbean Netherlands",
+    ]),
+    country("BR", "BRA", "076", "Brazil", "Brasil", &[]),
+    country("BS", "BHS", "044", "Bahamas", "Bahamas", &["The Bahamas"]),
+    country("BT", "BTN", "064", "Bhutan", "འབྲུག་ཡུལ", &[]),
+    country("BV", "BVT", "074", "Bouvet Island", "Bouvetøya", &[]),
+    country("BW", "BWA", "072", "Botswana", "Botswana", &[]),
+    country("BY", "BLR", "112", "Belarus", "Беларусь", &[]),
+    country("BZ", "BLZ", "084", "Belize", "Belize", &[]),
+    country("CA", "CAN", "124", "Canada", "Canada", &[]),
+    country("CC", "CCK", "166", "Cocos (Keeling) Islands", "Cocos (Keeling) Islands", &[
+        "Cocos Islands",
+    ]),
+    country("CD", "COD", "180", "DR Congo", "République démocratique du Congo", &[
+        "Democratic Republic of the Congo",
+        "Congo, Democratic Republic of the",
+        "Congo-Kinshasa",
+    ]),
+    country("CF", "CAF", "140", "Central African Republic", "République centrafricaine", &[
+        "Centrafrique",
+    ]),
+    country("CG", "COG", "178", "Congo", "Congo", &["Republic of the Congo", "Congo-Brazzaville"]),
+    country("CH", "CHE", "756", "Switzerland", "Schweiz", &["Suisse", "Svizzera", "Svizra"]),
+    country("CI", "CIV", "384", "Côte d'Ivoire", "Côte d'Ivoire", &[
+        "Cote d'Ivoire",
+        "Ivory Coast",
+    ]),
+    country("CK", "COK", "184", "Cook Islands", "Kūki 'Āirani", &[]),
+    country("CL", "CHL", "152", "Chile", "Chile", &[]),
+    country("CM", "CMR", "120", "Cameroon", "Cameroun", &[]),
+    country("CN", "CHN", "156", "China", "中国", &["People's Republic of China"]),
+    country("CO", "COL", "170", "Colombia", "Colombia", &[]),
+    country("CR", "CRI", "188", "Costa Rica", "Costa Rica", &[]),
+    country("CU", "CUB", "192", "Cuba", "Cuba", &[]),
+    country("CV", "CPV", "132", "Cabo Verde", "Cabo Verde", &["Cape Verde"]),
+    country("CW", "CUW", "531", "Curaçao", "Curaçao", &["Curacao"]),
+    country("CX", "CXR", "162", "Christmas Island", "Christmas Island", &[]),
+    country("CY", "CYP", "196", "Cyprus", "Κύπρος", &["Kıbrıs"]),
+    country("CZ", "CZE", "203", "Czechia", "Česko", &["Czech Republic", "Česká republika"]),
+    country("DE", "DEU", "276", "Germany", "Deutschland", &[]),
+    country("DJ", "DJI", "262", "Djibouti", "Djibouti", &[]),
+    country("DK", "DNK", "208", "Denmark", "Danmark", &[]),
+    country("DM", "DMA", "212", "Dominica", "Dominica", &[]),
+    country("DO", "DOM", "214", "Dominican Republic", "República Dominicana", &[]),
+    country("DZ", "DZA", "012", "Algeria", "الجزائر", &["Algérie"]),
+    country("EC", "ECU", "218", "Ecuador", "Ecuador", &[]),
+    country("EE", "EST", "233", "Estonia", "Eesti", &[]),
+    country("EG", "EGY", "818", "Egypt", "مصر", &[]),
+    country("EH", "ESH", "732", "Western Sahara", "الصحراء الغربية", &[]),
+    country("ER", "ERI", "232", "Eritrea", "ኤርትራ", &[]),
+    country("ES", "ESP", "724", "Spain", "España", &[]),
+    country("ET", "ETH", "231", "Ethiopia", "ኢትዮጵያ", &[]),
+    country("FI", "FIN", "246", "Finland", "Suomi", &[]),
+    country("FJ", "FJI", "242", "Fiji", "Fiji", &[]),
+    country("FK", "FLK", "238", "Falkland Islands", "Falkland Islands", &[
+        "Falkland Islands (Malvinas)",
+    ]),
+    country("FM", "FSM", "583", "Micronesia", "Micronesia", &[
+        "Federated States of Micronesia",
+        "Micronesia (Federated States of)",
+    ]),
+    country("FO", "FRO", "234", "Faroe Islands", "Føroyar", &[]),
+    country("FR", "FRA", "250", "France", "France", &[]),
+    country("GA", "GAB", "266", "Gabon", "Gabon", &[]),
+    country("GB", "GBR", "826", "United Kingdom", "United Kingdom", &[
+        "UK",
+        "Great Britain",
+        "Britain",
+        "United Kingdom of Great Britain and Northern Ireland",
+        "England",
+        "Scotland",
+        "Wales",
+        "Northern Ireland",
+    ]),
+    country("GD", "GRD", "308", "Grenada", "Grenada", &[]),
+    country("GE", "GEO", "268", "Georgia", "საქართველო", &[]),
+    country("GF", "GUF", "254", "French Guiana", "Guyane", &["Guyane française"]),
+    country("GG", "GGY", "831", "Guernsey", "Guernsey", &[]),
+    country("GH", "GHA", "288", "Ghana", "Ghana", &[]),
+    country("GI", "GIB", "292", "Gibraltar", "Gibraltar", &[]),
+    country("GL", "GRL", "304", "Greenland", "Kalaallit Nunaat", &["Grønland"]),
+    country("GM", "GMB", "270", "Gambia", "Gambia", &["The Gambia"]),
+    country("GN", "GIN", "324", "Guinea", "Guinée", &[]),
+    country("GP", "GLP", "312", "Guadeloupe", "Guadeloupe", &[]),
+    country("GQ", "GNQ", "226", "Equatorial Guinea", "Guinea Ecuatorial", &[]),
+    country("GR", "GRC", "300", "Greece", "Ελλάδα", &["Ελλάς", "Hellas"]),
+    country("GS", "SGS", "239", "South Georgia and the South Sandwich Islands",
+        "South Georgia and the South Sandwich Islands", &[]),
+    country("GT", "GTM", "320", "Guatemala", "Guatemala", &[]),
+    country("GU", "GUM", "316", "Guam", "Guam", &[]),
+    country("GW", "GNB", "624", "Guinea-Bissau", "Guiné-Bissau", &[]),
+    country("GY", "GUY", "328", "Guyana", "Guyana", &[]),
+    country("HK", "HKG", "344", "Hong Kong", "香港", &[]),
+    country("HM", "HMD", "334", "Heard Island and McDonald Islands",
+        "Heard Island and McDonald Islands", &[]),
+    country("HN", "HND", "340", "Honduras", "Honduras", &[]),
+    country("HR", "HRV", "191", "Croatia", "Hrvatska", &[]),
+    country("HT", "HTI", "332", "Haiti", "Haïti", &[]),
+    country("HU", "HUN", "348", "Hungary", "Magyarország", &[]),
+    country("ID", "IDN", "360", "Indonesia", "Indonesia", &[]),
+    country("IE", "IRL", "372", "Ireland", "Éire", &["Eire"]),
+    country("IL", "ISR", "376", "Israel", "ישראל", &[]),
+    country("IM", "IMN", "833", "Isle of Man", "Ellan Vannin", &[]),
+    country("IN", "IND", "356", "India", "भारत", &["Bharat"]),
+    country("IO", "IOT", "086", "British Indian Ocean Territory",
+        "British Indian Ocean Territory", &[]),
+    country("IQ", "IRQ", "368", "Iraq", "العراق", &[]),
+    country("IR", "IRN", "364", "Iran", "ایران", &[
+        "Islamic Republic of Iran",
+        "Iran (Islamic Republic of)",
+    ]),
+    country("IS", "ISL", "352", "Iceland", "Ísland", &[]),
+    country("IT", "ITA", "380", "Italy", "Italia", &[]),
+    country("JE", "JEY", "832", "Jersey", "Jersey", &[]),
+    country("JM", "JAM", "388", "Jamaica", "Jamaica", &[]),
+    country("JO", "JOR", "400", "Jordan", "الأردن", &[]),
+    country("JP", "JPN", "392", "Japan", "日本", &[]),
+    country("KE", "KEN", "404", "Kenya", "Kenya", &[]),
+    country("KG", "KGZ", "417", "Kyrgyzstan", "Кыргызстан", &[]),
+    country("KH", "KHM", "116", "Cambodia", "កម្ពុជា", &[]),
+    country("KI", "KIR", "296", "Kiribati", "Kiribati", &[]),
+    country("KM", "COM", "174", "Comoros", "Comores", &[]),
+    country("KN", "KNA", "659", "Saint Kitts and Nevis", "Saint Kitts and Nevis", &[]),
+    country("KP", "PRK", "408", "North Korea", "조선", &[
+        "Democratic People's Republic of Korea",
+        "Korea (Democratic People's Republic of)",
+        "DPRK",
+    ]),
+    country("KR", "KOR", "410", "South Korea", "대한민국", &[
+        "Republic of Korea",
+        "Korea, Republic of",
+        "Korea",
+    ]),
+    country("KW", "KWT", "414", "Kuwait", "الكويت", &[]),
+    country("KY", "CYM", "136", "Cayman Islands", "Cayman Islands", &[]),
+    country("KZ", "KAZ", "398", "Kazakhstan", "Қазақстан", &[]),
+    country("LA", "LAO", "418", "Laos", "ລາວ", &["Lao People's Democratic Republic"]),
+    country("LB", "LBN", "422", "Lebanon", "لبنان", &["Liban"]),
+    country("LC", "LCA", "662", "Saint Lucia", "Saint Lucia", &[]),
+    country("LI", "LIE", "438", "Liechtenstein", "Liechtenstein", &[]),
+    country("LK", "LKA", "144", "Sri Lanka", "ශ්‍රී ලංකාව", &[]),
+    country("LR", "LBR", "430", "Liberia", "Liberia", &[]),
+    country("LS", "LSO", "426", "Lesotho", "Lesotho", &[]),
+    country("LT", "LTU", "440", "Lithuania", "Lietuva", &[]),
+    country("LU", "LUX", "442", "Luxembourg", "Lëtzebuerg", &["Luxemburg"]),
+    country("LV", "LVA", "428", "Latvia", "Latvija", &[]),
+    country("LY", "LBY", "434", "Libya", "ليبيا", &[]),
+    country("MA", "MAR", "504", "Morocco", "المغرب", &["Maroc"]),
+    country("MC", "MCO", "492", "Monaco", "Monaco", &[]),
+    country("MD", "MDA", "498", "Moldova", "Moldova", &[
+        "Republic of Moldova",
+        "Moldova, Republic of",
+    ]),
+    country("ME", "MNE", "499", "Montenegro", "Crna Gora", &["Црна Гора"]),
+    country("MF", "MAF", "663", "Saint Martin", "Saint-Martin", &[
+        "Saint Martin (French part)",
+    ]),
+    country("MG", "MDG", "450", "Madagascar", "Madagasikara", &[]),
+    country("MH", "MHL", "584", "Marshall Islands", "Marshall Islands", &[]),
+    country("MK", "MKD", "807", "North Macedonia", "Северна Македонија", &["Macedonia"]),
+    country("ML", "MLI", "466", "Mali", "Mali", &[]),
+    country("MM", "MMR", "104", "Myanmar", "မြန်မာ", &["Burma"]),
+    country("MN", "MNG", "496", "Mongolia", "Монгол Улс", &[]),
+    country("MO", "MAC", "446", "Macao", "澳門", &["Macau"]),
+    country("MP", "MNP", "580", "Northern Mariana Islands", "Northern Mariana Islands", &[]),
+    country("MQ", "MTQ", "474", "Martinique", "Martinique", &[]),
+    country("MR", "MRT", "478", "Mauritania", "موريتانيا", &["Mauritanie"]),
+    country("MS", "MSR", "500", "Montserrat", "Montserrat", &[]),
+    country("MT", "MLT", "470", "Malta", "Malta", &[]),
+    country("MU", "MUS", "480", "Mauritius", "Mauritius", &["Maurice"]),
+    country("MV", "MDV", "462", "Maldives", "ދިވެހިރާއްޖެ", &[]),
+    country("MW", "MWI", "454", "Malawi", "Malawi", &[]),
+    country("MX", "MEX", "484", "Mexico", "México", &[]),
+    country("MY", "MYS", "458", "Malaysia", "Malaysia", &[]),
+    country("MZ", "MOZ", "508", "Mozambique", "Moçambique", &[]),
+    country("NA", "NAM", "516", "Namibia", "Namibia", &[]),
+    country("NC", "NCL", "540", "New Caledonia", "Nouvelle-Calédonie", &[]),
+    country("NE", "NER", "562", "Niger", "Niger", &[]),
+    country("NF", "NFK", "574", "Norfolk Island", "Norfolk Island", &[]),
+    country("NG", "NGA", "566", "Nigeria", "Nigeria", &[]),
+    country("NI", "NIC", "558", "Nicaragua", "Nicaragua", &[]),
+    country("NL", "NLD", "528", "Netherlands", "Nederland", &[
+        "The Netherlands",
+        "Holland",
+        "Netherlands (Kingdom of the)",
+    ]),
+    country("NO", "NOR", "578", "Norway", "Norge", &["Noreg"]),
+    country("NP", "NPL", "524", "Nepal", "नेपाल", &[]),
+    country("NR", "NRU", "520", "Nauru", "Nauru", &[]),
+    country("NU", "NIU", "570", "Niue", "Niue", &[]),
+    country("NZ", "NZL", "554", "New Zealand", "Aotearoa", &[]),
+    country("OM", "OMN", "512", "Oman", "عُمان", &[]),
+    country("PA", "PAN", "591", "Panama", "Panamá", &[]),
+    country("PE", "PER", "604", "Peru", "Perú", &[]),
+    country("PF", "PYF", "258", "French Polynesia", "Polynésie française", &[]),
+    country("PG", "PNG", "598", "Papua New Guinea", "Papua Niugini", &[]),
+    country("PH", "PHL", "608", "Philippines", "Pilipinas", &[]),
+    country("PK", "PAK", "586", "Pakistan", "پاکستان", &[]),
+    country("PL", "POL", "616", "Poland", "Polska", &[]),
+    country("PM", "SPM", "666", "Saint Pierre and Miquelon", "Saint-Pierre-et-Miquelon", &[]),
+    country("PN", "PCN", "612", "Pitcairn", "Pitcairn", &["Pitcairn Islands"]),
+    country("PR", "PRI", "630", "Puerto Rico", "Puerto Rico", &[]),
+    country("PS", "PSE", "275", "Palestine", "فلسطين", &[
+        "State of Palestine",
+        "Palestine, State of",
+    ]),
+    country("PT", "PRT", "620", "Portugal", "Portugal", &[]),
+    country("PW", "PLW", "585", "Palau", "Palau", &[]),
+    country("PY", "PRY", "600", "Paraguay", "Paraguay", &[]),
+    country("QA", "QAT", "634", "Qatar", "قطر", &[]),
+    country("RE", "REU", "638", "Réunion", "La Réunion", &["Reunion"]),
+    country("RO", "ROU", "642", "Romania", "România", &[]),
+    country("RS", "SRB", "688", "Serbia", "Србија", &["Srbija"]),
+    country("RU", "RUS", "643", "Russia", "Россия", &["Russian Federation"]),
+    country("RW", "RWA", "646", "Rwanda", "Rwanda", &[]),
+    country("SA", "SAU", "682", "Saudi Arabia", "السعودية", &[]),
+    country("SB", "SLB", "090", "Solomon Islands", "Solomon Islands", &[]),
+    country("SC", "SYC", "690", "Seychelles", "Seychelles", &[]),
+    country("SD", "SDN", "729", "Sudan", "السودان", &[]),
+    country("SE", "SWE", "752", "Sweden", "Sverige", &[]),
+    country("SG", "SGP", "702", "Singapore", "Singapore", &[]),
+    country("SH", "SHN", "654", "Saint Helena, Ascension and Tristan da Cunha", "Saint Helena",
+        &[]),
+    country("SI", "SVN", "705", "Slovenia", "Slovenija", &[]),
+    country("SJ", "SJM", "744", "Svalbard and Jan Mayen", "Svalbard og Jan Mayen", &[]),
+    country("SK", "SVK", "703", "Slovakia", "Slovensko", &["Slovak Republic"]),
+    country("SL", "SLE", "694", "Sierra Leone", "Sierra Leone", &[]),
+    country("SM", "SMR", "674", "San Marino", "San Marino", &[]),
+    country("SN", "SEN", "686", "Senegal", "Sénégal", &[]),
+    country("SO", "SOM", "706", "Somalia", "Soomaaliya", &[]),
+    country("SR", "SUR", "740", "Suriname", "Suriname", &[]),
+    country("SS", "SSD", "728", "South Sudan", "South Sudan", &[]),
+    country("ST", "STP", "678", "Sao Tome and Principe", "São Tomé e Príncipe", &[]),
+    country("SV", "SLV", "222", "El Salvador", "El Salvador", &[]),
+    country("SX", "SXM", "534", "Sint Maarten", "Sint Maarten", &["Sint Maarten (Dutch part)"]),
+    country("SY", "SYR", "760", "Syria", "سوريا", &["Syrian Arab Republic"]),
+    country("SZ", "SWZ", "748", "Eswatini", "eSwatini", &["Swaziland"]),
+    country("TC", "TCA", "796", "Turks and Caicos Islands", "Turks and Caicos Islands", &[]),
+    country("TD", "TCD", "148", "Chad", "Tchad", &[]),
+    country("TF", "ATF", "260", "French Southern Territories", "Terres australes françaises", &[]),
+    country("TG", "TGO", "768", "Togo", "Togo", &[]),
+    country("TH", "THA", "764", "Thailand", "ประเทศไทย", &[]),
+    country("TJ", "TJK", "762", "Tajikistan", "Тоҷикистон", &[]),
+    country("TK", "TKL", "772", "Tokelau", "Tokelau", &[]),
+    country("TL", "TLS", "626", "Timor-Leste", "Timor-Leste", &["East Timor"]),
+    country("TM", "TKM", "795", "Turkmenistan", "Türkmenistan", &[]),
+    country("TN", "TUN", "788", "Tunisia", "تونس", &["Tunisie"]),
+    country("TO", "TON", "776", "Tonga", "Tonga", &[]),
+    country("TR", "TUR", "792", "Türkiye", "Türkiye", &["Turkey", "Turkiye"]),
+    country("TT", "TTO", "780", "Trinidad and Tobago", "Trinidad and Tobago", &[]),
+    country("TV", "TUV", "798", "Tuvalu", "Tuvalu", &[]),
+    country("TW", "TWN", "158", "Taiwan", "臺灣", &["Taiwan, Province of China"]),
+    country("TZ", "TZA", "834", "Tanzania", "Tanzania", &[
+        "United Republic of Tanzania",
+        "Tanzania, United Republic of",
+    ]),
+    country("UA", "UKR", "804", "Ukraine", "Україна", &[]),
+    country("UG", "UGA", "800", "Uganda", "Uganda", &[]),
+    country("UM", "UMI", "581", "United States Minor Outlying Islands",
+        "United States Minor Outlying Islands", &[]),
+    country("US", "USA", "840", "United States", "United States", &[
+        "United States of America",
+        "U.S.",
+        "U.S.A.",
+    ]),
+    country("UY", "URY", "858", "Uruguay", "Uruguay", &[]),
+    country("UZ", "UZB", "860", "Uzbekistan", "Oʻzbekiston", &[]),
+    country("VA", "VAT", "336", "Vatican City", "Città del Vaticano", &["Holy See", "Vatican"]),
+    country("VC", "VCT", "670", "Saint Vincent and the Grenadines",
+        "Saint Vincent and the Grenadines", &[]),
+    country("VE", "VEN", "862", "Venezuela", "Venezuela", &["Venezuela (Bolivarian Republic of)"]),
+    country("VG", "VGB", "092", "British Virgin Islands", "British Virgin Islands", &[
+        "Virgin Islands (British)",
+    ]),
+    country("VI", "VIR", "850", "U.S. Virgin Islands", "U.S. Virgin Islands", &[
+        "Virgin Islands (U.S.)",
+    ]),
+    country("VN", "VNM", "704", "Vietnam", "Việt Nam", &["Viet Nam"]),
+    country("VU", "VUT", "548", "Vanuatu", "Vanuatu", &[]),
+    country("WF", "WLF", "876", "Wallis and Futuna", "Wallis-et-Futuna", &[]),
+    country("WS", "WSM", "882", "Samoa", "Samoa", &[]),
+    country("YE", "YEM", "887", "Yemen", "اليمن", &[]),
+    country("YT", "MYT", "175", "Mayotte", "Mayotte", &[]),
+    country("ZA", "ZAF", "710", "South Africa", "South Africa", &["Suid-Afrika"]),
+    country("ZM", "ZMB", "894", "Zambia", "Zambia", &[]),
+    country("ZW", "ZWE", "716", "Zimbabwe", "Zimbabwe", &[]),
+];
+
+/// Lower-cases a country name and collapses whitespace and apostrophes for matching.
+fn match_key(value: &str) -> String {
+    value
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .replace('’', "'")
+        .to_lowercase()
+}
+
+/// Finds a country by its alpha-2, alpha-3 or numeric code, English or native name or alias.
+///
+/// Case, repeated whitespace and typographic apostrophes are ignored.
+pub fn find_country(value: &str) -> Option<&'static Country> {
+    let key = match_key(value);
+    if key.is_empty() {
+        return None;
+    }
+    COUNTRIES.iter().find(|c| {
+        [c.alpha2, c.alpha3, c.numeric, c.name, c.native]
+            .iter()
+            .chain(c.aliases.iter())
+            .any(|name| match_key(name) == key)
+    })
+}
+
+/// Normalizes a country to the given form: `alpha2`, `alpha3`, `numeric`, `name` or `native`.
+///
+/// Returns `None` if the country is not recognised.
+pub fn normalize(value: &str, form: &str) -> Result<Option<&'static str>, ParseError> {
+    let form: fn(&Country) -> &'static str = match form.trim().to_lowercase().as_str() {
+        "alpha2" => |c| c.alpha2,
+        "alpha3" => |c| c.alpha3,
+        "numeric" => |c| c.numeric,
+        "name" => |c| c.name,
+        "native" => |c| c.native,
+        _ => {
+            return Err(ParseError {
+                errcode: PgSqlErrorCode::ERRCODE_INVALID_PARAMETER_VALUE,
+                message: format!("unknown country form: \"{}\"", form),
+                detail: "Valid forms are alpha2, alpha3, numeric, name and native.".to_string(),
+            })
+        }
+    };
+    Ok(find_country(value).map(form))
+}
+
+/// Returns the ISO 3166-1 countries.
+///
+/// # Returns
+/// A row per country with the `alpha2`, `alpha3` and `numeric` codes (the latter as text with
+/// leading zeros), the English short `name` and the `native_name` in the first official
+/// language, ordered by `alpha2`.
+#[pg_extern(immutable, parallel_safe, create_or_replace)]
+pub fn iso_countries() -> TableIterator<
+    'static,
+    (
+        name!(alpha2, String),
+        name!(alpha3, String),
+        name!(numeric, String),
+        name!(name, String),
+        name!(native_name, String),
+    ),
+> {
+    TableIterator::new(COUNTRIES.iter().map(|c| {
+        (
+            c.alpha2.to_string(),
+            c.alpha3.to_string(),
+            c.numeric.to_string(),
+            c.name.to_string(),
+            c.native.to_string(),
+        )
+    }))
+}
+
+/// Normalizes a country code, name or alias to a canonical form.
+///
+/// # Parameters
+/// - `country`: An ISO 3166-1 alpha-2, alpha-3 or numeric code, English or native name, or a
+///   common alias, e.g. `EE`, `EST`, `233`, `Estonia` or `Eesti`. Case is ignored.
+/// - `form`: The form to return: `alpha2` (default), `alpha3`, `numeric`, `name` (English)
+///   or `native`.
+///
+/// # Returns
+/// - The country in the requested form, e.g. `EE`.
+/// - `null` if the country is not recognised.
+///
+/// # Errors
+/// - `22023` (`invalid_parameter_value`) if `form` is unknown.
+#[pg_extern(immutable, parallel_safe, create_or_replace)]
+pub fn normalize_country(country: &str, form: default!(&str, "'alpha2'")) -> Option<String> {
+    normalize(country, form)
+        .unwrap_or_else(|e| e.report())
+        .map(str::to_string)
+}
+
+#[cfg(any(test, feature = "pg_test"))]
+#[pg_schema]
+mod tests {
+    use super::*;
+
+    /// Tests `iso_countries`
+    #[pg_test]
+    fn test_iso_countries() {
+        let countries: Vec<_> = iso_countries().collect();
+        assert_eq!(249, countries.len());
+        let estonia = countries.iter().find(|c| c.0 == "EE").unwrap();
+        assert_eq!(("EST", "233"), (estonia.1.as_str(), estonia.2.as_str()));
+        assert_eq!(
+            ("Estonia", "Eesti"),
+            (estonia.3.as_str(), estonia.4.as_str())
+        );
+        assert!(countries.iter().any(|c| c.0 == "AF" && c.2 == "004"));
+        assert!(countries.windows(2).all(|w| w[0].0 < w[1].0));
+        let mut codes: Vec<_> = countries.iter().map(|c| c.1.as_str()).collect();
+        codes.sort();
+        codes.dedup();
+        assert_eq!(249, codes.len());
+    }
+
+    /// Tests `normalize_country`
+    #[pg_test]
+    fn test_normalize_country() {
+        for name in ["Estonia", "EE", "ee", "Eesti", "EST", "233", " estonia "] {
+            assert_eq!(
+                Some("EE".to_string()),
+                normalize_country(name, "alpha2"),
+                "{}",
+                name
+            );
+        }
+        assert_eq!(
+            Some("EST".to_string()),
+            normalize_country("Eesti", "alpha3")
+        );
+        assert_eq!(Some("233".to_string()), normalize_country("EE", "numeric"));
+        assert_eq!(
+            Some("Estonia".to_string()),
+            normalize_country("EST", "name")
+        );
+        assert_eq!(
+            Some("Eesti".to_string()),
+            normalize_country("Estonia", "native")
+        );
+        assert_eq!(
+            Some("GB".to_string()),
+            normalize_country("Great  Britain", "alpha2")
+        );
+        assert_eq!(
+            Some("CI".to_string()),
+            normalize_country("Côte d’Ivoire", "alpha2")
+        );
+        assert_eq!(
+            Some("Germany".to_string()),
+            normalize_country("DEUTSCHLAND", "Name")
+        );
+        assert_eq!(None, normalize_country("Atlantis", "alpha2"));
+        assert_eq!(None, normalize_country("", "alpha2"));
+    }
+
+    /// Tests `normalize_country` with an unknown form
+    #[pg_test(error = "unknown country form: \"iso\"")]
+    fn test_normalize_country_error() {
+        normalize_country("Estonia", "iso");
+    }
+}
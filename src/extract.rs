@@ -0,0 +1,203 @@
+//! Extraction and bulk normalisation of waste codes.
+use crate::{parse_disposal_code, parse_env_code, parse_low_code, parse_recovery_code};
+#[allow(unused_imports)]
+use pgrx::prelude::{name, pg_extern, pg_schema, pg_test, TableIterator};
+use regex::Regex;
+
+/// Code found in free text.
+pub struct FoundCode {
+    /// 1-based character position of the match.
+    pub position: i32,
+    /// `lowcode`, `disposalcode` or `recoverycode`, as used by `parse_env_code`.
+    pub code_type: &'static str,
+    /// The normalised code.
+    pub code: String,
+    /// The text as it appears in the input.
+    pub matched: String,
+}
+
+/// Finds all LoW entries and disposal and recovery codes in the given text, in order of appearance.
+///
+/// LoW codes are only recognised as 6-digit entries, digits optionally separated by spaces,
+/// dots or dashes used consistently. Chapters and sub-chapters are not extracted, as they
+/// cannot be told apart from other numbers. Neither are digit pairs that are part of a longer
+/// sequence of groups, e.g. the phone number `12 34 56 78`.
+pub fn find_env_codes(value: &str) -> Vec<FoundCode> {
+    let re_low = Regex::new(
+        r"\b(?:\d{6}|\d{2} \d{2} \d{2}|\d{2}\.\d{2}\.\d{2}|\d{2}-\d{2}-\d{2})\b(?:\s?\*)?",
+    )
+    .unwrap();
+    let re_before = Regex::new(r"\d[.-]$").unwrap();
+    let re_after = Regex::new(r"^[.-]\d").unwrap();
+    let re_before_spaced = Regex::new(r"\d $").unwrap();
+    let re_after_spaced = Regex::new(r"^ \d").unwrap();
+    let re_op = Regex::new(r"(?i)\b([DR])\s?(\d{1,2}(?:\.\d{1,2})?)\b").unwrap();
+    let position = |start: usize| (value[..start].chars().count() + 1) as i32;
+    let mut found: Vec<FoundCode> = Vec::new();
+    for m in re_low.find_iter(value) {
+        // Part of a longer dotted or dashed number, e.g. a date
+        if re_before.is_match(&value[..m.start()]) || re_after.is_match(&value[m.end()..]) {
+            continue;
+        }
+        // Part of a longer sequence of space-separated groups, e.g. a phone number
+        let spaced = m.as_str().as_bytes()[2] == b' ';
+        if spaced
+            && (re_before_spaced.is_match(&value[..m.start()])
+                || re_after_spaced.is_match(&value[m.end()..]))
+        {
+            continue;
+        }
+        if let Some(code) = parse_low_code(m.as_str()) {
+            found.push(FoundCode {
+                position: position(m.start()),
+                code_type: "lowcode",
+                code,
+                matched: m.as_str().to_string(),
+            });
+        }
+    }
+    for caps in re_op.captures_iter(value) {
+        let m = caps.get(0).unwrap();
+        let compact = format!("{}{}", &caps[1], &caps[2]);
+        let parsed = if caps[1].eq_ignore_ascii_case("d") {
+            parse_disposal_code(&compact).map(|c| ("disposalcode", c))
+        } else {
+            parse_recovery_code(&compact).map(|c| ("recoverycode", c))
+        };
+        if let Some((code_type, code)) = parsed {
+            found.push(FoundCode {
+                position: position(m.start()),
+                code_type,
+                code,
+                matched: m.as_str().to_string(),
+            });
+        }
+    }
+    found.sort_by_key(|f| f.position);
+    found
+}
+
+/// Extracts all LoW, disposal and recovery codes from free text.
+///
+/// # Parameters
+/// - `value`: Free text, e.g. `codes 17 05 04, 17 09 04 and D1/R5`.
+///
+/// # Returns
+/// A set of rows, one for every code found, in order of appearance:
+/// - `position`: The 1-based character position of the code in `value`.
+/// - `code_type`: `lowcode`, `disposalcode` or `recoverycode`, as used by `parse_env_code`.
+/// - `code`: The code normalised by `parse_low_code`, `parse_disposal_code` or `parse_recovery_code`.
+/// - `matched`: The code as it appears in `value`.
+///
+/// LoW codes are only recognised as 6-digit entries (e.g. `17 05 04`, `17.05.03*`),
+/// as chapters and sub-chapters cannot be told apart from other numbers.
+#[pg_extern(create_or_replace)]
+pub fn extract_env_codes(
+    value: &str,
+) -> TableIterator<
+    'static,
+    (
+        name!(position, i32),
+        name!(code_type, String),
+        name!(code, String),
+        name!(matched, String),
+    ),
+> {
+    let rows: Vec<_> = find_env_codes(value)
+        .into_iter()
+        .map(|f| (f.position, f.code_type.to_string(), f.code, f.matched))
+        .collect();
+    TableIterator::new(rows)
+}
+
+/// Parses every element of an array with `parse_env_code`.
+///
+/// # Parameters
+/// - `values`: An array of codes.
+/// - `code_type`: The type of code to parse (`"disposalcode"`, `"recoverycode"`, or `"lowcode"`).
+///
+/// # Returns
+/// An array of the same length, with every element parsed by `parse_env_code`.
+/// Elements that are `null` or cannot be parsed are `null`.
+#[pg_extern(create_or_replace)]
+pub fn parse_env_codes(values: Vec<Option<String>>, code_type: &str) -> Vec<Option<String>> {
+    values
+        .iter()
+        .map(|v| v.as_deref().and_then(|v| parse_env_code(v, code_type)))
+        .collect()
+}
+
+#[cfg(any(test, feature = "pg_test"))]
+#[pg_schema]
+mod tests {
+    use super::*;
+
+    /// Tests `extract_env_codes`
+    #[pg_test]
+    fn test_extract_env_codes() {
+        let rows: Vec<_> = extract_env_codes("codes 17 05 04, 17 09 04 and D1/R5").collect();
+        assert_eq!(4, rows.len());
+        assert_eq!(
+            (
+                7,
+                "lowcode".to_string(),
+                "170504".to_string(),
+                "17 05 04".to_string()
+            ),
+            rows[0]
+        );
+        assert_eq!((17, "170904".to_string()), (rows[1].0, rows[1].2.clone()));
+        assert_eq!(
+            (30, "disposalcode".to_string(), "D1".to_string()),
+            (rows[2].0, rows[2].1.clone(), rows[2].2.clone())
+        );
+        assert_eq!(
+            (33, "recoverycode".to_string(), "R5".to_string()),
+            (rows[3].0, rows[3].1.clone(), rows[3].2.clone())
+        );
+        let rows: Vec<_> = extract_env_codes("Jäätmed 17.05.03 *, r 13 ja d10.1").collect();
+        assert_eq!(
+            vec!["170503*", "R13", "D10.1"],
+            rows.iter().map(|r| r.2.as_str()).collect::<Vec<_>>()
+        );
+        assert_eq!(9, rows[0].0);
+        assert_eq!("17.05.03 *", rows[0].3);
+        // Dates, phone numbers and words are ignored
+        assert_eq!(
+            0,
+            extract_env_codes("2024-05-04, 01.02.03.04, +372 5551234, Dr Smith, RD100").count()
+        );
+        assert_eq!(0, extract_env_codes("tel 12 34 56 78").count());
+        assert_eq!(0, extract_env_codes("tel 5 12 34 56").count());
+        assert_eq!(
+            vec!["170504"],
+            extract_env_codes("17 05 04, 12 34 56 78")
+                .map(|r| r.2)
+                .collect::<Vec<_>>()
+        );
+    }
+
+    /// Tests `parse_env_codes`
+    #[pg_test]
+    fn test_parse_env_codes() {
+        let values = vec![
+            Some("17 05 04".to_string()),
+            None,
+            Some("bla".to_string()),
+            Some("170503*".to_string()),
+        ];
+        assert_eq!(
+            vec![
+                Some("170504".to_string()),
+                None,
+                None,
+                Some("170503*".to_string())
+            ],
+            parse_env_codes(values, "lowcode")
+        );
+        assert_eq!(
+            vec![Some("D1".to_string())],
+            parse_env_codes(vec![Some("d1".to_string())], "disposalcode")
+        );
+    }
+}
@@ -0,0 +1,372 @@
+//! Distances, bearings and bounding boxes between the GPS coordinates stored by `to_address`,
+//! for proximity queries without PostGIS.
+//!
+//! ```sql
+//! select site.id, bfn.address_distance(site.adr, :home) as metres
+//! from site, bfn.gps_bounding_box(:home_gps, 5000) box
+//! where (site.adr->'gps'->>0)::float8 between box.min_lat and box.max_lat
+//!   and (site.adr->'gps'->>1)::float8 between box.min_lon and box.max_lon
+//! order by metres
+//! limit 1;
+//! ```
+use crate::gps::check_coordinates;
+use crate::parse_strict::ParseError;
+#[allow(unused_imports)]
+use pgrx::prelude::{default, name, pg_extern, pg_schema, pg_test, PgSqlErrorCode, TableIterator};
+use serde_json::Value;
+
+/// Mean radius of the Earth in metres, as used by the haversine formula.
+pub const EARTH_RADIUS: f64 = 6_371_008.8;
+
+/// Padding of bounding boxes, more than the largest relative difference between haversine and
+/// Vincenty distances (about 0.5%).
+const BOX_MARGIN: f64 = 0.01;
+
+/// Semi-major axis of the WGS 84 ellipsoid in metres.
+const WGS84_A: f64 = 6_378_137.0;
+
+/// Flattening of the WGS 84 ellipsoid.
+const WGS84_F: f64 = 1.0 / 298.257_223_563;
+
+/// Returns checked coordinates from the first two elements of an array.
+pub(crate) fn point(gps: &[Option<f64>]) -> Option<[f64; 2]> {
+    match gps {
+        [Some(lat), Some(lon), ..] => check_coordinates(*lat, *lon, false).ok(),
+        _ => None,
+    }
+}
+
+/// Returns checked coordinates from the `gps` key of a `to_address` object.
+fn address_point(address: &Value) -> Option<[f64; 2]> {
+    let gps = address.get("gps")?.as_array()?;
+    point(&gps.iter().map(Value::as_f64).collect::<Vec<_>>())
+}
+
+/// Great-circle distance in metres on a sphere with the mean radius of the Earth.
+pub fn haversine(a: [f64; 2], b: [f64; 2]) -> f64 {
+    let (lat1, lat2) = (a[0].to_radians(), b[0].to_radians());
+    let d_lat = lat2 - lat1;
+    let d_lon = (b[1] - a[1]).to_radians();
+    let h = (d_lat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (d_lon / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS * h.sqrt().min(1.0).asin()
+}
+
+/// Distance in metres on the WGS 84 ellipsoid, by Vincenty's inverse formula.
+///
+/// Returns `None` for nearly antipodal points, where the iteration does not converge.
+pub fn vincenty(a: [f64; 2], b: [f64; 2]) -> Option<f64> {
+    let b_axis = WGS84_A * (1.0 - WGS84_F);
+    let l = (b[1] - a[1]).to_radians();
+    let u1 = ((1.0 - WGS84_F) * a[0].to_radians().tan()).atan();
+    let u2 = ((1.0 - WGS84_F) * b[0].to_radians().tan()).atan();
+    let (sin_u1, cos_u1) = u1.sin_cos();
+    let (sin_u2, cos_u2) = u2.sin_cos();
+    let mut lambda = l;
+    for _ in 0..200 {
+        let (sin_lambda, cos_lambda) = lambda.sin_cos();
+        let sin_sigma = ((cos_u2 * sin_lambda).powi(2)
+            + (cos_u1 * sin_u2 - sin_u1 * cos_u2 * cos_lambda).powi(2))
+        .sqrt();
+        if sin_sigma == 0.0 {
+            return Some(0.0); // Coincident points
+        }
+        let cos_sigma = sin_u1 * sin_u2 + cos_u1 * cos_u2 * cos_lambda;
+        let sigma = sin_sigma.atan2(cos_sigma);
+        let sin_alpha = cos_u1 * cos_u2 * sin_lambda / sin_sigma;
+        let cos2_alpha = 1.0 - sin_alpha.powi(2);
+        let cos_2sigma_m = if cos2_alpha == 0.0 {
+            0.0 // Both points on the equator
+        } else {
+            cos_sigma - 2.0 * sin_u1 * sin_u2 / cos2_alpha
+        };
+        let c = WGS84_F / 16.0 * cos2_alpha * (4.0 + WGS84_F * (4.0 - 3.0 * cos2_alpha));
+        let previous = lambda;
+        lambda = l
+            + (1.0 - c)
+                * WGS84_F
+                * sin_alpha
+                * (sigma
+                    + c * sin_sigma
+                        * (cos_2sigma_m + c * cos_sigma * (-1.0 + 2.0 * cos_2sigma_m.powi(2))));
+        if (lambda - previous).abs() < 1e-12 {
+            let u2 = cos2_alpha * (WGS84_A.powi(2) - b_axis.powi(2)) / b_axis.powi(2);
+            let k1 = ((1.0 + u2).sqrt() - 1.0) / ((1.0 + u2).sqrt() + 1.0);
+            let big_a = (1.0 + k1.powi(2) / 4.0) / (1.0 - k1);
+            let big_b = k1 * (1.0 - 3.0 / 8.0 * k1.powi(2));
+            let delta_sigma = big_b
+                * sin_sigma
+                * (cos_2sigma_m
+                    + big_b / 4.0
+                        * (cos_sigma * (-1.0 + 2.0 * cos_2sigma_m.powi(2))
+                            - big_b / 6.0
+                                * cos_2sigma_m
+                                * (-3.0 + 4.0 * sin_sigma.powi(2))
+                                * (-3.0 + 4.0 * cos_2sigma_m.powi(2))));
+            return Some(b_axis * big_a * (sigma - delta_sigma));
+        }
+    }
+    None
+}
+
+/// Distance in metres by the given method, `haversine` or `vincenty`.
+///
+/// Vincenty's formula falls back to haversine for nearly antipodal points.
+pub fn distance(a: [f64; 2], b: [f64; 2], method: &str) -> Result<f64, ParseError> {
+    match method.trim().to_lowercase().as_str() {
+        "haversine" => Ok(haversine(a, b)),
+        "vincenty" => Ok(vincenty(a, b).unwrap_or_else(|| haversine(a, b))),
+        _ => Err(ParseError {
+            errcode: PgSqlErrorCode::ERRCODE_INVALID_PARAMETER_VALUE,
+            message: format!("unknown distance method: \"{}\"", method),
+            detail: "Supported methods are haversine and vincenty.".to_string(),
+        }),
+    }
+}
+
+/// Initial bearing in degrees from north (0 to 360) on the great circle from `a` to `b`.
+pub fn bearing(a: [f64; 2], b: [f64; 2]) -> f64 {
+    let (lat1, lat2) = (a[0].to_radians(), b[0].to_radians());
+    let d_lon = (b[1] - a[1]).to_radians();
+    let y = d_lon.sin() * lat2.cos();
+    let x = lat1.cos() * lat2.sin() - lat1.sin() * lat2.cos() * d_lon.cos();
+    (y.atan2(x).to_degrees() + 360.0) % 360.0
+}
+
+/// Returns the distance between two coordinate pairs.
+///
+/// # Parameters
+/// - `a`: An array of latitude and longitude, e.g. the `gps` value of `to_address`.
+/// - `b`: An array of latitude and longitude.
+/// - `method`: `haversine` (great circle on a sphere, fast) or `vincenty` (WGS 84 ellipsoid,
+///   accurate to millimetres). Defaults to `haversine`, which is within 0.5% of `vincenty`.
+///
+/// # Returns
+/// - The distance in metres.
+/// - `null` if an array has fewer than two values or the coordinates are out of range.
+///
+/// # Errors
+/// - `22023` (`invalid_parameter_value`) if the method is unknown.
+#[pg_extern(immutable, parallel_safe, create_or_replace)]
+pub fn gps_distance(
+    a: Vec<Option<f64>>,
+    b: Vec<Option<f64>>,
+    method: default!(&str, "'haversine'"),
+) -> Option<f64> {
+    let (a, b) = (point(&a), point(&b));
+    Some(distance(a?, b?, method).unwrap_or_else(|e| e.report()))
+}
+
+/// Returns the distance between two addresses.
+///
+/// # Parameters
+/// - `a`: An address as returned by `to_address`.
+/// - `b`: An address as returned by `to_address`.
+/// - `method`: `haversine` or `vincenty`, see `gps_distance`. Defaults to `haversine`.
+///
+/// # Returns
+/// - The distance in metres between the `gps` coordinates of the addresses.
+/// - `null` if an address has no valid `gps` coordinates.
+///
+/// # Errors
+/// - `22023` (`invalid_parameter_value`) if the method is unknown.
+#[pg_extern(immutable, parallel_safe, create_or_replace)]
+pub fn address_distance(
+    a: pgrx::JsonB,
+    b: pgrx::JsonB,
+    method: default!(&str, "'haversine'"),
+) -> Option<f64> {
+    let (a, b) = (address_point(&a.0), address_point(&b.0));
+    Some(distance(a?, b?, method).unwrap_or_else(|e| e.report()))
+}
+
+/// Returns the initial bearing from one coordinate pair to another.
+///
+/// # Parameters
+/// - `a`: An array of latitude and longitude to start from.
+/// - `b`: An array of latitude and longitude to go to.
+///
+/// # Returns
+/// - The bearing in degrees clockwise from north, from 0 up to 360 (90 is east).
+/// - `null` if an array has fewer than two values or the coordinates are out of range.
+#[pg_extern(immutable, parallel_safe, create_or_replace)]
+pub fn gps_bearing(a: Vec<Option<f64>>, b: Vec<Option<f64>>) -> Option<f64> {
+    Some(bearing(point(&a)?, point(&b)?))
+}
+
+/// Checks whether a coordinate pair is within a radius of another.
+///
+/// # Parameters
+/// - `gps`: An array of latitude and longitude to check.
+/// - `center`: An array of latitude and longitude of the center.
+/// - `radius`: The radius in metres.
+///
+/// # Returns
+/// - `true` if the haversine distance is at most `radius`, `false` otherwise.
+/// - `null` if an array has fewer than two values or the coordinates are out of range.
+#[pg_extern(immutable, parallel_safe, create_or_replace)]
+pub fn gps_within(gps: Vec<Option<f64>>, center: Vec<Option<f64>>, radius: f64) -> Option<bool> {
+    Some(haversine(point(&gps)?, point(&center)?) <= radius)
+}
+
+/// Returns a bounding box around a coordinate pair, for prefiltering with btree indexes
+/// before calculating distances.
+///
+/// All points within `radius` lie within the box, by haversine or Vincenty distance: the box is
+/// padded by 1% for the difference between the sphere and the ellipsoid. If the box would
+/// include a pole or cross the 180th meridian, it spans all longitudes.
+///
+/// # Parameters
+/// - `center`: An array of latitude and longitude.
+/// - `radius`: The radius in metres.
+///
+/// # Returns
+/// A row with `min_lat`, `min_lon`, `max_lat` and `max_lon`, or no row if `center` has fewer
+/// than two values, the coordinates are out of range or `radius` is negative.
+#[pg_extern(immutable, parallel_safe, create_or_replace)]
+pub fn gps_bounding_box(
+    center: Vec<Option<f64>>,
+    radius: f64,
+) -> TableIterator<
+    'static,
+    (
+        name!(min_lat, f64),
+        name!(min_lon, f64),
+        name!(max_lat, f64),
+        name!(max_lon, f64),
+    ),
+> {
+    let Some([lat, lon]) = point(&center).filter(|_| radius >= 0.0) else {
+        return TableIterator::new(Vec::new());
+    };
+    let angle = radius * (1.0 + BOX_MARGIN) / EARTH_RADIUS;
+    let d_lat = angle.to_degrees();
+    let (min_lat, max_lat) = (lat - d_lat, lat + d_lat);
+    if min_lat <= -90.0 || max_lat >= 90.0 {
+        return TableIterator::once((min_lat.max(-90.0), -180.0, max_lat.min(90.0), 180.0));
+    }
+    // Widest longitude difference of the circle, at the latitude of its tangent points
+    let d_lon = (angle.sin() / lat.to_radians().cos())
+        .min(1.0)
+        .asin()
+        .to_degrees();
+    let (min_lon, max_lon) = (lon - d_lon, lon + d_lon);
+    if min_lon < -180.0 || max_lon > 180.0 {
+        return TableIterator::once((min_lat, -180.0, max_lat, 180.0));
+    }
+    TableIterator::once((min_lat, min_lon, max_lat, max_lon))
+}
+
+#[cfg(any(test, feature = "pg_test"))]
+#[pg_schema]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const TALLINN: [Option<f64>; 2] = [Some(59.437), Some(24.745)];
+    const HELSINKI: [Option<f64>; 2] = [Some(60.1699), Some(24.9384)];
+
+    /// Tests `gps_distance`
+    #[pg_test]
+    fn test_gps_distance() {
+        let d = gps_distance(TALLINN.to_vec(), HELSINKI.to_vec(), "haversine").unwrap();
+        assert!((d - 82209.4).abs() < 1.0);
+        // Flinders Peak to Buninyong, the example of Vincenty's paper
+        let a = vec![Some(-37.95103342), Some(144.42486789)];
+        let b = vec![Some(-37.65282114), Some(143.92649554)];
+        let d = gps_distance(a.clone(), b.clone(), "Vincenty").unwrap();
+        assert!((d - 54972.271).abs() < 0.001);
+        assert_eq!(Some(0.0), gps_distance(a.clone(), a.clone(), "vincenty"));
+        // Antipodal points fall back to haversine
+        let d = gps_distance(
+            vec![Some(0.0), Some(0.0)],
+            vec![Some(0.5), Some(179.7)],
+            "vincenty",
+        );
+        assert!(d.unwrap() > 19_000_000.0);
+        assert_eq!(
+            None,
+            gps_distance(vec![Some(95.0), Some(0.0)], b, "haversine")
+        );
+        assert_eq!(None, gps_distance(vec![Some(59.437)], a, "haversine"));
+    }
+
+    /// Tests `gps_distance` with an unknown method
+    #[pg_test(error = "unknown distance method: \"euclid\"")]
+    fn test_gps_distance_error() {
+        gps_distance(TALLINN.to_vec(), HELSINKI.to_vec(), "euclid");
+    }
+
+    /// Tests `address_distance`
+    #[pg_test]
+    fn test_address_distance() {
+        let a = pgrx::JsonB(json!({"city": "Tallinn", "gps": [59.437, 24.745]}));
+        let b = pgrx::JsonB(json!({"city": "Helsinki", "gps": [60.1699, 24.9384]}));
+        let d = address_distance(a, b, "haversine").unwrap();
+        assert!((d - 82209.4).abs() < 1.0);
+        let a = pgrx::JsonB(json!({"city": "Tallinn", "gps": null}));
+        let b = pgrx::JsonB(json!({"city": "Helsinki", "gps": [60.1699, 24.9384]}));
+        assert_eq!(None, address_distance(a, b, "haversine"));
+    }
+
+    /// Tests `gps_bearing`
+    #[pg_test]
+    fn test_gps_bearing() {
+        let north = gps_bearing(TALLINN.to_vec(), HELSINKI.to_vec()).unwrap();
+        assert!((north - 7.48).abs() < 0.01);
+        let east = gps_bearing(vec![Some(0.0), Some(0.0)], vec![Some(0.0), Some(1.0)]);
+        assert!((east.unwrap() - 90.0).abs() < 1e-9);
+        let west = gps_bearing(vec![Some(0.0), Some(1.0)], vec![Some(0.0), Some(0.0)]);
+        assert!((west.unwrap() - 270.0).abs() < 1e-9);
+    }
+
+    /// Tests `gps_within`
+    #[pg_test]
+    fn test_gps_within() {
+        assert_eq!(
+            Some(true),
+            gps_within(HELSINKI.to_vec(), TALLINN.to_vec(), 83_000.0)
+        );
+        assert_eq!(
+            Some(false),
+            gps_within(HELSINKI.to_vec(), TALLINN.to_vec(), 80_000.0)
+        );
+        assert_eq!(None, gps_within(vec![], TALLINN.to_vec(), 80_000.0));
+    }
+
+    /// Tests `gps_bounding_box`
+    #[pg_test]
+    fn test_gps_bounding_box() {
+        let rows: Vec<_> = gps_bounding_box(TALLINN.to_vec(), 10_000.0).collect();
+        let (min_lat, min_lon, max_lat, max_lon) = rows[0];
+        assert!((max_lat - min_lat - 0.1817).abs() < 0.001);
+        assert!(min_lon < 24.745 && max_lon > 24.745);
+        // Points on the circle lie in the box
+        for bearing in (0..360).step_by(15) {
+            let (lat, lon) = destination([59.437, 24.745], bearing as f64, 9_999.0);
+            assert!(lat >= min_lat && lat <= max_lat && lon >= min_lon && lon <= max_lon);
+        }
+        // Points within the radius by Vincenty distance lie in the box, e.g. north of the
+        // equator, where a degree of latitude is shorter on the ellipsoid than on the sphere
+        let rows: Vec<_> = gps_bounding_box(vec![Some(0.0), Some(0.0)], 100_000.0).collect();
+        assert!(vincenty([0.0, 0.0], [0.904, 0.0]).unwrap() < 100_000.0);
+        assert!(0.904 <= rows[0].2);
+        let rows: Vec<_> = gps_bounding_box(vec![Some(89.99), Some(0.0)], 10_000.0).collect();
+        assert_eq!((-180.0, 90.0, 180.0), (rows[0].1, rows[0].2, rows[0].3));
+        let rows: Vec<_> = gps_bounding_box(vec![Some(0.0), Some(179.99)], 10_000.0).collect();
+        assert_eq!((-180.0, 180.0), (rows[0].1, rows[0].3));
+        assert_eq!(0, gps_bounding_box(TALLINN.to_vec(), -1.0).count());
+    }
+
+    /// Destination point at a distance and bearing on the sphere.
+    fn destination(from: [f64; 2], bearing: f64, distance: f64) -> (f64, f64) {
+        let (lat1, lon1, b) = (
+            from[0].to_radians(),
+            from[1].to_radians(),
+            bearing.to_radians(),
+        );
+        let d = distance / EARTH_RADIUS;
+        let lat2 = (lat1.sin() * d.cos() + lat1.cos() * d.sin() * b.cos()).asin();
+        let lon2 = lon1 + (b.sin() * d.sin() * lat1.cos()).atan2(d.cos() - lat1.sin() * lat2.sin());
+        (lat2.to_degrees(), lon2.to_degrees())
+    }
+}
@@ -0,0 +1,308 @@
+//! Validation, normalisation and parsing of the GPS coordinates stored by `to_address`.
+//!
+//! Coordinates are pairs of latitude and longitude in decimal degrees (WGS 84), as in the
+//! `gps` key of `to_address`.
+use crate::extract_f64_from_vec;
+use crate::parse_strict::ParseError;
+#[allow(unused_imports)]
+use pgrx::prelude::{default, pg_extern, pg_schema, pg_test, AnyNumeric, PgSqlErrorCode};
+use regex::{Captures, Regex};
+
+/// Largest supported number of decimal places of rounded coordinates.
+const MAX_PRECISION: i32 = 15;
+
+/// Checks that latitude and longitude are within range.
+///
+/// If `swap` is set and the latitude is out of range while the longitude would be a valid
+/// latitude, the pair is taken to be swapped and is returned in the right order. Pairs where both
+/// values are valid latitudes cannot be told apart and are returned unchanged.
+pub fn check_coordinates(lat: f64, lon: f64, swap: bool) -> Result<[f64; 2], ParseError> {
+    let invalid = |detail: String| {
+        Err(ParseError {
+            errcode: PgSqlErrorCode::ERRCODE_INVALID_PARAMETER_VALUE,
+            message: format!("invalid coordinates: [{}, {}]", lat, lon),
+            detail,
+        })
+    };
+    if !lat.is_finite() || !lon.is_finite() {
+        return invalid("Coordinates must be finite numbers.".to_string());
+    }
+    let (lat, lon) = if swap && lat.abs() > 90.0 && lon.abs() <= 90.0 {
+        (lon, lat)
+    } else {
+        (lat, lon)
+    };
+    if lat.abs() > 90.0 {
+        return invalid(format!("Latitude {} is not between -90 and 90.", lat));
+    }
+    if lon.abs() > 180.0 {
+        return invalid(format!("Longitude {} is not between -180 and 180.", lon));
+    }
+    Ok([lat, lon])
+}
+
+/// Checks that a precision is between 0 and 15 decimal places; `None` means no rounding.
+pub fn check_precision(precision: Option<i32>) -> Result<(), ParseError> {
+    match precision {
+        Some(precision) if !(0..=MAX_PRECISION).contains(&precision) => Err(ParseError {
+            errcode: PgSqlErrorCode::ERRCODE_INVALID_PARAMETER_VALUE,
+            message: format!("invalid GPS precision: {}", precision),
+            detail: format!(
+                "Precision is the number of decimal places, from 0 to {}.",
+                MAX_PRECISION
+            ),
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// Rounds coordinates to the given number of decimal places.
+pub fn round_coordinates(gps: [f64; 2], precision: Option<i32>) -> Result<[f64; 2], ParseError> {
+    check_precision(precision)?;
+    let Some(precision) = precision else {
+        return Ok(gps);
+    };
+    let factor = 10f64.powi(precision);
+    Ok(gps.map(|v| (v * factor).round() / factor))
+}
+
+/// Converts the first two elements of a `to_address` `gps` array to checked coordinates.
+///
+/// Returns `None` if there are fewer than two numbers or the coordinates are out of range, and
+/// an error if the precision is invalid, whatever the coordinates.
+pub fn normalize_coordinates(
+    gps: &Vec<Option<AnyNumeric>>,
+    swap: bool,
+    precision: Option<i32>,
+) -> Result<Option<[f64; 2]>, ParseError> {
+    check_precision(precision)?;
+    let (Some(lat), Some(lon)) = (extract_f64_from_vec(gps, 0), extract_f64_from_vec(gps, 1))
+    else {
+        return Ok(None);
+    };
+    match check_coordinates(lat, lon, swap) {
+        Ok(gps) => round_coordinates(gps, precision).map(Some),
+        Err(_) => Ok(None),
+    }
+}
+
+/// Returns the regular expression matching one coordinate in decimal degrees,
+/// degrees and decimal minutes, or degrees, minutes and seconds.
+fn coordinate_pattern(n: usize) -> String {
+    format!(
+        concat!(
+            r#"(?P<h{n}a>[NSEW])?\s*(?P<d{n}>[+-]?\d+(?:\.\d+)?)\s*[°º˚]?\s*"#,
+            r#"(?:(?P<m{n}>\d+(?:\.\d+)?)\s*['′’]\s*)?"#,
+            r#"(?:(?P<s{n}>\d+(?:\.\d+)?)\s*(?:"|″|”|''|′′)\s*)?(?P<h{n}b>[NSEW])?"#
+        ),
+        n = n
+    )
+}
+
+/// Converts a matched coordinate to decimal degrees and its hemisphere letter, if any.
+fn coordinate(caps: &Captures, n: usize) -> Option<(f64, Option<char>)> {
+    let number = |name: String| caps.name(&name).map(|m| m.as_str().parse::<f64>());
+    let degrees = number(format!("d{n}"))?.ok()?;
+    let minutes = number(format!("m{n}")).transpose().ok()?;
+    let seconds = number(format!("s{n}")).transpose().ok()?;
+    let hemisphere = match (caps.name(&format!("h{n}a")), caps.name(&format!("h{n}b"))) {
+        (Some(_), Some(_)) => return None,
+        (a, b) => a
+            .or(b)
+            .and_then(|h| h.as_str().to_uppercase().chars().next()),
+    };
+    // Minutes and seconds need whole degrees and minutes, and are below 60
+    let whole = |v: f64| v.fract() == 0.0;
+    if minutes.is_some_and(|m| m >= 60.0 || !whole(degrees))
+        || seconds.is_some_and(|s| s >= 60.0 || !minutes.is_some_and(whole))
+    {
+        return None;
+    }
+    // A sign and a hemisphere letter contradict or duplicate each other
+    if hemisphere.is_some() && caps[format!("d{n}").as_str()].starts_with(['+', '-']) {
+        return None;
+    }
+    let magnitude = degrees.abs() + minutes.unwrap_or(0.0) / 60.0 + seconds.unwrap_or(0.0) / 3600.0;
+    let value = match hemisphere {
+        Some('S') | Some('W') => -magnitude,
+        _ => magnitude.copysign(degrees),
+    };
+    Some((value, hemisphere))
+}
+
+/// Parses a coordinate pair from text, e.g. `59°26'14"N 24°44'43"E` or `59.437,24.745`.
+///
+/// Without hemisphere letters, the latitude comes first. Values are separated by a comma,
+/// semicolon, slash or whitespace. With a semicolon separator, a comma may be used as
+/// the decimal separator (`59,437; 24,745`).
+pub fn parse_coordinates(value: &str) -> Result<[f64; 2], ParseError> {
+    let invalid = |detail: &str| {
+        ParseError::invalid(
+            format!("invalid coordinates: \"{}\"", value),
+            detail.to_string(),
+        )
+    };
+    let text = if value.contains(';') {
+        value.replace(',', ".")
+    } else {
+        value.to_string()
+    };
+    let re = Regex::new(&format!(
+        r"(?i)^\s*{}(?:\s*[,;/]\s*|\s+){}\s*$",
+        coordinate_pattern(1),
+        coordinate_pattern(2)
+    ))
+    .unwrap();
+    let caps = re
+        .captures(&text)
+        .ok_or_else(|| invalid("Expected latitude and longitude, e.g. 59.437,24.745."))?;
+    let first = coordinate(&caps, 1).ok_or_else(|| invalid("Invalid degrees or minutes."))?;
+    let second = coordinate(&caps, 2).ok_or_else(|| invalid("Invalid degrees or minutes."))?;
+    let is_lat = |h: Option<char>| h.map(|h| h == 'N' || h == 'S');
+    let (lat, lon) = match (is_lat(first.1), is_lat(second.1)) {
+        (Some(false), Some(true)) | (Some(false), None) | (None, Some(true)) => (second, first),
+        (Some(true), Some(true)) | (Some(false), Some(false)) => {
+            return Err(invalid("Both values are in the same direction."))
+        }
+        _ => (first, second),
+    };
+    check_coordinates(lat.0, lon.0, false)
+}
+
+/// Converts coordinates to a `numeric[]` as accepted by `to_address`.
+fn to_numeric_array(gps: [f64; 2]) -> Vec<AnyNumeric> {
+    gps.iter()
+        .map(|v| AnyNumeric::try_from(v.to_string().as_str()).unwrap())
+        .collect()
+}
+
+/// Parses GPS coordinates from text.
+///
+/// Accepted formats include decimal degrees (`59.437,24.745`, `59.437 24.745`,
+/// `59.437N 24.745E`), degrees and decimal minutes (`59°26.233'N 24°44.717'E`) and
+/// degrees, minutes and seconds (`59°26'14"N 24°44'43"E`). Without hemisphere letters, the
+/// latitude comes first; with letters, the order is free. A comma may be used as the decimal
+/// separator if the values are separated by a semicolon.
+///
+/// # Parameters
+/// - `value`: The text to parse.
+///
+/// # Returns
+/// - An array of latitude and longitude, to be passed as `gps` to `to_address`.
+/// - `null` if the text cannot be parsed or the coordinates are out of range.
+#[pg_extern(immutable, parallel_safe, create_or_replace)]
+pub fn parse_gps(value: &str) -> Option<Vec<AnyNumeric>> {
+    parse_coordinates(value).ok().map(to_numeric_array)
+}
+
+/// Validates and normalises GPS coordinates, as `to_address` does.
+///
+/// # Parameters
+/// - `gps`: An array of latitude and longitude. Further elements are ignored.
+/// - `swap`: Whether to swap latitude and longitude if the latitude is out of range
+///   and the longitude is not. Defaults to `false`.
+/// - `precision`: The number of decimal places to round to (0 to 15). Defaults to `null`,
+///   no rounding. 5 decimal places are about 1 m.
+///
+/// # Returns
+/// - An array of latitude and longitude.
+/// - `null` if there are fewer than two numbers or the coordinates are out of range.
+///
+/// # Errors
+/// - `22023` (`invalid_parameter_value`) if `precision` is not between 0 and 15.
+#[pg_extern(immutable, parallel_safe, create_or_replace)]
+pub fn normalize_gps(
+    gps: Vec<Option<AnyNumeric>>,
+    swap: default!(bool, false),
+    precision: default!(Option<i32>, "NULL"),
+) -> Option<Vec<AnyNumeric>> {
+    normalize_coordinates(&gps, swap, precision)
+        .unwrap_or_else(|e| e.report())
+        .map(to_numeric_array)
+}
+
+#[cfg(any(test, feature = "pg_test"))]
+#[pg_schema]
+mod tests {
+    use super::*;
+
+    fn gps(values: &[f64]) -> Vec<Option<AnyNumeric>> {
+        values
+            .iter()
+            .map(|v| Some(AnyNumeric::try_from(*v).unwrap()))
+            .collect()
+    }
+
+    fn strings(values: Option<Vec<AnyNumeric>>) -> Option<Vec<String>> {
+        values.map(|v| v.iter().map(|n| n.to_string()).collect())
+    }
+
+    /// Tests `check_coordinates`
+    #[pg_test]
+    fn test_check_coordinates() {
+        assert_eq!(
+            [59.437, 24.745],
+            check_coordinates(59.437, 24.745, false).unwrap()
+        );
+        assert_eq!(
+            [-33.9, 151.2],
+            check_coordinates(151.2, -33.9, true).unwrap()
+        );
+        assert!(check_coordinates(151.2, -33.9, false).is_err());
+        assert!(check_coordinates(500.0, 24.745, true).is_err());
+        assert!(check_coordinates(59.437, -180.5, false).is_err());
+        assert!(check_coordinates(f64::NAN, 24.745, false).is_err());
+    }
+
+    /// Tests `parse_gps`
+    #[pg_test]
+    fn test_parse_gps() {
+        let expected = Some(vec!["59.437".to_string(), "24.745".to_string()]);
+        assert_eq!(expected, strings(parse_gps("59.437,24.745")));
+        assert_eq!(expected, strings(parse_gps(" 59.437, 24.745 ")));
+        assert_eq!(expected, strings(parse_gps("59.437 24.745")));
+        assert_eq!(expected, strings(parse_gps("59,437; 24,745")));
+        assert_eq!(expected, strings(parse_gps("24.745E 59.437N")));
+        let dms = parse_coordinates("59°26'14\"N 24°44'43\"E").unwrap();
+        assert!((dms[0] - 59.437222).abs() < 0.000001);
+        assert!((dms[1] - 24.745278).abs() < 0.000001);
+        let dms = parse_coordinates("S 33° 52′ 4″, W 151° 12′ 36″").unwrap();
+        assert!((dms[0] + 33.867778).abs() < 0.000001);
+        assert!((dms[1] + 151.21).abs() < 0.000001);
+        let dm = parse_coordinates("59°26.233'N 24°44.717'E").unwrap();
+        assert!((dm[0] - 59.437217).abs() < 0.000001);
+        assert_eq!(None, parse_gps("59°61'N 24°44'E"));
+        assert_eq!(None, parse_gps("59.437N 24.745N"));
+        assert_eq!(None, parse_gps("-59.437S 24.745E"));
+        assert_eq!(None, parse_gps("95.1, 24.745"));
+        assert_eq!(None, parse_gps("59.437"));
+        assert_eq!(None, parse_gps("Tallinn"));
+    }
+
+    /// Tests `normalize_gps`
+    #[pg_test]
+    fn test_normalize_gps() {
+        assert_eq!(
+            Some(vec!["59.43722".to_string(), "24.74528".to_string()]),
+            strings(normalize_gps(gps(&[59.437222, 24.745278]), false, Some(5)))
+        );
+        assert_eq!(
+            Some(vec!["-33.9".to_string(), "151.2".to_string()]),
+            strings(normalize_gps(gps(&[151.23, -33.87]), true, Some(1)))
+        );
+        assert_eq!(None, normalize_gps(gps(&[500.0, 24.745]), true, None));
+        assert_eq!(None, normalize_gps(gps(&[59.437]), false, None));
+    }
+
+    /// Tests `normalize_gps` with an invalid precision
+    #[pg_test(error = "invalid GPS precision: 16")]
+    fn test_normalize_gps_error() {
+        normalize_gps(gps(&[59.437, 24.745]), false, Some(16));
+    }
+
+    /// Tests `normalize_gps` with an invalid precision and invalid coordinates
+    #[pg_test(error = "invalid GPS precision: -1")]
+    fn test_normalize_gps_error_invalid_coordinates() {
+        normalize_gps(gps(&[500.0, 24.745]), false, Some(-1));
+    }
+}
@@ -0,0 +1,633 @@
+//! Hashing of text and bytea with SHA-256, SHA-512, BLAKE3 and xxHash3.
+//!
+//! Hashes are returned as hex, base64, base64url or as a UUID made of the leading 128 bits of
+//! the hash, and can be checked with constant-time `*_verify_*` functions:
+//!
+//! ```sql
+//! select bfn.hash_as_text('Hello, world!', 'sha256', 'base64url');
+//! select bfn.hash_verify_text(payload, 'blake3', payload_hash) from audit_log;
+//! ```
+//!
+//! xxHash3 is fast but not cryptographic: use it for change detection and partitioning, and
+//! SHA-256, SHA-512 or BLAKE3 where the hash must not be forged.
+//!
+//! HMAC-SHA256 signatures prove that a message was produced by a holder of a secret key. All
+//! verification, also of the `md5_verify_*` functions, compares in constant time:
+//!
+//! ```sql
+//! select bfn.hmac_sha256_verify(token_id, current_setting('app.token_key'), $1) from api_token;
+//! select bfn.constant_time_equal(stored_secret, $1) from api_client where id = $2;
+//! ```
+use crate::parse_strict::ParseError;
+use base64::{engine::general_purpose, Engine as _};
+use hmac::{Hmac, Mac};
+#[allow(unused_imports)]
+use pgrx::prelude::{default, pg_extern, pg_schema, pg_test, PgSqlErrorCode};
+use sha2::{Digest, Sha256, Sha512};
+use subtle::ConstantTimeEq;
+
+/// Supported hash algorithms.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HashAlgorithm {
+    Sha256,
+    Sha512,
+    Blake3,
+    /// 64 bit xxHash3.
+    Xxh3_64,
+    /// 128 bit xxHash3.
+    Xxh3_128,
+}
+
+impl HashAlgorithm {
+    /// Parses an algorithm name: `sha256`, `sha512`, `blake3`, `xxh3_64` or `xxh3_128`.
+    pub fn parse(value: &str) -> Result<HashAlgorithm, ParseError> {
+        match value.trim().to_lowercase().as_str() {
+            "sha256" | "sha-256" => Ok(HashAlgorithm::Sha256),
+            "sha512" | "sha-512" => Ok(HashAlgorithm::Sha512),
+            "blake3" => Ok(HashAlgorithm::Blake3),
+            "xxh3" | "xxh3_64" => Ok(HashAlgorithm::Xxh3_64),
+            "xxh3_128" => Ok(HashAlgorithm::Xxh3_128),
+            _ => Err(ParseError {
+                errcode: PgSqlErrorCode::ERRCODE_INVALID_PARAMETER_VALUE,
+                message: format!("unknown hash algorithm: \"{}\"", value),
+                detail: "Supported algorithms are sha256, sha512, blake3, xxh3_64 and xxh3_128."
+                    .to_string(),
+            }),
+        }
+    }
+
+    /// Returns the name of the algorithm.
+    pub fn name(self) -> &'static str {
+        match self {
+            HashAlgorithm::Sha256 => "sha256",
+            HashAlgorithm::Sha512 => "sha512",
+            HashAlgorithm::Blake3 => "blake3",
+            HashAlgorithm::Xxh3_64 => "xxh3_64",
+            HashAlgorithm::Xxh3_128 => "xxh3_128",
+        }
+    }
+
+    /// Hashes data. xxHash3 digests are in big-endian byte order, as in their canonical form.
+    pub fn digest(self, data: &[u8]) -> Vec<u8> {
+        match self {
+            HashAlgorithm::Sha256 => Sha256::digest(data).to_vec(),
+            HashAlgorithm::Sha512 => Sha512::digest(data).to_vec(),
+            HashAlgorithm::Blake3 => blake3::hash(data).as_bytes().to_vec(),
+            HashAlgorithm::Xxh3_64 => xxhash_rust::xxh3::xxh3_64(data).to_be_bytes().to_vec(),
+            HashAlgorithm::Xxh3_128 => xxhash_rust::xxh3::xxh3_128(data).to_be_bytes().to_vec(),
+        }
+    }
+}
+
+/// Text encodings of hashes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HashEncoding {
+    /// Lower case hex; upper case is accepted when verifying.
+    Hex,
+    /// Standard base64 with padding.
+    Base64,
+    /// URL-safe base64 without padding; padding is accepted when verifying.
+    Base64Url,
+}
+
+impl HashEncoding {
+    /// Parses an encoding name: `hex`, `base64` or `base64url`.
+    pub fn parse(value: &str) -> Result<HashEncoding, ParseError> {
+        match value.trim().to_lowercase().as_str() {
+            "hex" => Ok(HashEncoding::Hex),
+            "base64" => Ok(HashEncoding::Base64),
+            "base64url" => Ok(HashEncoding::Base64Url),
+            _ => Err(ParseError {
+                errcode: PgSqlErrorCode::ERRCODE_INVALID_PARAMETER_VALUE,
+                message: format!("unknown hash encoding: \"{}\"", value),
+                detail: "Supported encodings are hex, base64 and base64url.".to_string(),
+            }),
+        }
+    }
+
+    /// Encodes a hash.
+    pub fn encode(self, hash: &[u8]) -> String {
+        match self {
+            HashEncoding::Hex => hash.iter().map(|b| format!("{:02x}", b)).collect(),
+            HashEncoding::Base64 => general_purpose::STANDARD.encode(hash),
+            HashEncoding::Base64Url => general_purpose::URL_SAFE_NO_PAD.encode(hash),
+        }
+    }
+
+    /// Decodes an encoded hash, or returns `None` if it is not valid in the encoding.
+    pub fn decode(self, value: &str) -> Option<Vec<u8>> {
+        match self {
+            HashEncoding::Hex => value
+                .as_bytes()
+                .chunks(2)
+                .map(|pair| {
+                    let digits = std::str::from_utf8(pair).ok();
+                    digits
+                        .filter(|d| d.len() == 2 && d.bytes().all(|b| b.is_ascii_hexdigit()))
+                        .and_then(|d| u8::from_str_radix(d, 16).ok())
+                })
+                .collect(),
+            HashEncoding::Base64 => general_purpose::STANDARD.decode(value).ok(),
+            HashEncoding::Base64Url => general_purpose::URL_SAFE_NO_PAD
+                .decode(value.trim_end_matches('='))
+                .ok(),
+        }
+    }
+}
+
+/// Compares two byte strings in constant time, i.e. in a time that does not depend on where
+/// they differ.
+///
+/// Only the lengths of the inputs, which are usually public, can be told from the timing.
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.ct_eq(b).into()
+}
+
+/// Builds a UUID from the leading 128 bits of a hash, with the Version 8 and variant bits set.
+fn hash_uuid(data: &[u8], algorithm: &str) -> Result<uuid::Uuid, ParseError> {
+    let algorithm = HashAlgorithm::parse(algorithm)?;
+    let hash = algorithm.digest(data);
+    let bytes: [u8; 16] = hash
+        .get(..16)
+        .and_then(|b| b.try_into().ok())
+        .ok_or_else(|| ParseError {
+            errcode: PgSqlErrorCode::ERRCODE_INVALID_PARAMETER_VALUE,
+            message: format!(
+                "hash algorithm {} is too short for a uuid",
+                algorithm.name()
+            ),
+            detail: "A uuid needs a hash of at least 128 bits.".to_string(),
+        })?;
+    Ok(uuid::Builder::from_custom_bytes(bytes).into_uuid())
+}
+
+/// Hashes data and encodes the hash as text.
+fn hash_text(data: &[u8], algorithm: &str, encoding: &str) -> Result<String, ParseError> {
+    let algorithm = HashAlgorithm::parse(algorithm)?;
+    Ok(HashEncoding::parse(encoding)?.encode(&algorithm.digest(data)))
+}
+
+/// Checks data against an encoded hash in constant time.
+fn verify_text(
+    data: &[u8],
+    algorithm: &str,
+    hash: &str,
+    encoding: &str,
+) -> Result<bool, ParseError> {
+    let algorithm = HashAlgorithm::parse(algorithm)?;
+    let expected = HashEncoding::parse(encoding)?.decode(hash);
+    Ok(expected.is_some_and(|expected| constant_time_eq(&algorithm.digest(data), &expected)))
+}
+
+/// Computes the HMAC-SHA256 of a message.
+pub fn hmac_sha256_digest(message: &[u8], key: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(message);
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Checks data against a hash in UUID form in constant time.
+fn verify_uuid(data: &[u8], algorithm: &str, hash: pgrx::Uuid) -> Result<bool, ParseError> {
+    let computed = hash_uuid(data, algorithm)?;
+    Ok(constant_time_eq(computed.as_bytes(), hash.as_bytes()))
+}
+
+/// Computes the hash of a string and encodes it as text.
+///
+/// # Parameters
+/// - `in_string`: The input string, hashed as UTF-8.
+/// - `algorithm`: `sha256`, `sha512`, `blake3`, `xxh3_64` or `xxh3_128`.
+/// - `encoding`: `hex` (default), `base64` or `base64url` (URL-safe, without padding).
+///
+/// # Returns
+/// - The encoded hash, e.g. `315f5bdb76d078c43b8ac0064e4a0164612b1fce77c869345bfc94c75894edd3`
+///   for the SHA-256 hash of `Hello, world!` in hex.
+///
+/// # Errors
+/// - If the algorithm or the encoding is unknown.
+#[pg_extern(immutable, parallel_safe, create_or_replace)]
+pub fn hash_as_text(in_string: &str, algorithm: &str, encoding: default!(&str, "'hex'")) -> String {
+    hash_text(in_string.as_bytes(), algorithm, encoding).unwrap_or_else(|e| e.report())
+}
+
+/// Computes the hash of binary data and encodes it as text.
+///
+/// # Parameters
+/// - `data`: The input data.
+/// - `algorithm`: `sha256`, `sha512`, `blake3`, `xxh3_64` or `xxh3_128`.
+/// - `encoding`: `hex` (default), `base64` or `base64url` (URL-safe, without padding).
+///
+/// # Returns
+/// - The encoded hash.
+///
+/// # Errors
+/// - If the algorithm or the encoding is unknown.
+#[pg_extern(immutable, parallel_safe, create_or_replace)]
+pub fn hash_bytea_as_text(
+    data: &[u8],
+    algorithm: &str,
+    encoding: default!(&str, "'hex'"),
+) -> String {
+    hash_text(data, algorithm, encoding).unwrap_or_else(|e| e.report())
+}
+
+/// Computes the hash of a string and converts its leading 128 bits into a UUID.
+///
+/// The version and variant bits are set as for a Version 8 UUID, so 122 bits of the hash are
+/// kept.
+///
+/// # Parameters
+/// - `in_string`: The input string, hashed as UTF-8.
+/// - `algorithm`: `sha256`, `sha512`, `blake3` or `xxh3_128`.
+///
+/// # Returns
+/// - A `uuid` object derived from the hash.
+///
+/// # Errors
+/// - If the algorithm is unknown or its hash is shorter than 128 bits (`xxh3_64`).
+#[pg_extern(immutable, parallel_safe, create_or_replace)]
+pub fn hash_as_uuid(in_string: &str, algorithm: &str) -> pgrx::Uuid {
+    let uuid = hash_uuid(in_string.as_bytes(), algorithm).unwrap_or_else(|e| e.report());
+    pgrx::Uuid::from_bytes(*uuid.as_bytes())
+}
+
+/// Computes the hash of binary data and converts its leading 128 bits into a UUID.
+///
+/// # Parameters
+/// - `data`: The input data.
+/// - `algorithm`: `sha256`, `sha512`, `blake3` or `xxh3_128`.
+///
+/// # Returns
+/// - A `uuid` object derived from the hash, as by `hash_as_uuid`.
+///
+/// # Errors
+/// - If the algorithm is unknown or its hash is shorter than 128 bits (`xxh3_64`).
+#[pg_extern(immutable, parallel_safe, create_or_replace)]
+pub fn hash_bytea_as_uuid(data: &[u8], algorithm: &str) -> pgrx::Uuid {
+    let uuid = hash_uuid(data, algorithm).unwrap_or_else(|e| e.report());
+    pgrx::Uuid::from_bytes(*uuid.as_bytes())
+}
+
+/// Verifies in constant time whether a string matches an encoded hash.
+///
+/// # Parameters
+/// - `in_string`: The input string to be hashed and verified.
+/// - `algorithm`: `sha256`, `sha512`, `blake3`, `xxh3_64` or `xxh3_128`.
+/// - `in_hash`: The expected hash, as returned by `hash_as_text`.
+/// - `encoding`: The encoding of `in_hash`: `hex` (default), `base64` or `base64url`.
+///
+/// # Returns
+/// - `true` if the hash of the string matches the provided hash.
+/// - `false` otherwise, also if the provided hash is not valid in the encoding.
+///
+/// # Errors
+/// - If the algorithm or the encoding is unknown.
+#[pg_extern(immutable, parallel_safe, create_or_replace)]
+pub fn hash_verify_text(
+    in_string: &str,
+    algorithm: &str,
+    in_hash: &str,
+    encoding: default!(&str, "'hex'"),
+) -> bool {
+    verify_text(in_string.as_bytes(), algorithm, in_hash, encoding).unwrap_or_else(|e| e.report())
+}
+
+/// Verifies in constant time whether binary data matches an encoded hash.
+///
+/// # Parameters
+/// - `data`: The input data to be hashed and verified.
+/// - `algorithm`: `sha256`, `sha512`, `blake3`, `xxh3_64` or `xxh3_128`.
+/// - `in_hash`: The expected hash, as returned by `hash_bytea_as_text`.
+/// - `encoding`: The encoding of `in_hash`: `hex` (default), `base64` or `base64url`.
+///
+/// # Returns
+/// - `true` if the hash of the data matches the provided hash.
+/// - `false` otherwise, also if the provided hash is not valid in the encoding.
+///
+/// # Errors
+/// - If the algorithm or the encoding is unknown.
+#[pg_extern(immutable, parallel_safe, create_or_replace)]
+pub fn hash_bytea_verify_text(
+    data: &[u8],
+    algorithm: &str,
+    in_hash: &str,
+    encoding: default!(&str, "'hex'"),
+) -> bool {
+    verify_text(data, algorithm, in_hash, encoding).unwrap_or_else(|e| e.report())
+}
+
+/// Verifies in constant time whether a string matches a hash in UUID form.
+///
+/// # Parameters
+/// - `in_string`: The input string to be hashed and verified.
+/// - `algorithm`: `sha256`, `sha512`, `blake3` or `xxh3_128`.
+/// - `in_uuid`: The expected UUID, as returned by `hash_as_uuid`.
+///
+/// # Returns
+/// - `true` if the UUID of the hash of the string matches the provided UUID.
+/// - `false` otherwise.
+///
+/// # Errors
+/// - If the algorithm is unknown or its hash is shorter than 128 bits (`xxh3_64`).
+#[pg_extern(immutable, parallel_safe, create_or_replace)]
+pub fn hash_verify_uuid(in_string: &str, algorithm: &str, in_uuid: pgrx::Uuid) -> bool {
+    verify_uuid(in_string.as_bytes(), algorithm, in_uuid).unwrap_or_else(|e| e.report())
+}
+
+/// Verifies in constant time whether binary data matches a hash in UUID form.
+///
+/// # Parameters
+/// - `data`: The input data to be hashed and verified.
+/// - `algorithm`: `sha256`, `sha512`, `blake3` or `xxh3_128`.
+/// - `in_uuid`: The expected UUID, as returned by `hash_bytea_as_uuid`.
+///
+/// # Returns
+/// - `true` if the UUID of the hash of the data matches the provided UUID.
+/// - `false` otherwise.
+///
+/// # Errors
+/// - If the algorithm is unknown or its hash is shorter than 128 bits (`xxh3_64`).
+#[pg_extern(immutable, parallel_safe, create_or_replace)]
+pub fn hash_bytea_verify_uuid(data: &[u8], algorithm: &str, in_uuid: pgrx::Uuid) -> bool {
+    verify_uuid(data, algorithm, in_uuid).unwrap_or_else(|e| e.report())
+}
+
+/// Compares two strings in constant time.
+///
+/// Unlike `=`, the time taken does not depend on where the strings differ, so comparing a
+/// secret, e.g. an API token, does not leak how much of it was guessed right.
+///
+/// # Parameters
+/// - `a`: The first string.
+/// - `b`: The second string.
+///
+/// # Returns
+/// - `true` if the strings are byte for byte equal, otherwise `false`.
+#[pg_extern(immutable, parallel_safe, create_or_replace)]
+pub fn constant_time_equal(a: &str, b: &str) -> bool {
+    constant_time_eq(a.as_bytes(), b.as_bytes())
+}
+
+/// Compares two byte strings in constant time.
+///
+/// # Parameters
+/// - `a`: The first byte string.
+/// - `b`: The second byte string.
+///
+/// # Returns
+/// - `true` if the byte strings are equal, otherwise `false`.
+#[pg_extern(immutable, parallel_safe, create_or_replace)]
+pub fn constant_time_equal_bytea(a: &[u8], b: &[u8]) -> bool {
+    constant_time_eq(a, b)
+}
+
+/// Signs a message with HMAC-SHA256.
+///
+/// # Parameters
+/// - `message`: The message to sign, as UTF-8.
+/// - `key`: The secret key, as UTF-8.
+/// - `encoding`: `hex` (default), `base64` or `base64url` (URL-safe, without padding).
+///
+/// # Returns
+/// - The encoded signature, e.g. `5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843`
+///   for `what do ya want for nothing?` with the key `Jefe` in hex.
+///
+/// # Errors
+/// - If the encoding is unknown.
+#[pg_extern(immutable, parallel_safe, create_or_replace)]
+pub fn hmac_sha256(message: &str, key: &str, encoding: default!(&str, "'hex'")) -> String {
+    let encoding = HashEncoding::parse(encoding).unwrap_or_else(|e| e.report());
+    encoding.encode(&hmac_sha256_digest(message.as_bytes(), key.as_bytes()))
+}
+
+/// Verifies an HMAC-SHA256 signature of a message in constant time.
+///
+/// # Parameters
+/// - `message`: The signed message, as UTF-8.
+/// - `key`: The secret key, as UTF-8.
+/// - `signature`: The signature to check, as returned by `hmac_sha256`.
+/// - `encoding`: The encoding of `signature`: `hex` (default), `base64` or `base64url`.
+///
+/// # Returns
+/// - `true` if the signature is valid for the message and the key.
+/// - `false` otherwise, also if the signature is not valid in the encoding.
+///
+/// # Errors
+/// - If the encoding is unknown.
+#[pg_extern(immutable, parallel_safe, create_or_replace)]
+pub fn hmac_sha256_verify(
+    message: &str,
+    key: &str,
+    signature: &str,
+    encoding: default!(&str, "'hex'"),
+) -> bool {
+    let encoding = HashEncoding::parse(encoding).unwrap_or_else(|e| e.report());
+    let expected = hmac_sha256_digest(message.as_bytes(), key.as_bytes());
+    encoding
+        .decode(signature)
+        .is_some_and(|signature| constant_time_eq(&expected, &signature))
+}
+
+#[cfg(any(test, feature = "pg_test"))]
+#[pg_schema]
+mod tests {
+    use super::*;
+
+    const HELLO: &str = "Hello, world!";
+
+    /// Tests `hash_as_text`
+    #[pg_test]
+    fn test_hash_as_text() {
+        for (algorithm, expected) in [
+            (
+                "sha256",
+                "315f5bdb76d078c43b8ac0064e4a0164612b1fce77c869345bfc94c75894edd3",
+            ),
+            (
+                "SHA-512",
+                concat!(
+                    "c1527cd893c124773d811911970c8fe6e857d6df5dc9226bd8a160614c0cd963",
+                    "a4ddea2b94bb7d36021ef9d865d5cea294a82dd49a0bb269f51f6e7a57f79421"
+                ),
+            ),
+            (
+                "blake3",
+                "ede5c0b10f2ec4979c69b52f61e42ff5b413519ce09be0f14d098dcfe5f6f98d",
+            ),
+        ] {
+            assert_eq!(
+                expected,
+                hash_as_text(HELLO, algorithm, "hex"),
+                "{}",
+                algorithm
+            );
+        }
+        assert_eq!(
+            "MV9b23bQeMQ7isAGTkoBZGErH853yGk0W/yUx1iU7dM=",
+            hash_as_text(HELLO, "sha256", "base64")
+        );
+        assert_eq!(
+            "MV9b23bQeMQ7isAGTkoBZGErH853yGk0W_yUx1iU7dM",
+            hash_as_text(HELLO, "sha256", "base64url")
+        );
+        assert_eq!(16, hash_as_text(HELLO, "xxh3_64", "hex").len());
+        assert_eq!(32, hash_as_text(HELLO, "xxh3_128", "hex").len());
+        assert_eq!("2d06800538d394c2", hash_as_text("", "xxh3", "hex"));
+    }
+
+    /// Tests `hash_as_text`
+    #[pg_test(error = "unknown hash algorithm: \"md4\"")]
+    fn test_hash_as_text_unknown_algorithm() {
+        hash_as_text(HELLO, "md4", "hex");
+    }
+
+    /// Tests `hash_bytea_as_text`
+    #[pg_test]
+    fn test_hash_bytea_as_text() {
+        assert_eq!(
+            hash_as_text(HELLO, "blake3", "base64"),
+            hash_bytea_as_text(HELLO.as_bytes(), "blake3", "base64")
+        );
+        assert_eq!(
+            "6e340b9cffb37a989ca544e6bb780a2c78901d3fb33738768511a30617afa01d",
+            hash_bytea_as_text(&[0], "sha256", "hex")
+        );
+    }
+
+    /// Tests `hash_as_uuid`
+    #[pg_test]
+    fn test_hash_as_uuid() {
+        let id = uuid::Uuid::from_bytes(*hash_as_uuid(HELLO, "sha256").as_bytes());
+        assert_eq!("315f5bdb-76d0-88c4-bb8a-c0064e4a0164", id.to_string());
+        assert_eq!(8, id.get_version_num());
+    }
+
+    /// Tests `hash_as_uuid`
+    #[pg_test(error = "hash algorithm xxh3_64 is too short for a uuid")]
+    fn test_hash_as_uuid_too_short() {
+        hash_as_uuid(HELLO, "xxh3_64");
+    }
+
+    /// Tests `hash_bytea_as_uuid`
+    #[pg_test]
+    fn test_hash_bytea_as_uuid() {
+        assert_eq!(
+            hash_as_uuid(HELLO, "xxh3_128"),
+            hash_bytea_as_uuid(HELLO.as_bytes(), "xxh3_128")
+        );
+    }
+
+    /// Tests `hash_verify_text`
+    #[pg_test]
+    fn test_hash_verify_text() {
+        let hex = "315f5bdb76d078c43b8ac0064e4a0164612b1fce77c869345bfc94c75894edd3";
+        assert!(hash_verify_text(HELLO, "sha256", hex, "hex"));
+        assert!(hash_verify_text(
+            HELLO,
+            "sha256",
+            &hex.to_uppercase(),
+            "hex"
+        ));
+        assert!(!hash_verify_text("Hello, world", "sha256", hex, "hex"));
+        assert!(!hash_verify_text(HELLO, "sha256", &hex[..62], "hex"));
+        assert!(!hash_verify_text(HELLO, "sha256", "not hex", "hex"));
+        let url = hash_as_text(HELLO, "sha512", "base64url");
+        assert!(hash_verify_text(HELLO, "sha512", &url, "base64url"));
+        assert!(!hash_verify_text(HELLO, "sha256", &url, "base64url"));
+    }
+
+    /// Tests `hash_bytea_verify_text`
+    #[pg_test]
+    fn test_hash_bytea_verify_text() {
+        let hash = hash_bytea_as_text(&[1, 2, 3], "xxh3_64", "base64");
+        assert!(hash_bytea_verify_text(
+            &[1, 2, 3],
+            "xxh3_64",
+            &hash,
+            "base64"
+        ));
+        assert!(!hash_bytea_verify_text(&[1, 2], "xxh3_64", &hash, "base64"));
+    }
+
+    /// Tests `hash_verify_uuid`
+    #[pg_test]
+    fn test_hash_verify_uuid() {
+        let id = hash_as_uuid(HELLO, "blake3");
+        assert!(hash_verify_uuid(HELLO, "blake3", id));
+        assert!(!hash_verify_uuid(HELLO, "sha256", id));
+    }
+
+    /// Tests `hash_bytea_verify_uuid`
+    #[pg_test]
+    fn test_hash_bytea_verify_uuid() {
+        let id = hash_bytea_as_uuid(&[1, 2, 3], "sha512");
+        assert!(hash_bytea_verify_uuid(&[1, 2, 3], "sha512", id));
+        assert!(!hash_bytea_verify_uuid(&[3, 2, 1], "sha512", id));
+    }
+
+    /// Tests `constant_time_equal`
+    #[pg_test]
+    fn test_constant_time_equal() {
+        assert!(constant_time_equal("token-123", "token-123"));
+        assert!(!constant_time_equal("token-123", "token-124"));
+        assert!(!constant_time_equal("token-123", "token-12"));
+        assert!(constant_time_equal("", ""));
+    }
+
+    /// Tests `constant_time_equal_bytea`
+    #[pg_test]
+    fn test_constant_time_equal_bytea() {
+        assert!(constant_time_equal_bytea(&[1, 2, 3], &[1, 2, 3]));
+        assert!(!constant_time_equal_bytea(&[1, 2, 3], &[1, 2, 4]));
+        assert!(!constant_time_equal_bytea(&[1, 2, 3], &[]));
+    }
+
+    /// Tests `hmac_sha256`
+    #[pg_test]
+    fn test_hmac_sha256() {
+        // RFC 4231, test case 2
+        let message = "what do ya want for nothing?";
+        assert_eq!(
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843",
+            hmac_sha256(message, "Jefe", "hex")
+        );
+        assert_eq!(
+            "W9zBRr9gdU5qBCQmCJV1x1oAPwidJzmDnexYuWTsOEM",
+            hmac_sha256(message, "Jefe", "base64url")
+        );
+        assert_ne!(
+            hmac_sha256(message, "Jefe", "hex"),
+            hmac_sha256(message, "jefe", "hex")
+        );
+    }
+
+    /// Tests `hmac_sha256_verify`
+    #[pg_test]
+    fn test_hmac_sha256_verify() {
+        let signature = hmac_sha256("token-123", "secret", "base64");
+        assert!(hmac_sha256_verify(
+            "token-123",
+            "secret",
+            &signature,
+            "base64"
+        ));
+        assert!(!hmac_sha256_verify(
+            "token-124",
+            "secret",
+            &signature,
+            "base64"
+        ));
+        assert!(!hmac_sha256_verify(
+            "token-123",
+            "Secret",
+            &signature,
+            "base64"
+        ));
+        assert!(!hmac_sha256_verify(
+            "token-123",
+            "secret",
+            &signature,
+            "hex"
+        ));
+    }
+}
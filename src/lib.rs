@@ -7,16 +7,45 @@ use base64::{engine::general_purpose, Engine as _};
 use chrono::{DateTime, Datelike, Timelike};
 #[allow(unused_imports)]
 use pgrx::prelude::{
-    pg_extern, pg_module_magic, pg_schema, pg_test, AnyNumeric, Date, Interval, Timestamp,
-    VariadicArray,
+    default, extension_sql, pg_extern, pg_guard, pg_module_magic, pg_schema, pg_test, AnyNumeric,
+    Date, Interval, PgHeapTuple, Spi, Timestamp, VariadicArray,
 };
-use pgrx::AnyElement;
+use pgrx::spi::quote_identifier;
+use pgrx::{AllocatedByRust, AnyElement};
 use rand::{rngs::ThreadRng, Rng};
 use regex::Regex;
 use serde_json::json;
 
+pub mod address;
+pub mod address_format;
+pub mod address_match;
+pub mod address_parse;
+pub mod countries;
+pub mod extract;
+pub mod geodesy;
+pub mod gps;
+pub mod hashes;
+pub mod location_codes;
+pub mod low;
+pub mod low_hierarchy;
+pub mod operations;
+pub mod parse_strict;
+pub mod passwords;
+pub mod postal_codes;
+pub mod quantity;
+pub mod tokens;
+pub mod units;
+pub mod uuids;
+pub mod waste_codes;
+
 pg_module_magic!();
 
+/// Registers the GUCs of the extension when the library is loaded.
+#[pg_guard]
+pub extern "C" fn _PG_init() {
+    passwords::init_gucs();
+}
+
 /// Returns the version number of the library.
 /// # Returns
 /// A static string representing the library version.
@@ -34,38 +63,26 @@ pub fn new_uuid() -> pgrx::Uuid {
     pgrx::Uuid::from_bytes(*uuid_v7.as_bytes())
 }
 
-/// Converts a Version 7 UUID (UUIDv7) into a timestamp.
+/// Converts a time-based UUID (v1, v6 or v7) into a timestamp in UTC.
+///
+/// See `uuid_to_tstz` for a `timestamptz` variant that also reads ULIDs.
 ///
 /// # Parameters
 /// - `uuid`: A UUID input.
 ///
 /// # Returns
 /// The timestamp if the UUID contains a valid timestamp, or `null` if:
-/// - The UUID is not Version 7.
+/// - The UUID is not Version 1, 6 or 7.
 /// - The extracted timestamp is out of the supported range.
 /// - The timestamp data is invalid.
 #[pg_extern(create_or_replace)]
 pub fn uuid_to_ts(uuid: pgrx::Uuid) -> Option<Timestamp> {
-    let bytes = uuid.as_bytes();
-    let version = (bytes[6] >> 4) & 0x0F;
-    if version != 7 {
-        return None; // Not a V7 UUID
-    }
-    let timestamp_ms = ((bytes[0] as u64) << 40)
-        | ((bytes[1] as u64) << 32)
-        | ((bytes[2] as u64) << 24)
-        | ((bytes[3] as u64) << 16)
-        | ((bytes[4] as u64) << 8)
-        | bytes[5] as u64;
-    let timestamp_secs = (timestamp_ms / 1000) as i64;
-    let timestamp_nanos = ((timestamp_ms % 1000) * 1_000_000) as u32;
+    let timestamp_micros = uuids::uuid_unix_micros(uuid.as_bytes(), false)?;
+    let timestamp_secs = timestamp_micros.div_euclid(1_000_000);
     if timestamp_secs < -62_135_596_800 || timestamp_secs > 253_402_300_799 {
         return None; // Chronos only supports years between 0000 and 9999
     }
-    let datetime_utc = match DateTime::from_timestamp(timestamp_secs, timestamp_nanos) {
-        Some(dt) => dt,
-        None => return None, // Invalid timestamp
-    };
+    let datetime_utc = DateTime::from_timestamp_micros(timestamp_micros)?;
     let seconds_with_fraction = datetime_utc.second() as f64
         + (datetime_utc.timestamp_subsec_nanos() as f64 / 1_000_000_000.0);
     Timestamp::new(
@@ -82,7 +99,7 @@ pub fn uuid_to_ts(uuid: pgrx::Uuid) -> Option<Timestamp> {
 /// Extracts a `f64` value from a given array at the specified index.
 ///
 /// Returns `None` if the index is out of bounds, the value is `None`, or cannot be parsed as `f64`.
-fn extract_f64_from_vec(vec: &Vec<Option<AnyNumeric>>, index: usize) -> Option<f64> {
+pub(crate) fn extract_f64_from_vec(vec: &Vec<Option<AnyNumeric>>, index: usize) -> Option<f64> {
     vec.get(index).and_then(|v| {
         v.as_ref().and_then(|num| {
             num.to_string().parse::<f64>().ok() // Convert AnyNumeric to f64
@@ -90,6 +107,24 @@ fn extract_f64_from_vec(vec: &Vec<Option<AnyNumeric>>, index: usize) -> Option<f
     })
 }
 
+/// Returns the schema the extension is installed in.
+pub(crate) fn extension_schema() -> Option<String> {
+    Spi::get_one::<String>(
+        "select n.nspname::text from pg_catalog.pg_extension e
+        join pg_catalog.pg_namespace n on n.oid = e.extnamespace where e.extname = 'bfn'",
+    )
+    .ok()?
+}
+
+/// Creates a value of a composite type of the extension, whatever the `search_path`.
+pub(crate) fn new_composite(type_name: &str) -> PgHeapTuple<'static, AllocatedByRust> {
+    let name = match extension_schema() {
+        Some(schema) => format!("{}.{}", quote_identifier(schema), type_name),
+        None => type_name.to_string(),
+    };
+    PgHeapTuple::new_composite_type(&name).unwrap_or_else(|e| pgrx::error!("{}", e))
+}
+
 /// Converts the given input parameters into a JSONB representation of an address.
 ///
 /// # Parameters
@@ -98,20 +133,37 @@ fn extract_f64_from_vec(vec: &Vec<Option<AnyNumeric>>, index: usize) -> Option<f
 /// - `postal_code`: An optional string representing the postal or ZIP code.
 /// - `country`: An optional string representing the country name.
 /// - `gps`: An optional vector containing two optional numeric values representing
-///    GPS coordinates (latitude and longitude). If fewer than two values are present
-///    or if the conversion to floating-point numbers fails, the GPS field
-///    will be `null`.
+///    GPS coordinates (latitude and longitude). If fewer than two values are present,
+///    if the conversion to floating-point numbers fails, or if the latitude is not between
+///    -90 and 90 or the longitude is not between -180 and 180, the GPS field will be `null`.
+///    Use `parse_gps` for coordinates given as text, e.g. `59°26'14"N 24°44'43"E`.
 /// - `adr_type`: An optional string representing the type of address (e.g., "home", "work").
+/// - `swap_gps`: Whether to swap latitude and longitude if the latitude is out of range
+///    and the longitude is not. Defaults to `false`.
+/// - `gps_precision`: The number of decimal places (0 to 15) to round the coordinates to.
+///    Defaults to `null`, no rounding.
+/// - `normalize_postal`: Whether to convert the postal code to the canonical form of the country
+///    (see `normalize_postal_code`), e.g. `sw1a1aa` to `SW1A 1AA`. Codes that are not valid in the
+///    country are kept as given. Defaults to `false`.
+/// - `country_form`: The form to convert the country to (see `normalize_country`): `alpha2`,
+///    `alpha3`, `numeric`, `name` or `native`. Countries that are not recognised are kept as given.
+///    Defaults to `null`, no conversion.
 ///
 /// # Returns
 /// A `pgrx::JsonB` object containing:
 /// - `address`: The provided street value (or `null` if not provided).
 /// - `city`: The provided city value (or `null` if not provided).
-/// - `postalCode`: The provided postal code value (or `null` if not provided).
-/// - `country`: The provided country value (or `null` if not provided).
+/// - `postalCode`: The provided postal code value, normalized if requested (or `null` if not
+///    provided).
+/// - `country`: The provided country value, normalized if requested (or `null` if not provided).
 /// - `gps`: A vector of valid latitude and longitude values as floating-point numbers
 ///    if they were successfully converted; otherwise, `null`.
 /// - `type`: The provided address type (or `null` if not provided).
+///
+/// # Errors
+/// - `22023` (`invalid_parameter_value`) if `gps_precision` is not between 0 and 15, or
+///    `country_form` is unknown.
+#[allow(clippy::too_many_arguments)]
 #[pg_extern(create_or_replace)]
 pub fn to_address(
     street: Option<&str>,
@@ -120,20 +172,30 @@ pub fn to_address(
     country: Option<&str>,
     gps: Option<Vec<Option<AnyNumeric>>>,
     adr_type: Option<&str>,
+    swap_gps: default!(bool, false),
+    gps_precision: default!(Option<i32>, "NULL"),
+    normalize_postal: default!(bool, false),
+    country_form: default!(Option<&str>, "NULL"),
 ) -> pgrx::JsonB {
-    let gps_valid = gps.and_then(|vec| {
-        if vec.len() >= 2 {
-            let first = extract_f64_from_vec(&vec, 0);
-            let second = extract_f64_from_vec(&vec, 1);
-            if let (Some(first), Some(second)) = (first, second) {
-                Some(vec![first, second]) // Valid f64 numbers in Vec
-            } else {
-                None // Conversion failed for one or both elements
-            }
-        } else {
-            None // Not enough elements in the vector
+    let postal_code = match (postal_code, country.and_then(postal_codes::postal_country)) {
+        (Some(code), Some(c)) if normalize_postal => {
+            Some(postal_codes::canonical_postal_code(code, c).unwrap_or_else(|| code.to_string()))
         }
-    });
+        (code, _) => code.map(str::to_string),
+    };
+    let country = match (country, country_form) {
+        (Some(name), Some(form)) => Some(
+            countries::normalize(name, form)
+                .unwrap_or_else(|e| e.report())
+                .unwrap_or(name)
+                .to_string(),
+        ),
+        (name, _) => name.map(str::to_string),
+    };
+    // Valid, in range coordinates; the precision is checked even without coordinates
+    let gps_valid = gps::normalize_coordinates(&gps.unwrap_or_default(), swap_gps, gps_precision)
+        .unwrap_or_else(|e| e.report())
+        .map(Vec::from);
     pgrx::JsonB(json!({
         "address": street,
         "city": city,
@@ -144,6 +206,17 @@ pub fn to_address(
     }))
 }
 
+// Version 2.0.1 had `to_address` without the optional parameters. Installing this version over it,
+// e.g. with the script copied as the upgrade script, would keep that signature as an overload and
+// make calls with six arguments ambiguous.
+extension_sql!(
+    r#"
+DROP FUNCTION IF EXISTS to_address(text, text, text, text, numeric[], text);
+"#,
+    name = "drop_to_address_2_0",
+    requires = [to_address]
+);
+
 /// Returns an array of all dates between given dates, including given dates.
 /// # Overview
 /// This function returns a vector containing every date from `start` to `end`,
@@ -392,6 +465,8 @@ pub fn upper_first(word: &str) -> Option<String> {
 /// This function produces a random string of 36 bytes, encodes it using Base64,
 /// and returns the resulting encoded string.
 ///
+/// See `random_token` for other lengths and encodings, e.g. URL-safe tokens.
+///
 /// # Returns
 /// - A string containing a random Base64-encoded value.
 #[pg_extern(create_or_replace)]
@@ -421,7 +496,9 @@ pub fn md5_as_base64(in_string: &str) -> String {
 /// Computes the MD5 hash of a given string and converts the result into a UUID.
 ///
 /// This function generates an `MD5` hash from the input string and uses the resulting
-/// hash bytes to create a valid `UUID`.
+/// hash bytes as they are, without setting the version and variant bits, so the result is
+/// not an RFC 9562 UUID. It is kept for compatibility with existing ids; use `uuid_v5`,
+/// `uuid_v3` or `uuid_v8` for name-based UUIDs that other systems can reproduce.
 ///
 /// # Parameters
 /// - `in_string`: The input string for which the MD5 hash will be computed and turned into a UUID.
@@ -438,7 +515,7 @@ pub fn md5_as_uuid(in_string: &str) -> pgrx::Uuid {
 /// Verifies whether a given string matches a specified MD5 hash encoded in Base64 format.
 ///
 /// This function computes the MD5 hash of the provided string, encodes it as Base64,
-/// and checks if it matches the provided Base64 hash.
+/// and checks if it matches the provided Base64 hash. The comparison takes constant time.
 ///
 /// # Parameters
 /// - `in_string`: The input string to be hashed and verified.
@@ -450,13 +527,13 @@ pub fn md5_as_uuid(in_string: &str) -> pgrx::Uuid {
 #[pg_extern(create_or_replace)]
 pub fn md5_verify_base64(in_string: &str, in_hash: &str) -> bool {
     let hash_of_input = md5_as_base64(in_string);
-    hash_of_input == in_hash
+    hashes::constant_time_eq(hash_of_input.as_bytes(), in_hash.as_bytes())
 }
 
 /// Verifies whether a given string matches a specified MD5 hash provided in UUID format.
 ///
 /// This function computes the MD5 hash of the input string, converts it into a UUID,
-/// and checks if it matches the provided UUID.
+/// and checks if it matches the provided UUID. The comparison takes constant time.
 ///
 /// # Parameters
 /// - `in_string`: The input string to be hashed and verified.
@@ -468,7 +545,7 @@ pub fn md5_verify_base64(in_string: &str, in_hash: &str) -> bool {
 #[pg_extern(create_or_replace)]
 pub fn md5_verify_uuid(in_string: &str, in_uuid: pgrx::Uuid) -> bool {
     let ver_uuid = md5_as_uuid(in_string);
-    ver_uuid == in_uuid
+    hashes::constant_time_eq(ver_uuid.as_bytes(), in_uuid.as_bytes())
 }
 
 /// Removes leading and trailing whitespace from the given string.
@@ -1347,6 +1424,10 @@ mod tests {
             Some("USA"),
             gps,
             Some("home"),
+            false,
+            None,
+            false,
+            None,
         );
         let expected = pgrx::JsonB(json!({
             "address": "Main St.",
@@ -1361,6 +1442,134 @@ mod tests {
         assert_eq!(result_str, expected_str);
     }
 
+    /// Tests `to_address` GPS validation, swapping and rounding
+    #[pg_test]
+    fn test_to_address_with_invalid_gps() {
+        let gps = |lat: f64, lon: f64| {
+            Some(vec![
+                Some(AnyNumeric::try_from(lat).unwrap()),
+                Some(AnyNumeric::try_from(lon).unwrap()),
+            ])
+        };
+        let result = to_address(
+            None,
+            None,
+            None,
+            None,
+            gps(500.0, 24.745),
+            None,
+            false,
+            None,
+            false,
+            None,
+        );
+        assert_eq!(json!(null), result.0["gps"]);
+        let result = to_address(
+            None,
+            None,
+            None,
+            None,
+            gps(151.2, -33.9),
+            None,
+            false,
+            None,
+            false,
+            None,
+        );
+        assert_eq!(json!(null), result.0["gps"]);
+        let result = to_address(
+            None,
+            None,
+            None,
+            None,
+            gps(151.2, -33.9),
+            None,
+            true,
+            None,
+            false,
+            None,
+        );
+        assert_eq!(json!([-33.9, 151.2]), result.0["gps"]);
+        let result = to_address(
+            None,
+            None,
+            None,
+            None,
+            gps(59.437222, 24.745278),
+            None,
+            false,
+            Some(4),
+            false,
+            None,
+        );
+        assert_eq!(json!([59.4372, 24.7453]), result.0["gps"]);
+    }
+
+    /// Tests `to_address` with an invalid GPS precision and no coordinates
+    #[pg_test(error = "invalid GPS precision: 16")]
+    fn test_to_address_gps_precision_error() {
+        to_address(
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            false,
+            Some(16),
+            false,
+            None,
+        );
+    }
+
+    /// Tests `to_address` postal code normalization
+    #[pg_test]
+    fn test_to_address_normalize_postal() {
+        let adr = |code: &str, country: &str, normalize: bool| {
+            let result = to_address(
+                None,
+                None,
+                Some(code),
+                Some(country),
+                None,
+                None,
+                false,
+                None,
+                normalize,
+                None,
+            );
+            result.0["postalCode"].clone()
+        };
+        assert_eq!(json!("SW1A 1AA"), adr("sw1a1aa", "UK", true));
+        assert_eq!(json!("sw1a1aa"), adr("sw1a1aa", "UK", false));
+        assert_eq!(json!("1234"), adr("1234", "Germany", true));
+        assert_eq!(json!("00100"), adr("00100", "Kenya", true));
+    }
+
+    /// Tests `to_address` country normalization
+    #[pg_test]
+    fn test_to_address_country_form() {
+        let country = |name: &str, form: Option<&str>| {
+            let result = to_address(
+                None,
+                None,
+                None,
+                Some(name),
+                None,
+                None,
+                false,
+                None,
+                false,
+                form,
+            );
+            result.0["country"].clone()
+        };
+        assert_eq!(json!("EE"), country("Eesti", Some("alpha2")));
+        assert_eq!(json!("Estonia"), country("EST", Some("name")));
+        assert_eq!(json!("Eesti"), country("Eesti", None));
+        assert_eq!(json!("Atlantis"), country("Atlantis", Some("alpha3")));
+    }
+
     /// Tests `uuid_to_ts`
     #[pg_test]
     fn test_uuid_to_ts_a() {
@@ -1407,6 +1616,24 @@ mod tests {
         assert!(timestamp.is_none());
     }
 
+    /// Tests `uuid_to_ts`
+    #[pg_test]
+    fn test_uuid_to_ts_d() {
+        // Version 1 and 6 UUIDs of 2022-02-22 19:22:22 UTC
+        for value in [
+            "c232ab00-9414-11ec-b3c8-9f6bdeced846",
+            "1ec9414c-232a-6b00-b3c8-9f6bdeced846",
+        ] {
+            let uuid = Uuid::parse_str(value).expect("Failed to parse UUID");
+            let ts = uuid_to_ts(pgrx::Uuid::from_bytes(*uuid.as_bytes())).unwrap();
+            assert_eq!(
+                (2022, 2, 22, 19, 22),
+                (ts.year(), ts.month(), ts.day(), ts.hour(), ts.minute())
+            );
+            assert_eq!(22.0, ts.second());
+        }
+    }
+
     //
 }
 
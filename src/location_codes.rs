@@ -0,0 +1,573 @@
+//! Geohash and Open Location Code (Plus Code) encoding of the GPS coordinates stored by
+//! `to_address`.
+//!
+//! Geohashes of nearby points share prefixes, so a btree index on a geohash column groups
+//! collection sites by proximity:
+//!
+//! ```sql
+//! select left(bfn.geohash_encode(gps, 9), 5) as cell, count(*) from site group by cell;
+//! select * from bfn.plus_code_decode('9GF6CPPW+R2');
+//! ```
+use crate::geodesy::point;
+use crate::parse_strict::ParseError;
+#[allow(unused_imports)]
+use pgrx::prelude::{default, name, pg_extern, pg_schema, pg_test, PgSqlErrorCode, TableIterator};
+
+/// Geohash base 32 alphabet.
+const GEOHASH_ALPHABET: &[u8] = b"0123456789bcdefghjkmnpqrstuvwxyz";
+
+/// Longest supported geohash.
+const GEOHASH_MAX_LENGTH: i32 = 12;
+
+/// Open Location Code alphabet.
+const OLC_ALPHABET: &[u8] = b"23456789CFGHJMPQRVWX";
+const OLC_SEPARATOR: char = '+';
+const OLC_SEPARATOR_POSITION: usize = 8;
+const OLC_PADDING: char = '0';
+/// Number of digits encoded as pairs of latitude and longitude digits.
+const OLC_PAIR_LENGTH: usize = 10;
+/// Longest code; digits after the pairs refine a 4 x 5 grid.
+const OLC_MAX_LENGTH: usize = 15;
+const OLC_GRID_COLUMNS: i64 = 4;
+const OLC_GRID_ROWS: i64 = 5;
+/// Pair precision: 1/8000 degree.
+const OLC_PAIR_PRECISION: i64 = 8000;
+/// Latitude precision of a 15 digit code: 1/(8000 * 5^5) degree.
+const OLC_FINAL_LAT_PRECISION: i64 = 8000 * 3125;
+/// Longitude precision of a 15 digit code: 1/(8000 * 4^5) degree.
+const OLC_FINAL_LNG_PRECISION: i64 = 8000 * 1024;
+
+/// Area of a geohash or Plus Code.
+#[derive(Debug, PartialEq)]
+pub struct CodeArea {
+    pub min_lat: f64,
+    pub min_lon: f64,
+    pub max_lat: f64,
+    pub max_lon: f64,
+}
+
+impl CodeArea {
+    /// Returns the center of the area.
+    pub fn center(&self) -> [f64; 2] {
+        [
+            ((self.min_lat + self.max_lat) / 2.0).min(90.0),
+            ((self.min_lon + self.max_lon) / 2.0).min(180.0),
+        ]
+    }
+
+    /// Converts the area to a row of center and bounds.
+    fn row(&self) -> (f64, f64, f64, f64, f64, f64) {
+        let [lat, lon] = self.center();
+        (
+            lat,
+            lon,
+            self.min_lat,
+            self.min_lon,
+            self.max_lat,
+            self.max_lon,
+        )
+    }
+}
+
+/// Returns the error for an invalid code.
+fn invalid_code(kind: &str, code: &str, detail: &str) -> ParseError {
+    ParseError::invalid(
+        format!("invalid {}: \"{}\"", kind, code),
+        detail.to_string(),
+    )
+}
+
+/// Encodes coordinates as a geohash of the given length (1 to 12).
+pub fn geohash(lat: f64, lon: f64, length: usize) -> String {
+    let (mut lat_range, mut lon_range) = ((-90.0, 90.0), (-180.0, 180.0));
+    let mut hash = String::with_capacity(length);
+    let (mut bits, mut value, mut even) = (0, 0usize, true);
+    while hash.len() < length {
+        let (range, v) = if even {
+            (&mut lon_range, lon)
+        } else {
+            (&mut lat_range, lat)
+        };
+        let mid = (range.0 + range.1) / 2.0;
+        value <<= 1;
+        if v >= mid {
+            value |= 1;
+            range.0 = mid;
+        } else {
+            range.1 = mid;
+        }
+        even = !even;
+        bits += 1;
+        if bits == 5 {
+            hash.push(GEOHASH_ALPHABET[value] as char);
+            (bits, value) = (0, 0);
+        }
+    }
+    hash
+}
+
+/// Decodes a geohash to its area.
+pub fn geohash_area(hash: &str) -> Result<CodeArea, ParseError> {
+    let trimmed = hash.trim().to_lowercase();
+    if trimmed.is_empty() || trimmed.len() > GEOHASH_MAX_LENGTH as usize {
+        return Err(invalid_code(
+            "geohash",
+            hash,
+            "A geohash has 1 to 12 characters.",
+        ));
+    }
+    let (mut lat_range, mut lon_range) = ((-90.0, 90.0), (-180.0, 180.0));
+    let mut even = true;
+    for c in trimmed.bytes() {
+        let value = GEOHASH_ALPHABET
+            .iter()
+            .position(|&a| a == c)
+            .ok_or_else(|| invalid_code("geohash", hash, "Unexpected character."))?;
+        for bit in (0..5).rev() {
+            let range = if even { &mut lon_range } else { &mut lat_range };
+            let mid = (range.0 + range.1) / 2.0;
+            if value >> bit & 1 == 1 {
+                range.0 = mid;
+            } else {
+                range.1 = mid;
+            }
+            even = !even;
+        }
+    }
+    Ok(CodeArea {
+        min_lat: lat_range.0,
+        min_lon: lon_range.0,
+        max_lat: lat_range.1,
+        max_lon: lon_range.1,
+    })
+}
+
+/// Returns the height in degrees of a Plus Code area of the given length.
+fn olc_lat_precision(length: usize) -> f64 {
+    if length <= OLC_PAIR_LENGTH {
+        20f64.powi(2 - (length as i32) / 2)
+    } else {
+        20f64.powi(-3) / 5f64.powi(length as i32 - OLC_PAIR_LENGTH as i32)
+    }
+}
+
+/// Normalises a longitude to -180 (inclusive) to 180 (exclusive).
+fn normalize_longitude(lon: f64) -> f64 {
+    (lon + 180.0).rem_euclid(360.0) - 180.0
+}
+
+/// Checks the number of digits of a Plus Code to encode.
+fn check_plus_code_length(length: usize) -> Result<(), ParseError> {
+    if !(2..=OLC_MAX_LENGTH).contains(&length) || (length < OLC_PAIR_LENGTH && length % 2 == 1) {
+        return Err(ParseError {
+            errcode: PgSqlErrorCode::ERRCODE_INVALID_PARAMETER_VALUE,
+            message: format!("invalid Plus Code length: {}", length),
+            detail: "Valid lengths are 2, 4, 6, 8, 10 and 11 to 15.".to_string(),
+        });
+    }
+    Ok(())
+}
+
+/// Encodes coordinates as a Plus Code with the given number of digits.
+///
+/// Valid lengths are 2, 4, 6, 8, 10 and 11 to 15.
+pub fn plus_code(lat: f64, lon: f64, length: usize) -> Result<String, ParseError> {
+    check_plus_code_length(length)?;
+    let mut lat = lat.clamp(-90.0, 90.0);
+    let lon = normalize_longitude(lon);
+    if lat == 90.0 {
+        lat -= olc_lat_precision(length);
+    }
+    let alphabet = |i: i64| OLC_ALPHABET[i as usize] as char;
+    let mut lat_val =
+        ((lat + 90.0) * OLC_FINAL_LAT_PRECISION as f64 * 1e6).round() as i64 / 1_000_000;
+    let mut lng_val =
+        ((lon + 180.0) * OLC_FINAL_LNG_PRECISION as f64 * 1e6).round() as i64 / 1_000_000;
+    let mut reversed = Vec::with_capacity(OLC_MAX_LENGTH);
+    if length > OLC_PAIR_LENGTH {
+        for _ in 0..OLC_MAX_LENGTH - OLC_PAIR_LENGTH {
+            let digit = (lat_val % OLC_GRID_ROWS) * OLC_GRID_COLUMNS + lng_val % OLC_GRID_COLUMNS;
+            reversed.push(alphabet(digit));
+            lat_val /= OLC_GRID_ROWS;
+            lng_val /= OLC_GRID_COLUMNS;
+        }
+    } else {
+        lat_val /= OLC_GRID_ROWS.pow(5);
+        lng_val /= OLC_GRID_COLUMNS.pow(5);
+    }
+    for _ in 0..OLC_PAIR_LENGTH / 2 {
+        reversed.push(alphabet(lng_val % 20));
+        reversed.push(alphabet(lat_val % 20));
+        lat_val /= 20;
+        lng_val /= 20;
+    }
+    let digits: String = reversed.iter().rev().collect();
+    if length >= OLC_SEPARATOR_POSITION {
+        Ok(format!(
+            "{}{}{}",
+            &digits[..OLC_SEPARATOR_POSITION],
+            OLC_SEPARATOR,
+            &digits[OLC_SEPARATOR_POSITION..length]
+        ))
+    } else {
+        Ok(format!(
+            "{}{}{}",
+            &digits[..length],
+            OLC_PADDING
+                .to_string()
+                .repeat(OLC_SEPARATOR_POSITION - length),
+            OLC_SEPARATOR
+        ))
+    }
+}
+
+/// Checks the syntax of a Plus Code and returns it in upper case.
+///
+/// Returns whether the code is short, i.e. has less than 8 digits before the separator.
+fn check_plus_code(code: &str) -> Result<(String, bool), ParseError> {
+    let invalid = |detail: &str| Err(invalid_code("Plus Code", code, detail));
+    let upper = code.trim().to_uppercase();
+    let Some(separator) = upper.find(OLC_SEPARATOR) else {
+        return invalid("The separator + is missing.");
+    };
+    if upper.matches(OLC_SEPARATOR).count() > 1
+        || separator > OLC_SEPARATOR_POSITION
+        || separator % 2 == 1
+    {
+        return invalid("The separator + must follow an even number of at most 8 digits.");
+    }
+    if upper.len() - separator == 2 {
+        return invalid("A single digit after the separator is not allowed.");
+    }
+    if let Some(padding) = upper.find(OLC_PADDING) {
+        let padded = upper[padding..separator].chars().all(|c| c == OLC_PADDING);
+        if padding == 0 || padding % 2 == 1 || !padded || upper.len() > separator + 1 {
+            return invalid(
+                "Padding 0 must fill whole pairs up to the separator, and end the code.",
+            );
+        }
+    }
+    if let Some(c) = upper
+        .chars()
+        .find(|c| *c != OLC_SEPARATOR && *c != OLC_PADDING && !OLC_ALPHABET.contains(&(*c as u8)))
+    {
+        return invalid(&format!("Unexpected character '{}'.", c));
+    }
+    let short = separator < OLC_SEPARATOR_POSITION;
+    if !short {
+        let value = |i: usize| OLC_ALPHABET.iter().position(|&a| a == upper.as_bytes()[i]);
+        // The first digits encode 20 degree bands of latitude (0 to 180) and longitude (0 to 360)
+        if value(0).is_some_and(|v| v * 20 >= 180) || value(1).is_some_and(|v| v * 20 >= 360) {
+            return invalid("The code is outside the range of coordinates.");
+        }
+    }
+    Ok((upper, short))
+}
+
+/// Decodes a full Plus Code to its area.
+fn plus_code_full_area(code: &str) -> CodeArea {
+    let digits: Vec<i64> = code
+        .chars()
+        .filter(|c| *c != OLC_SEPARATOR && *c != OLC_PADDING)
+        .map(|c| OLC_ALPHABET.iter().position(|&a| a as char == c).unwrap() as i64)
+        .collect();
+    let pair_digits = digits.len().min(OLC_PAIR_LENGTH);
+    let (mut normal_lat, mut normal_lng) = (-90 * OLC_PAIR_PRECISION, -180 * OLC_PAIR_PRECISION);
+    let mut place_value = 20i64.pow(4);
+    for i in (0..pair_digits).step_by(2) {
+        normal_lat += digits[i] * place_value;
+        normal_lng += digits[i + 1] * place_value;
+        if i < pair_digits - 2 {
+            place_value /= 20;
+        }
+    }
+    let mut lat_precision = place_value as f64 / OLC_PAIR_PRECISION as f64;
+    let mut lng_precision = lat_precision;
+    let (mut grid_lat, mut grid_lng) = (0i64, 0i64);
+    if digits.len() > OLC_PAIR_LENGTH {
+        let (mut row_value, mut column_value) = (OLC_GRID_ROWS.pow(4), OLC_GRID_COLUMNS.pow(4));
+        let grid_digits = digits.len().min(OLC_MAX_LENGTH);
+        for (i, digit) in digits
+            .iter()
+            .enumerate()
+            .take(grid_digits)
+            .skip(OLC_PAIR_LENGTH)
+        {
+            grid_lat += digit / OLC_GRID_COLUMNS * row_value;
+            grid_lng += digit % OLC_GRID_COLUMNS * column_value;
+            if i < grid_digits - 1 {
+                row_value /= OLC_GRID_ROWS;
+                column_value /= OLC_GRID_COLUMNS;
+            }
+        }
+        lat_precision = row_value as f64 / OLC_FINAL_LAT_PRECISION as f64;
+        lng_precision = column_value as f64 / OLC_FINAL_LNG_PRECISION as f64;
+    }
+    let min_lat = normal_lat as f64 / OLC_PAIR_PRECISION as f64
+        + grid_lat as f64 / OLC_FINAL_LAT_PRECISION as f64;
+    let min_lon = normal_lng as f64 / OLC_PAIR_PRECISION as f64
+        + grid_lng as f64 / OLC_FINAL_LNG_PRECISION as f64;
+    CodeArea {
+        min_lat,
+        min_lon,
+        max_lat: min_lat + lat_precision,
+        max_lon: min_lon + lng_precision,
+    }
+}
+
+/// Decodes a Plus Code to its area.
+///
+/// Short codes (e.g. `CWC8+R9`) are recovered to the full code nearest to `reference`.
+/// Anything after the code, such as the locality in `CWC8+R9 Mountain View`, is ignored.
+pub fn plus_code_area(code: &str, reference: Option<[f64; 2]>) -> Result<CodeArea, ParseError> {
+    let code = code.split_whitespace().next().unwrap_or_default();
+    let (code, short) = check_plus_code(code)?;
+    if !short {
+        return Ok(plus_code_full_area(&code));
+    }
+    let Some([ref_lat, ref_lon]) = reference else {
+        return Err(invalid_code(
+            "Plus Code",
+            &code,
+            "A short code needs reference coordinates.",
+        ));
+    };
+    let ref_lon = normalize_longitude(ref_lon);
+    let padding_length = OLC_SEPARATOR_POSITION - code.find(OLC_SEPARATOR).unwrap();
+    let resolution = 20f64.powi(2 - (padding_length as i32) / 2);
+    let half = resolution / 2.0;
+    let prefix = plus_code(ref_lat, ref_lon, OLC_PAIR_LENGTH)?;
+    let area = plus_code_full_area(&format!("{}{}", &prefix[..padding_length], code));
+    let [mut lat, mut lon] = area.center();
+    if ref_lat + half < lat && lat - resolution >= -90.0 {
+        lat -= resolution;
+    } else if ref_lat - half > lat && lat + resolution <= 90.0 {
+        lat += resolution;
+    }
+    if ref_lon + half < lon {
+        lon -= resolution;
+    } else if ref_lon - half > lon {
+        lon += resolution;
+    }
+    let length = code.chars().filter(|c| *c != OLC_SEPARATOR).count() + padding_length;
+    Ok(plus_code_full_area(&plus_code(lat, lon, length)?))
+}
+
+/// Encodes coordinates as a geohash.
+///
+/// # Parameters
+/// - `gps`: An array of latitude and longitude, e.g. the `gps` value of `to_address`.
+/// - `precision`: The number of characters, 1 to 12. Defaults to `9` (about 5 x 5 m).
+///   5 characters are about 5 x 5 km, 7 characters about 150 x 150 m.
+///
+/// # Returns
+/// - The geohash, e.g. `ud9d5h4v8` for Tallinn Old Town.
+/// - `null` if `gps` has fewer than two values or the coordinates are out of range.
+///
+/// # Errors
+/// - `22023` (`invalid_parameter_value`) if `precision` is not between 1 and 12.
+#[pg_extern(immutable, parallel_safe, create_or_replace)]
+pub fn geohash_encode(gps: Vec<Option<f64>>, precision: default!(i32, 9)) -> Option<String> {
+    if !(1..=GEOHASH_MAX_LENGTH).contains(&precision) {
+        ParseError {
+            errcode: PgSqlErrorCode::ERRCODE_INVALID_PARAMETER_VALUE,
+            message: format!("invalid geohash precision: {}", precision),
+            detail: "A geohash has 1 to 12 characters.".to_string(),
+        }
+        .report();
+    }
+    let [lat, lon] = point(&gps)?;
+    Some(geohash(lat, lon, precision as usize))
+}
+
+/// Decodes a geohash.
+///
+/// # Parameters
+/// - `hash`: The geohash (case-insensitive).
+///
+/// # Returns
+/// A row with the center (`lat`, `lon`) and the bounds (`min_lat`, `min_lon`, `max_lat`,
+/// `max_lon`) of the geohash cell.
+///
+/// # Errors
+/// - `22P02` (`invalid_text_representation`) if `hash` is not a geohash.
+#[pg_extern(immutable, parallel_safe, create_or_replace)]
+pub fn geohash_decode(
+    hash: &str,
+) -> TableIterator<
+    'static,
+    (
+        name!(lat, f64),
+        name!(lon, f64),
+        name!(min_lat, f64),
+        name!(min_lon, f64),
+        name!(max_lat, f64),
+        name!(max_lon, f64),
+    ),
+> {
+    let area = geohash_area(hash).unwrap_or_else(|e| e.report());
+    TableIterator::once(area.row())
+}
+
+/// Encodes coordinates as an Open Location Code (Plus Code).
+///
+/// # Parameters
+/// - `gps`: An array of latitude and longitude, e.g. the `gps` value of `to_address`.
+/// - `code_length`: The number of digits: 2, 4, 6, 8, 10 or 11 to 15. Defaults to `10`
+///   (about 14 x 14 m); 11 digits are about 3 x 3 m.
+///
+/// # Returns
+/// - The full Plus Code, e.g. `9GF6CPPW+R2`.
+/// - `null` if `gps` has fewer than two values or the coordinates are out of range.
+///
+/// # Errors
+/// - `22023` (`invalid_parameter_value`) if `code_length` is not valid.
+#[pg_extern(immutable, parallel_safe, create_or_replace)]
+pub fn plus_code_encode(gps: Vec<Option<f64>>, code_length: default!(i32, 10)) -> Option<String> {
+    let length = usize::try_from(code_length).unwrap_or(0);
+    check_plus_code_length(length).unwrap_or_else(|e| e.report());
+    let [lat, lon] = point(&gps)?;
+    Some(plus_code(lat, lon, length).unwrap_or_else(|e| e.report()))
+}
+
+/// Decodes an Open Location Code (Plus Code).
+///
+/// # Parameters
+/// - `code`: A full code (e.g. `9GF6CPPW+R2`) or a short code (e.g. `CPPW+R2`, also followed by
+///   a locality as in `CPPW+R2 Tallinn`, which is ignored).
+/// - `reference`: An array of latitude and longitude near the place, needed to recover
+///   short codes. Defaults to `null`.
+///
+/// # Returns
+/// A row with the center (`lat`, `lon`) and the bounds (`min_lat`, `min_lon`, `max_lat`,
+/// `max_lon`) of the code area.
+///
+/// # Errors
+/// - `22P02` (`invalid_text_representation`) if `code` is not a Plus Code, or a short code
+///   without `reference`.
+#[pg_extern(immutable, parallel_safe, create_or_replace)]
+pub fn plus_code_decode(
+    code: &str,
+    reference: default!(Option<Vec<Option<f64>>>, "NULL"),
+) -> TableIterator<
+    'static,
+    (
+        name!(lat, f64),
+        name!(lon, f64),
+        name!(min_lat, f64),
+        name!(min_lon, f64),
+        name!(max_lat, f64),
+        name!(max_lon, f64),
+    ),
+> {
+    let reference = reference.and_then(|r| point(&r));
+    let area = plus_code_area(code, reference).unwrap_or_else(|e| e.report());
+    TableIterator::once(area.row())
+}
+
+#[cfg(any(test, feature = "pg_test"))]
+#[pg_schema]
+mod tests {
+    use super::*;
+
+    fn gps(lat: f64, lon: f64) -> Vec<Option<f64>> {
+        vec![Some(lat), Some(lon)]
+    }
+
+    /// Tests `geohash_encode`
+    #[pg_test]
+    fn test_geohash_encode() {
+        assert_eq!(
+            Some("u4pruydqqvj".to_string()),
+            geohash_encode(gps(57.64911, 10.40744), 11)
+        );
+        assert_eq!(
+            Some("ud9d5".to_string()),
+            geohash_encode(gps(59.437, 24.745), 5)
+        );
+        assert_eq!(None, geohash_encode(gps(95.0, 24.745), 5));
+    }
+
+    /// Tests `geohash_encode` with an invalid precision
+    #[pg_test(error = "invalid geohash precision: 13")]
+    fn test_geohash_encode_error() {
+        geohash_encode(gps(59.437, 24.745), 13);
+    }
+
+    /// Tests `geohash_decode`
+    #[pg_test]
+    fn test_geohash_decode() {
+        let (lat, lon, min_lat, min_lon, max_lat, max_lon) =
+            geohash_decode("EZS42").next().unwrap();
+        assert!((lat - 42.605).abs() < 0.001 && (lon + 5.603).abs() < 0.001);
+        assert!(min_lat < lat && lat < max_lat && min_lon < lon && lon < max_lon);
+        let hash = geohash_encode(gps(59.437, 24.745), 12).unwrap();
+        let (lat, lon, ..) = geohash_decode(&hash).next().unwrap();
+        assert!((lat - 59.437).abs() < 1e-6 && (lon - 24.745).abs() < 1e-6);
+        assert!(geohash_area("ud9a").is_err());
+        assert!(geohash_area("").is_err());
+    }
+
+    /// Tests `plus_code_encode`
+    #[pg_test]
+    fn test_plus_code_encode() {
+        for (lat, lon, length, code) in [
+            (20.375, 2.775, 6, "7FG49Q00+"),
+            (20.3700625, 2.7821875, 10, "7FG49QCJ+2V"),
+            (20.3701125, 2.782234375, 11, "7FG49QCJ+2VX"),
+            (20.3701135, 2.78223535156, 13, "7FG49QCJ+2VXGJ"),
+            (47.0000625, 8.0000625, 10, "8FVC2222+22"),
+            (-41.2730625, 174.7859375, 10, "4VCPPQGP+Q9"),
+            (0.5, -179.5, 4, "62G20000+"),
+            (90.0, 1.0, 4, "CFX30000+"),
+            (1.0, 180.0, 4, "62H20000+"),
+        ] {
+            assert_eq!(
+                Some(code.to_string()),
+                plus_code_encode(gps(lat, lon), length)
+            );
+        }
+        assert_eq!(None, plus_code_encode(vec![Some(59.437)], 10));
+    }
+
+    /// Tests `plus_code_encode` with an invalid length
+    #[pg_test(error = "invalid Plus Code length: 9")]
+    fn test_plus_code_encode_error() {
+        plus_code_encode(gps(59.437, 24.745), 9);
+    }
+
+    /// Tests `plus_code_decode`
+    #[pg_test]
+    fn test_plus_code_decode() {
+        let (_, _, min_lat, min_lon, max_lat, max_lon) =
+            plus_code_decode("7fg49qcj+2v", None).next().unwrap();
+        assert!((min_lat - 20.37).abs() < 1e-9 && (max_lat - 20.370125).abs() < 1e-9);
+        assert!((min_lon - 2.782125).abs() < 1e-9 && (max_lon - 2.78225).abs() < 1e-9);
+        let code = plus_code_encode(gps(59.437, 24.745), 11).unwrap();
+        let (lat, lon, ..) = plus_code_decode(&code, None).next().unwrap();
+        assert!((lat - 59.437).abs() < 0.0001 && (lon - 24.745).abs() < 0.0001);
+        // Short code with locality, recovered near a reference
+        let reference = Some(gps(37.4, -122.1));
+        let (lat, lon, ..) = plus_code_decode("CWC8+R9 Mountain View", reference)
+            .next()
+            .unwrap();
+        let full = plus_code_area("849VCWC8+R9", None).unwrap().center();
+        assert_eq!([full[0], full[1]], [lat, lon]);
+        for code in [
+            "7FG49QCJ2V",
+            "7FG49QCJ+2",
+            "7FG4+",
+            "7FG00000+2V",
+            "7FG49QCJ+2I",
+            "ZZ000000+",
+        ] {
+            assert!(plus_code_area(code, None).is_err(), "{}", code);
+        }
+    }
+
+    /// Tests `plus_code_decode` with a short code and no reference
+    #[pg_test(error = "invalid Plus Code: \"CWC8+R9\"")]
+    fn test_plus_code_decode_error() {
+        plus_code_decode("cwc8+r9", None).next();
+    }
+}
@@ -0,0 +1,588 @@
+//! European List of Waste (LoW) catalogue embedded into the extension.
+//!
+//! The catalogue is compiled in from the `data/low` directory and indexed on first use.
+//! Codes are handled in the same compact form as returned by [`parse_low_code`],
+//! e.g. `17`, `1705`, `170504` or `170503*`.
+use crate::{new_composite, parse_low_code};
+#[allow(unused_imports)]
+use pgrx::prelude::{
+    default, extension_sql, name, pg_extern, pg_schema, pg_test, Spi, TableIterator,
+};
+use regex::Regex;
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+/// English descriptions, the catalogue itself.
+const LOW_EN: &str = include_str!("../data/low/en.tsv");
+
+/// Translated descriptions. Codes missing from a translation fall back to English.
+const LOW_TRANSLATIONS: [(&str, &str); 2] = [
+    ("de", include_str!("../data/low/de.tsv")),
+    ("et", include_str!("../data/low/et.tsv")),
+];
+
+/// Single chapter, sub-chapter or entry of the catalogue.
+pub struct LowEntry {
+    /// Code digits without spaces and hazardous marker (e.g. `170503`).
+    pub digits: String,
+    /// `true` if the entry is marked as hazardous (`*`) in the catalogue.
+    pub hazardous: bool,
+    /// Official English description.
+    pub description: &'static str,
+    /// Codes of the mirror entries (hazardous ↔ non-hazardous counterparts).
+    pub mirrors: Vec<String>,
+}
+
+impl LowEntry {
+    /// Returns the code in the form used by [`parse_low_code`], e.g. `170503*`.
+    pub fn code(&self) -> String {
+        if self.hazardous {
+            format!("{}*", self.digits)
+        } else {
+            self.digits.clone()
+        }
+    }
+
+    /// Returns `1` for chapters, `2` for sub-chapters and `3` for entries.
+    pub fn level(&self) -> i32 {
+        (self.digits.len() / 2) as i32
+    }
+
+    /// Returns the chapter code of the entry.
+    pub fn chapter(&self) -> String {
+        self.digits[..2].to_string()
+    }
+
+    /// Returns the sub-chapter code of the entry, or `None` for chapters.
+    pub fn sub_chapter(&self) -> Option<String> {
+        if self.digits.len() >= 4 {
+            Some(self.digits[..4].to_string())
+        } else {
+            None
+        }
+    }
+
+    /// Returns the hazard class of a 6-digit entry, or `None` for chapters and sub-chapters.
+    ///
+    /// - `absolute_hazardous`: Hazardous entry without a non-hazardous mirror.
+    /// - `mirror_hazardous`: Hazardous entry with a non-hazardous mirror.
+    /// - `mirror_non_hazardous`: Non-hazardous entry with a hazardous mirror.
+    /// - `non_hazardous`: Non-hazardous entry without a mirror.
+    pub fn hazard_class(&self) -> Option<&'static str> {
+        if self.level() != 3 {
+            return None;
+        }
+        Some(match (self.hazardous, self.mirrors.is_empty()) {
+            (true, true) => "absolute_hazardous",
+            (true, false) => "mirror_hazardous",
+            (false, false) => "mirror_non_hazardous",
+            (false, true) => "non_hazardous",
+        })
+    }
+
+    /// Returns the non-hazardous mirror of a hazardous entry.
+    pub fn non_hazardous_mirror(&self) -> Option<String> {
+        if !self.hazardous {
+            return None;
+        }
+        self.mirrors.first().cloned()
+    }
+}
+
+/// Indexed LoW catalogue.
+pub struct LowCatalogue {
+    /// Entries in catalogue order.
+    pub entries: Vec<LowEntry>,
+    /// Position of each entry in `entries` by code digits.
+    index: HashMap<String, usize>,
+    /// Translated descriptions by language and code digits.
+    translations: HashMap<(&'static str, String), &'static str>,
+}
+
+impl LowCatalogue {
+    /// Parses the embedded data files.
+    fn load() -> LowCatalogue {
+        let mut entries: Vec<LowEntry> = Vec::new();
+        let mut index: HashMap<String, usize> = HashMap::new();
+        for (code, description) in tsv_rows(LOW_EN) {
+            let digits = code.replace([' ', '*'], "");
+            index.insert(digits.clone(), entries.len());
+            entries.push(LowEntry {
+                digits,
+                hazardous: code.ends_with('*'),
+                description,
+                mirrors: Vec::new(),
+            });
+        }
+        let mut translations: HashMap<(&'static str, String), &'static str> = HashMap::new();
+        for (lang, data) in LOW_TRANSLATIONS {
+            for (code, description) in tsv_rows(data) {
+                translations.insert((lang, code.replace([' ', '*'], "")), description);
+            }
+        }
+        let mut catalogue = LowCatalogue {
+            entries,
+            index,
+            translations,
+        };
+        catalogue.link_mirrors();
+        catalogue
+    }
+
+    /// Links mirror entries.
+    ///
+    /// A non-hazardous entry described as "other than those mentioned in ..." mirrors
+    /// the hazardous entries it refers to.
+    fn link_mirrors(&mut self) {
+        let re_other = Regex::new(
+            r"others? than [a-z ]*?mentioned in (\d{2} \d{2} \d{2}(?:(?:,\s*|\s+and\s+|\s+or\s+|\s+to\s+)\d{2} \d{2} \d{2})*)",
+        )
+        .unwrap();
+        let re_ref = Regex::new(r"(\d{2} \d{2} \d{2})(?:\s+to\s+(\d{2} \d{2} \d{2}))?").unwrap();
+        let mut pairs: Vec<(usize, usize)> = Vec::new();
+        for (pos, entry) in self.entries.iter().enumerate() {
+            if entry.hazardous || entry.level() != 3 {
+                continue;
+            }
+            for refs in re_other.captures_iter(entry.description) {
+                for r in re_ref.captures_iter(&refs[1]) {
+                    let from = r[1].replace(' ', "");
+                    let to = r
+                        .get(2)
+                        .map_or(from.clone(), |m| m.as_str().replace(' ', ""));
+                    for (target, other) in self.entries.iter().enumerate() {
+                        if other.hazardous
+                            && other.level() == 3
+                            && other.digits >= from
+                            && other.digits <= to
+                        {
+                            pairs.push((pos, target));
+                        }
+                    }
+                }
+            }
+        }
+        for (a, b) in pairs {
+            let code_a = self.entries[a].code();
+            let code_b = self.entries[b].code();
+            if !self.entries[a].mirrors.contains(&code_b) {
+                self.entries[a].mirrors.push(code_b);
+            }
+            if !self.entries[b].mirrors.contains(&code_a) {
+                self.entries[b].mirrors.push(code_a);
+            }
+        }
+    }
+
+    /// Looks up an entry by any input accepted by [`parse_low_code`].
+    ///
+    /// A code with the hazardous marker only matches hazardous entries.
+    pub fn find(&self, value: &str) -> Option<&LowEntry> {
+        let code = parse_low_code(value)?;
+        let digits = code.trim_end_matches('*');
+        let entry = &self.entries[*self.index.get(digits)?];
+        if code.ends_with('*') && !entry.hazardous {
+            return None;
+        }
+        Some(entry)
+    }
+
+    /// Returns the description of the entry in the given language, falling back to English.
+    pub fn description(&self, entry: &LowEntry, lang: &str) -> &'static str {
+        let lang = lang.trim().to_lowercase();
+        LOW_TRANSLATIONS
+            .iter()
+            .find(|(l, _)| *l == lang)
+            .and_then(|(l, _)| self.translations.get(&(*l, entry.digits.clone())))
+            .copied()
+            .unwrap_or(entry.description)
+    }
+}
+
+/// Iterates over `code<TAB>description` rows, skipping comments and blank lines.
+pub(crate) fn tsv_rows(data: &'static str) -> impl Iterator<Item = (&'static str, &'static str)> {
+    data.lines()
+        .filter(|line| !line.trim().is_empty() && !line.starts_with('#'))
+        .filter_map(|line| line.split_once('\t'))
+        .map(|(code, description)| (code.trim(), description.trim()))
+}
+
+/// Returns the embedded LoW catalogue, parsing it on first access.
+pub fn low_catalogue_data() -> &'static LowCatalogue {
+    static CATALOGUE: OnceLock<LowCatalogue> = OnceLock::new();
+    CATALOGUE.get_or_init(LowCatalogue::load)
+}
+
+/// Returns the full European List of Waste catalogue.
+///
+/// # Parameters
+/// - `lang`: Language of the descriptions (`en`, `de`, `et`). Defaults to `en`.
+///   Descriptions without a translation are returned in English.
+///
+/// # Returns
+/// A set of rows, one for every chapter, sub-chapter and entry, in catalogue order:
+/// - `code`: The code in the form returned by `parse_low_code` (e.g. `170503*`).
+/// - `chapter`: The 2-digit chapter code.
+/// - `sub_chapter`: The 4-digit sub-chapter code, or `null` for chapters.
+/// - `level`: `1` for chapters, `2` for sub-chapters, `3` for entries.
+/// - `hazardous`: `true` if the entry is marked as hazardous.
+/// - `mirror_codes`: Codes of the mirror entries, empty if there are none.
+/// - `description`: The description in the requested language.
+#[pg_extern(create_or_replace)]
+pub fn low_catalogue(
+    lang: default!(&str, "'en'"),
+) -> TableIterator<
+    'static,
+    (
+        name!(code, String),
+        name!(chapter, String),
+        name!(sub_chapter, Option<String>),
+        name!(level, i32),
+        name!(hazardous, bool),
+        name!(mirror_codes, Vec<String>),
+        name!(description, String),
+    ),
+> {
+    let catalogue = low_catalogue_data();
+    let rows: Vec<_> = catalogue
+        .entries
+        .iter()
+        .map(|entry| {
+            (
+                entry.code(),
+                entry.chapter(),
+                entry.sub_chapter(),
+                entry.level(),
+                entry.hazardous,
+                entry.mirrors.clone(),
+                catalogue.description(entry, lang).to_string(),
+            )
+        })
+        .collect();
+    TableIterator::new(rows)
+}
+
+/// Checks whether the given value is an existing LoW code.
+///
+/// # Parameters
+/// - `value`: Any input accepted by `parse_low_code` (e.g. `17 05 04`, `170503*`).
+///
+/// # Returns
+/// - `true` if the parsed code is a chapter, sub-chapter or entry of the catalogue.
+/// - `false` if the value cannot be parsed, the code does not exist (e.g. `999999`)
+///   or the hazardous marker `*` is given for a non-hazardous entry.
+#[pg_extern(create_or_replace)]
+pub fn low_code_exists(value: &str) -> bool {
+    low_catalogue_data().find(value).is_some()
+}
+
+/// Returns the description of the given LoW code.
+///
+/// # Parameters
+/// - `value`: Any input accepted by `parse_low_code`.
+/// - `lang`: Language of the description (`en`, `de`, `et`). Defaults to `en`.
+///
+/// # Returns
+/// - The description in the requested language, or in English if there is no translation.
+/// - `null` if the code does not exist.
+#[pg_extern(create_or_replace)]
+pub fn low_code_description(value: &str, lang: default!(&str, "'en'")) -> Option<String> {
+    let catalogue = low_catalogue_data();
+    let entry = catalogue.find(value)?;
+    Some(catalogue.description(entry, lang).to_string())
+}
+
+/// Returns the catalogue details of the given LoW code as JSONB.
+///
+/// # Parameters
+/// - `value`: Any input accepted by `parse_low_code`.
+/// - `lang`: Language of the descriptions (`en`, `de`, `et`). Defaults to `en`.
+///
+/// # Returns
+/// A `pgrx::JsonB` object containing `code`, `chapter`, `subChapter`, `level`, `hazardous`,
+/// `mirrorCodes`, `description`, `chapterDescription` and `subChapterDescription`,
+/// or `null` if the code does not exist.
+#[pg_extern(create_or_replace)]
+pub fn low_code_info(value: &str, lang: default!(&str, "'en'")) -> Option<pgrx::JsonB> {
+    let catalogue = low_catalogue_data();
+    let entry = catalogue.find(value)?;
+    let chapter = entry.chapter();
+    let sub_chapter = entry.sub_chapter();
+    let chapter_description = catalogue
+        .find(&chapter)
+        .map(|e| catalogue.description(e, lang));
+    let sub_chapter_description = sub_chapter
+        .as_deref()
+        .and_then(|c| catalogue.find(c))
+        .map(|e| catalogue.description(e, lang));
+    Some(pgrx::JsonB(json!({
+        "code": entry.code(),
+        "chapter": chapter,
+        "subChapter": sub_chapter,
+        "level": entry.level(),
+        "hazardous": entry.hazardous,
+        "mirrorCodes": entry.mirrors,
+        "description": catalogue.description(entry, lang),
+        "chapterDescription": chapter_description,
+        "subChapterDescription": sub_chapter_description
+    })))
+}
+
+/// Checks whether the given LoW entry is hazardous.
+///
+/// Unlike checking for the `*` suffix kept by `parse_low_code`, the catalogue is consulted,
+/// so `170503` is recognised as hazardous as well.
+///
+/// # Parameters
+/// - `value`: Any input accepted by `parse_low_code`.
+///
+/// # Returns
+/// - `true` if the entry is hazardous (absolute or mirror hazardous).
+/// - `false` if the entry is not hazardous.
+/// - `null` if the value is not an existing 6-digit entry.
+#[pg_extern(create_or_replace)]
+pub fn low_is_hazardous(value: &str) -> Option<bool> {
+    let entry = low_catalogue_data().find(value)?;
+    entry.hazard_class().map(|_| entry.hazardous)
+}
+
+/// Checks whether the given LoW entry is absolute hazardous (hazardous without a non-hazardous mirror).
+///
+/// # Parameters
+/// - `value`: Any input accepted by `parse_low_code`.
+///
+/// # Returns
+/// - `true` if the entry is absolute hazardous, otherwise `false`.
+/// - `null` if the value is not an existing 6-digit entry.
+#[pg_extern(create_or_replace)]
+pub fn low_is_absolute_hazardous(value: &str) -> Option<bool> {
+    let class = low_catalogue_data().find(value)?.hazard_class()?;
+    Some(class == "absolute_hazardous")
+}
+
+/// Checks whether the given LoW entry is mirror hazardous (hazardous with a non-hazardous mirror).
+///
+/// # Parameters
+/// - `value`: Any input accepted by `parse_low_code`.
+///
+/// # Returns
+/// - `true` if the entry is mirror hazardous, otherwise `false`.
+/// - `null` if the value is not an existing 6-digit entry.
+#[pg_extern(create_or_replace)]
+pub fn low_is_mirror_hazardous(value: &str) -> Option<bool> {
+    let class = low_catalogue_data().find(value)?.hazard_class()?;
+    Some(class == "mirror_hazardous")
+}
+
+/// Returns the non-hazardous mirror entry of the given hazardous LoW entry.
+///
+/// # Parameters
+/// - `value`: Any input accepted by `parse_low_code`.
+///
+/// # Returns
+/// - The code of the non-hazardous mirror entry (e.g. `170504` for `170503*`).
+/// - `null` if the entry does not exist, is not hazardous or has no mirror.
+#[pg_extern(create_or_replace)]
+pub fn low_non_hazardous_mirror(value: &str) -> Option<String> {
+    low_catalogue_data().find(value)?.non_hazardous_mirror()
+}
+
+extension_sql!(
+    r#"
+CREATE TYPE low_classification AS (
+    code text,
+    chapter text,
+    sub_chapter text,
+    hazardous boolean,
+    hazard_class text,
+    non_hazardous_mirror text,
+    mirror_codes text[]
+);
+"#,
+    name = "low_classification"
+);
+
+/// Classifies the given LoW entry.
+///
+/// The result is a `low_classification` value, so single fields can be selected, e.g.
+/// `(bfn.low_classify(code)).hazardous`.
+///
+/// # Parameters
+/// - `value`: Any input accepted by `parse_low_code`.
+///
+/// # Returns
+/// - A `low_classification` with the fields:
+///   - `code`: The code in the form returned by `parse_low_code`, with the hazardous marker
+///     added where the catalogue has one.
+///   - `chapter`: The 2-digit chapter code.
+///   - `sub_chapter`: The 4-digit sub-chapter code.
+///   - `hazardous`: `true` if the entry is hazardous.
+///   - `hazard_class`: `absolute_hazardous`, `mirror_hazardous`, `mirror_non_hazardous`
+///     or `non_hazardous`.
+///   - `non_hazardous_mirror`: The non-hazardous mirror of a hazardous entry, otherwise `null`.
+///   - `mirror_codes`: Codes of all mirror entries.
+/// - `null` if the value is not an existing 6-digit entry.
+#[pg_extern(create_or_replace, requires = ["low_classification"])]
+pub fn low_classify(value: &str) -> Option<pgrx::composite_type!('static, "low_classification")> {
+    let entry = low_catalogue_data().find(value)?;
+    let hazard_class = entry.hazard_class()?;
+    let mut row = new_composite("low_classification");
+    row.set_by_name("code", entry.code()).unwrap();
+    row.set_by_name("chapter", entry.chapter()).unwrap();
+    row.set_by_name("sub_chapter", entry.sub_chapter()).unwrap();
+    row.set_by_name("hazardous", entry.hazardous).unwrap();
+    row.set_by_name("hazard_class", hazard_class.to_string())
+        .unwrap();
+    row.set_by_name("non_hazardous_mirror", entry.non_hazardous_mirror())
+        .unwrap();
+    row.set_by_name("mirror_codes", entry.mirrors.clone())
+        .unwrap();
+    Some(row)
+}
+
+#[cfg(any(test, feature = "pg_test"))]
+#[pg_schema]
+mod tests {
+    use super::*;
+
+    /// Tests `low_catalogue`
+    #[pg_test]
+    fn test_low_catalogue() {
+        let rows: Vec<_> = low_catalogue("en").collect();
+        assert_eq!(20, rows.iter().filter(|r| r.3 == 1).count());
+        assert_eq!(842, rows.iter().filter(|r| r.3 == 3).count());
+        assert_eq!(408, rows.iter().filter(|r| r.4).count());
+        let de: Vec<_> = low_catalogue("DE").collect();
+        let chapter = de.iter().find(|r| r.0 == "17").unwrap();
+        assert!(chapter.6.starts_with("BAU- UND ABBRUCHABFÄLLE"));
+    }
+
+    /// Tests `low_code_exists`
+    #[pg_test]
+    fn test_low_code_exists() {
+        assert!(low_code_exists("17 05 04"));
+        assert!(low_code_exists("17 05 03*"));
+        assert!(low_code_exists("170503"));
+        assert!(low_code_exists("1705"));
+        assert!(low_code_exists("17"));
+        assert!(!low_code_exists("17 05 04*"));
+        assert!(!low_code_exists("999999"));
+        assert!(!low_code_exists("21"));
+        assert!(!low_code_exists("bla bla"));
+    }
+
+    /// Tests `low_code_description`
+    #[pg_test]
+    fn test_low_code_description() {
+        assert_eq!(
+            Some("soil and stones other than those mentioned in 17 05 03".to_string()),
+            low_code_description("17 05 04", "en")
+        );
+        assert_eq!(
+            Some("sonstige Bau- und Abbruchabfälle".to_string()),
+            low_code_description("1709", "de")
+        );
+        // No translation, falls back to English
+        assert_eq!(
+            Some("concrete".to_string()),
+            low_code_description("170101", "et")
+        );
+        assert_eq!(None, low_code_description("999999", "en"));
+    }
+
+    /// Tests `low_code_info`
+    #[pg_test]
+    fn test_low_code_info() {
+        let info = low_code_info(" 17 05 03 * ", "en").unwrap();
+        assert_eq!(info.0["code"], "170503*");
+        assert_eq!(info.0["chapter"], "17");
+        assert_eq!(info.0["subChapter"], "1705");
+        assert_eq!(info.0["level"], 3);
+        assert_eq!(info.0["hazardous"], true);
+        assert_eq!(info.0["mirrorCodes"], json!(["170504"]));
+        let info = low_code_info("170904", "en").unwrap();
+        assert_eq!(
+            info.0["mirrorCodes"],
+            json!(["170901*", "170902*", "170903*"])
+        );
+        assert!(low_code_info("999999", "en").is_none());
+    }
+
+    /// Tests `low_is_hazardous`
+    #[pg_test]
+    fn test_low_is_hazardous() {
+        assert_eq!(Some(true), low_is_hazardous("17 05 03*"));
+        assert_eq!(Some(true), low_is_hazardous("170503"));
+        assert_eq!(Some(false), low_is_hazardous("17 05 04"));
+        assert_eq!(None, low_is_hazardous("1705"));
+        assert_eq!(None, low_is_hazardous("999999"));
+    }
+
+    /// Tests `low_is_absolute_hazardous`
+    #[pg_test]
+    fn test_low_is_absolute_hazardous() {
+        assert_eq!(Some(true), low_is_absolute_hazardous("13 02 05*"));
+        assert_eq!(Some(false), low_is_absolute_hazardous("17 05 03*"));
+        assert_eq!(Some(false), low_is_absolute_hazardous("17 05 04"));
+        assert_eq!(None, low_is_absolute_hazardous("13"));
+    }
+
+    /// Tests `low_is_mirror_hazardous`
+    #[pg_test]
+    fn test_low_is_mirror_hazardous() {
+        assert_eq!(Some(true), low_is_mirror_hazardous("17 05 03*"));
+        assert_eq!(Some(false), low_is_mirror_hazardous("13 02 05*"));
+        assert_eq!(Some(false), low_is_mirror_hazardous("17 05 04"));
+        assert_eq!(None, low_is_mirror_hazardous("999999"));
+    }
+
+    /// Tests `low_non_hazardous_mirror`
+    #[pg_test]
+    fn test_low_non_hazardous_mirror() {
+        assert_eq!(
+            Some("170504".to_string()),
+            low_non_hazardous_mirror("17 05 03*")
+        );
+        assert_eq!(
+            Some("150203".to_string()),
+            low_non_hazardous_mirror("150202")
+        );
+        assert_eq!(None, low_non_hazardous_mirror("17 05 04"));
+        assert_eq!(None, low_non_hazardous_mirror("13 02 05*"));
+    }
+
+    /// Tests `low_classify`
+    #[pg_test]
+    fn test_low_classify() {
+        let row = low_classify("170503").unwrap();
+        let field = |name: &str| row.get_by_name::<String>(name).unwrap();
+        assert_eq!(Some("170503*".to_string()), field("code"));
+        assert_eq!(Some("17".to_string()), field("chapter"));
+        assert_eq!(Some("1705".to_string()), field("sub_chapter"));
+        assert_eq!(Some(true), row.get_by_name::<bool>("hazardous").unwrap());
+        assert_eq!(Some("mirror_hazardous".to_string()), field("hazard_class"));
+        assert_eq!(Some("170504".to_string()), field("non_hazardous_mirror"));
+        assert_eq!(
+            Some(vec!["170504".to_string()]),
+            row.get_by_name::<Vec<String>>("mirror_codes").unwrap()
+        );
+        let class = |value: &str| {
+            low_classify(value)
+                .unwrap()
+                .get_by_name::<String>("hazard_class")
+                .unwrap()
+        };
+        assert_eq!(Some("mirror_non_hazardous".to_string()), class("17 05 04"));
+        assert_eq!(Some("non_hazardous".to_string()), class("200301"));
+        assert!(low_classify("1705").is_none());
+        assert!(low_classify("999999").is_none());
+    }
+
+    /// Tests `low_classify` in SQL
+    #[pg_test]
+    fn test_low_classify_sql() {
+        let hazardous = Spi::get_one::<bool>("select (low_classify('17 05 03*')).hazardous");
+        assert_eq!(Some(true), hazardous.unwrap());
+    }
+}
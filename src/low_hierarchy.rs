@@ -0,0 +1,285 @@
+//! Navigation in the LoW hierarchy of chapters (2 digits), sub-chapters (4 digits) and
+//! entries (6 digits), and rolling quantities up to a hierarchy level.
+#![allow(non_camel_case_types)]
+use crate::low::low_catalogue_data;
+use crate::parse_low_code;
+#[allow(unused_imports)]
+use pgrx::prelude::{
+    name, pg_aggregate, pg_extern, pg_schema, pg_sys, pg_test, Aggregate, AnyNumeric, PostgresType,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+
+/// Key under which `low_rollup_sum` totals quantities of codes that cannot be parsed.
+const INVALID_KEY: &str = "invalid";
+
+/// Truncates a parsed LoW code to the given level, dropping the hazardous marker above entry level.
+fn truncate(code: &str, level: usize) -> String {
+    let digits = code.trim_end_matches('*');
+    if level * 2 >= digits.len() {
+        return code.to_string();
+    }
+    digits[..level * 2].to_string()
+}
+
+/// Returns the depth of a LoW code in the hierarchy.
+///
+/// # Parameters
+/// - `value`: Any input accepted by `parse_low_code`.
+///
+/// # Returns
+/// - `1` for chapters, `2` for sub-chapters and `3` for entries.
+/// - `null` if the value cannot be parsed.
+#[pg_extern(create_or_replace)]
+pub fn low_depth(value: &str) -> Option<i32> {
+    let code = parse_low_code(value)?;
+    Some((code.trim_end_matches('*').len() / 2) as i32)
+}
+
+/// Returns the parent of a LoW code.
+///
+/// # Parameters
+/// - `value`: Any input accepted by `parse_low_code`.
+///
+/// # Returns
+/// - The sub-chapter of an entry (e.g. `1705` for `17 05 03*`) or the chapter of a sub-chapter.
+/// - `null` for chapters and values that cannot be parsed.
+#[pg_extern(create_or_replace)]
+pub fn low_parent(value: &str) -> Option<String> {
+    let code = parse_low_code(value)?;
+    let digits = code.trim_end_matches('*');
+    if digits.len() == 2 {
+        return None;
+    }
+    Some(digits[..digits.len() - 2].to_string())
+}
+
+/// Returns the ancestors of a LoW code, starting with the chapter.
+///
+/// # Parameters
+/// - `value`: Any input accepted by `parse_low_code`.
+///
+/// # Returns
+/// - The chapter and sub-chapter of an entry (e.g. `{17,1705}` for `170503*`),
+///   the chapter of a sub-chapter, an empty array for chapters.
+/// - `null` if the value cannot be parsed.
+#[pg_extern(create_or_replace)]
+pub fn low_ancestors(value: &str) -> Option<Vec<String>> {
+    let code = parse_low_code(value)?;
+    let digits = code.trim_end_matches('*');
+    Some(
+        (1..digits.len() / 2)
+            .map(|level| digits[..level * 2].to_string())
+            .collect(),
+    )
+}
+
+/// Returns the direct children of a LoW chapter or sub-chapter from the catalogue.
+///
+/// # Parameters
+/// - `value`: Any input accepted by `parse_low_code`.
+///
+/// # Returns
+/// - The sub-chapters of a chapter or the entries of a sub-chapter, in catalogue order,
+///   hazardous entries with the `*` marker.
+/// - An empty array for entries and codes not in the catalogue.
+/// - `null` if the value cannot be parsed.
+#[pg_extern(create_or_replace)]
+pub fn low_children(value: &str) -> Option<Vec<String>> {
+    let code = parse_low_code(value)?;
+    let digits = code.trim_end_matches('*');
+    Some(
+        low_catalogue_data()
+            .entries
+            .iter()
+            .filter(|e| e.digits.len() == digits.len() + 2 && e.digits.starts_with(digits))
+            .map(|e| e.code())
+            .collect(),
+    )
+}
+
+/// Rolls a LoW code up to the given hierarchy level.
+///
+/// Unlike taking a substring, the hazardous marker is handled: `17 05 03*` rolls up to `1705`
+/// and `17`, and stays `170503*` at entry level.
+///
+/// # Parameters
+/// - `value`: Any input accepted by `parse_low_code`.
+/// - `level`: `1` for chapters, `2` for sub-chapters, `3` for entries.
+///
+/// # Returns
+/// - The code truncated to `level`. Codes above `level` (e.g. a chapter rolled up to
+///   sub-chapter level) are returned unchanged.
+/// - `null` if the value cannot be parsed or `level` is not `1`, `2` or `3`.
+#[pg_extern(create_or_replace)]
+pub fn low_rollup(value: &str, level: i32) -> Option<String> {
+    if !(1..=3).contains(&level) {
+        return None;
+    }
+    Some(truncate(&parse_low_code(value)?, level as usize))
+}
+
+/// State of the `low_rollup_sum` aggregate: totals by rolled up code.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PostgresType)]
+pub struct low_rollup_state {
+    totals: BTreeMap<String, AnyNumeric>,
+}
+
+/// Aggregate summing quantities by LoW code rolled up to a hierarchy level.
+///
+/// ```sql
+/// select bfn.low_rollup_sum(low_code, tonnes, 1) from waste_report;
+/// -- {"17": 120.5, "20": 14.25}
+/// ```
+///
+/// # Parameters
+/// - `code`: Any input accepted by `parse_low_code`.
+/// - `quantity`: The quantity to add. Rows with a `null` code or quantity are skipped.
+/// - `level`: `1` for chapters, `2` for sub-chapters, `3` for entries.
+///
+/// # Returns
+/// A `pgrx::JsonB` object with the exact total quantity per rolled up code (see `low_rollup`),
+/// ordered by code. Quantities of codes that cannot be parsed are totalled under `invalid`.
+pub struct LowRollupSum;
+
+#[pg_aggregate]
+impl Aggregate for LowRollupSum {
+    const NAME: &'static str = "low_rollup_sum";
+    const INITIAL_CONDITION: Option<&'static str> = Some(r#"{"totals":{}}"#);
+    type State = low_rollup_state;
+    type Args = (
+        name!(code, String),
+        name!(quantity, AnyNumeric),
+        name!(level, i32),
+    );
+    type Finalize = pgrx::JsonB;
+
+    fn state(
+        mut current: Self::State,
+        (code, quantity, level): Self::Args,
+        _fcinfo: pg_sys::FunctionCallInfo,
+    ) -> Self::State {
+        let key = low_rollup(&code, level).unwrap_or_else(|| INVALID_KEY.to_string());
+        let total = current
+            .totals
+            .entry(key)
+            .or_insert_with(|| AnyNumeric::from(0));
+        *total += quantity;
+        current
+    }
+
+    fn finalize(
+        current: Self::State,
+        _direct_args: Self::OrderedSetArgs,
+        _fcinfo: pg_sys::FunctionCallInfo,
+    ) -> Self::Finalize {
+        let totals: Map<String, Value> = current
+            .totals
+            .into_iter()
+            .map(|(code, total)| {
+                // With `arbitrary_precision`, the number keeps the exact digits of the numeric
+                let total = serde_json::from_str(&total.to_string()).unwrap_or(Value::Null);
+                (code, total)
+            })
+            .collect();
+        pgrx::JsonB(Value::Object(totals))
+    }
+}
+
+#[cfg(any(test, feature = "pg_test"))]
+#[pg_schema]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// Tests `low_depth`
+    #[pg_test]
+    fn test_low_depth() {
+        assert_eq!(Some(1), low_depth("17"));
+        assert_eq!(Some(2), low_depth("17 05"));
+        assert_eq!(Some(3), low_depth("17 05 03*"));
+        assert_eq!(None, low_depth("175"));
+    }
+
+    /// Tests `low_parent`
+    #[pg_test]
+    fn test_low_parent() {
+        assert_eq!(Some("1705".to_string()), low_parent("17 05 03*"));
+        assert_eq!(Some("17".to_string()), low_parent("1705"));
+        assert_eq!(None, low_parent("17"));
+        assert_eq!(None, low_parent("bla"));
+    }
+
+    /// Tests `low_ancestors`
+    #[pg_test]
+    fn test_low_ancestors() {
+        assert_eq!(
+            Some(vec!["17".to_string(), "1705".to_string()]),
+            low_ancestors("170503*")
+        );
+        assert_eq!(Some(vec!["20".to_string()]), low_ancestors("2003"));
+        assert_eq!(Some(vec![]), low_ancestors("20"));
+        assert_eq!(None, low_ancestors(""));
+    }
+
+    /// Tests `low_children`
+    #[pg_test]
+    fn test_low_children() {
+        let children = low_children("1705").unwrap();
+        assert_eq!(
+            vec!["170503*", "170504", "170505*", "170506", "170507*", "170508"],
+            children
+        );
+        let children = low_children("17").unwrap();
+        assert_eq!(8, children.len());
+        assert_eq!("1701", children[0]);
+        assert_eq!(Some(vec![]), low_children("170504"));
+        assert_eq!(Some(vec![]), low_children("99"));
+        assert_eq!(None, low_children("bla"));
+    }
+
+    /// Tests `low_rollup`
+    #[pg_test]
+    fn test_low_rollup() {
+        assert_eq!(Some("17".to_string()), low_rollup("17 05 03*", 1));
+        assert_eq!(Some("1705".to_string()), low_rollup("17 05 03*", 2));
+        assert_eq!(Some("170503*".to_string()), low_rollup("17 05 03*", 3));
+        assert_eq!(Some("17".to_string()), low_rollup("17", 2));
+        assert_eq!(None, low_rollup("17 05 03*", 4));
+        assert_eq!(None, low_rollup("bla", 1));
+    }
+
+    /// Tests `low_rollup_sum`
+    #[pg_test]
+    fn test_low_rollup_sum() {
+        let fcinfo = std::ptr::null_mut();
+        let mut state = low_rollup_state::default();
+        for (code, quantity) in [
+            ("17 05 03*", 1.5),
+            ("170504", 2.0),
+            ("20 03 01", 0.25),
+            ("bla", 1.25),
+        ] {
+            let args = (code.to_string(), AnyNumeric::try_from(quantity).unwrap(), 1);
+            state = LowRollupSum::state(state, args, fcinfo);
+        }
+        let totals = LowRollupSum::finalize(state.clone(), (), fcinfo);
+        assert_eq!(json!({"17": 3.5, "20": 0.25, "invalid": 1.25}), totals.0);
+        let args = ("170101".to_string(), AnyNumeric::from(1), 2);
+        let totals = LowRollupSum::finalize(LowRollupSum::state(state, args, fcinfo), (), fcinfo);
+        assert_eq!(json!(1), totals.0["1701"]);
+        // Totals are exact, also beyond the precision of f64
+        let mut state = low_rollup_state::default();
+        for quantity in ["0.1", "0.2", "12345678901234567.891"] {
+            let args = (
+                "200301".to_string(),
+                AnyNumeric::try_from(quantity).unwrap(),
+                1,
+            );
+            state = LowRollupSum::state(state, args, fcinfo);
+        }
+        let totals = LowRollupSum::finalize(state, (), fcinfo);
+        assert_eq!(r#"{"20":12345678901234568.191}"#, totals.0.to_string());
+    }
+}
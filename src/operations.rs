@@ -0,0 +1,357 @@
+//! Disposal (Annex I) and recovery (Annex II) operations of the Waste Framework Directive.
+//!
+//! The catalogue is compiled in from the `data/operations` directory and indexed on first use.
+//! Codes are handled in the form returned by [`parse_disposal_code`] and [`parse_recovery_code`]
+//! without leading zeros, e.g. `D1`, `R13`. National sub-codes such as `R1.1` or `D10.2`
+//! are accepted for every existing operation and share the description of their parent.
+use crate::low::tsv_rows;
+use crate::{parse_disposal_code, parse_recovery_code};
+#[allow(unused_imports)]
+use pgrx::prelude::{default, name, pg_extern, pg_schema, pg_test, TableIterator};
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+/// English descriptions, the catalogue itself.
+const OPERATIONS_EN: &str = include_str!("../data/operations/en.tsv");
+
+/// Translated descriptions. Codes missing from a translation fall back to English.
+const OPERATIONS_TRANSLATIONS: [(&str, &str); 2] = [
+    ("de", include_str!("../data/operations/de.tsv")),
+    ("et", include_str!("../data/operations/et.tsv")),
+];
+
+/// Single disposal or recovery operation of the catalogue.
+pub struct WasteOperation {
+    /// Operation code (e.g. `D1`, `R13`).
+    pub code: &'static str,
+    /// Official English description.
+    pub description: &'static str,
+}
+
+impl WasteOperation {
+    /// Returns `disposal` for D codes and `recovery` for R codes.
+    pub fn operation_type(&self) -> &'static str {
+        if self.code.starts_with('D') {
+            "disposal"
+        } else {
+            "recovery"
+        }
+    }
+
+    /// Returns the annex of the Waste Framework Directive listing the operation.
+    pub fn annex(&self) -> &'static str {
+        if self.code.starts_with('D') {
+            "I"
+        } else {
+            "II"
+        }
+    }
+}
+
+/// Operation code resolved against the catalogue.
+pub struct OperationCode {
+    /// Normalised code, including the national sub-code if given (e.g. `R1.1`).
+    pub code: String,
+    /// National sub-code without the operation (e.g. `1` for `R1.1`).
+    pub sub_code: Option<String>,
+    /// The catalogue operation, the parent of a national sub-code.
+    pub operation: &'static WasteOperation,
+}
+
+/// Indexed operations catalogue.
+pub struct OperationCatalogue {
+    /// Operations in catalogue order, D1–D15 followed by R1–R13.
+    pub operations: Vec<WasteOperation>,
+    /// Position of each operation in `operations` by code.
+    index: HashMap<&'static str, usize>,
+    /// Translated descriptions by language and code.
+    translations: HashMap<(&'static str, &'static str), &'static str>,
+}
+
+impl OperationCatalogue {
+    /// Parses the embedded data files.
+    fn load() -> OperationCatalogue {
+        let mut operations: Vec<WasteOperation> = Vec::new();
+        let mut index: HashMap<&'static str, usize> = HashMap::new();
+        for (code, description) in tsv_rows(OPERATIONS_EN) {
+            index.insert(code, operations.len());
+            operations.push(WasteOperation { code, description });
+        }
+        let mut translations: HashMap<(&'static str, &'static str), &'static str> = HashMap::new();
+        for (lang, data) in OPERATIONS_TRANSLATIONS {
+            for (code, description) in tsv_rows(data) {
+                translations.insert((lang, code), description);
+            }
+        }
+        OperationCatalogue {
+            operations,
+            index,
+            translations,
+        }
+    }
+
+    /// Looks up an operation by any input accepted by [`parse_disposal_code`]
+    /// or [`parse_recovery_code`].
+    ///
+    /// Leading zeros are dropped (`D01` resolves to `D1`). A national sub-code resolves
+    /// to its parent operation, which has to exist.
+    pub fn find(&'static self, value: &str) -> Option<OperationCode> {
+        let code = parse_disposal_code(value).or_else(|| parse_recovery_code(value))?;
+        let (number, sub_code) = split_operation_code(&code)?;
+        let base = format!("{}{}", &code[..1], number);
+        let operation = &self.operations[*self.index.get(base.as_str())?];
+        let code = match &sub_code {
+            Some(sub_code) => format!("{}.{}", base, sub_code),
+            None => base,
+        };
+        Some(OperationCode {
+            code,
+            sub_code,
+            operation,
+        })
+    }
+
+    /// Returns the description of the operation in the given language, falling back to English.
+    pub fn description(&self, operation: &WasteOperation, lang: &str) -> &'static str {
+        let lang = lang.trim().to_lowercase();
+        OPERATIONS_TRANSLATIONS
+            .iter()
+            .find(|(l, _)| *l == lang)
+            .and_then(|(l, _)| self.translations.get(&(*l, operation.code)))
+            .copied()
+            .unwrap_or(operation.description)
+    }
+}
+
+/// Splits a code of `parse_disposal_code` or `parse_recovery_code` (e.g. `D10.02`) into the
+/// operation number without leading zeros and the national sub-code as written.
+pub fn split_operation_code(code: &str) -> Option<(u8, Option<String>)> {
+    let (number, sub_code) = match code[1..].split_once('.') {
+        Some((number, sub_code)) => (number, Some(sub_code.to_string())),
+        None => (&code[1..], None),
+    };
+    Some((number.parse::<u8>().ok()?, sub_code))
+}
+
+/// Returns the embedded operations catalogue, parsing it on first access.
+pub fn operation_catalogue_data() -> &'static OperationCatalogue {
+    static CATALOGUE: OnceLock<OperationCatalogue> = OnceLock::new();
+    CATALOGUE.get_or_init(OperationCatalogue::load)
+}
+
+/// Returns the full catalogue of disposal (D1–D15) and recovery (R1–R13) operations.
+///
+/// # Parameters
+/// - `lang`: Language of the descriptions (`en`, `de`, `et`). Defaults to `en`.
+///   Descriptions without a translation are returned in English.
+///
+/// # Returns
+/// A set of rows, one for every operation, disposal operations first:
+/// - `code`: The operation code (e.g. `D1`, `R13`).
+/// - `operation_type`: `disposal` or `recovery`.
+/// - `annex`: `I` for disposal and `II` for recovery operations.
+/// - `description`: The description in the requested language.
+#[pg_extern(create_or_replace)]
+pub fn waste_operations(
+    lang: default!(&str, "'en'"),
+) -> TableIterator<
+    'static,
+    (
+        name!(code, String),
+        name!(operation_type, String),
+        name!(annex, String),
+        name!(description, String),
+    ),
+> {
+    let catalogue = operation_catalogue_data();
+    let rows: Vec<_> = catalogue
+        .operations
+        .iter()
+        .map(|operation| {
+            (
+                operation.code.to_string(),
+                operation.operation_type().to_string(),
+                operation.annex().to_string(),
+                catalogue.description(operation, lang).to_string(),
+            )
+        })
+        .collect();
+    TableIterator::new(rows)
+}
+
+/// Checks whether the given value is an existing disposal operation code.
+///
+/// Unlike `parse_disposal_code`, which only checks the format, the catalogue is consulted.
+///
+/// # Parameters
+/// - `value`: Any input accepted by `parse_disposal_code` (e.g. `D1`, `d10`, `D10.2`).
+///
+/// # Returns
+/// - `true` if the code is one of D1–D15, or a national sub-code of one of them.
+/// - `false` if the value cannot be parsed or the operation does not exist (e.g. `D99`).
+#[pg_extern(create_or_replace)]
+pub fn disposal_code_exists(value: &str) -> bool {
+    operation_catalogue_data()
+        .find(value)
+        .is_some_and(|c| c.operation.operation_type() == "disposal")
+}
+
+/// Checks whether the given value is an existing recovery operation code.
+///
+/// Unlike `parse_recovery_code`, which only checks the format, the catalogue is consulted.
+///
+/// # Parameters
+/// - `value`: Any input accepted by `parse_recovery_code` (e.g. `R1`, `r5`, `R1.1`).
+///
+/// # Returns
+/// - `true` if the code is one of R1–R13, or a national sub-code of one of them.
+/// - `false` if the value cannot be parsed or the operation does not exist (e.g. `R45`).
+#[pg_extern(create_or_replace)]
+pub fn recovery_code_exists(value: &str) -> bool {
+    operation_catalogue_data()
+        .find(value)
+        .is_some_and(|c| c.operation.operation_type() == "recovery")
+}
+
+/// Returns the validated and normalised disposal or recovery operation code.
+///
+/// # Parameters
+/// - `value`: Any input accepted by `parse_disposal_code` or `parse_recovery_code`.
+///
+/// # Returns
+/// - The code without leading zeros (e.g. `D1` for ` d01 `, `R1.1` for `r01.1`).
+/// - `null` if the value cannot be parsed or the operation does not exist.
+#[pg_extern(create_or_replace)]
+pub fn waste_operation_code(value: &str) -> Option<String> {
+    Some(operation_catalogue_data().find(value)?.code)
+}
+
+/// Returns the description of the given disposal or recovery operation.
+///
+/// # Parameters
+/// - `value`: Any input accepted by `parse_disposal_code` or `parse_recovery_code`.
+/// - `lang`: Language of the description (`en`, `de`, `et`). Defaults to `en`.
+///
+/// # Returns
+/// - The description in the requested language, or in English if there is no translation.
+///   National sub-codes return the description of their parent operation.
+/// - `null` if the operation does not exist.
+#[pg_extern(create_or_replace)]
+pub fn waste_operation_description(value: &str, lang: default!(&str, "'en'")) -> Option<String> {
+    let catalogue = operation_catalogue_data();
+    let code = catalogue.find(value)?;
+    Some(catalogue.description(code.operation, lang).to_string())
+}
+
+/// Returns the catalogue details of the given disposal or recovery operation as JSONB.
+///
+/// # Parameters
+/// - `value`: Any input accepted by `parse_disposal_code` or `parse_recovery_code`.
+/// - `lang`: Language of the description (`en`, `de`, `et`). Defaults to `en`.
+///
+/// # Returns
+/// A `pgrx::JsonB` object containing `code`, `operation`, `subCode`, `operationType`,
+/// `annex` and `description`, or `null` if the operation does not exist.
+/// `operation` is the Annex code, which differs from `code` for national sub-codes.
+#[pg_extern(create_or_replace)]
+pub fn waste_operation_info(value: &str, lang: default!(&str, "'en'")) -> Option<pgrx::JsonB> {
+    let catalogue = operation_catalogue_data();
+    let code = catalogue.find(value)?;
+    Some(pgrx::JsonB(json!({
+        "code": code.code,
+        "operation": code.operation.code,
+        "subCode": code.sub_code,
+        "operationType": code.operation.operation_type(),
+        "annex": code.operation.annex(),
+        "description": catalogue.description(code.operation, lang)
+    })))
+}
+
+#[cfg(any(test, feature = "pg_test"))]
+#[pg_schema]
+mod tests {
+    use super::*;
+
+    /// Tests `waste_operations`
+    #[pg_test]
+    fn test_waste_operations() {
+        let rows: Vec<_> = waste_operations("en").collect();
+        assert_eq!(28, rows.len());
+        assert_eq!(15, rows.iter().filter(|r| r.1 == "disposal").count());
+        assert_eq!(13, rows.iter().filter(|r| r.2 == "II").count());
+        assert_eq!("D1", rows[0].0);
+        assert_eq!("R13", rows[27].0);
+        let de: Vec<_> = waste_operations("DE").collect();
+        assert_eq!("Verbrennung an Land", de[9].3);
+    }
+
+    /// Tests `disposal_code_exists`
+    #[pg_test]
+    fn test_disposal_code_exists() {
+        assert!(disposal_code_exists("D1"));
+        assert!(disposal_code_exists(" d15 "));
+        assert!(disposal_code_exists("D01"));
+        assert!(disposal_code_exists("D10.2"));
+        assert!(!disposal_code_exists("D0"));
+        assert!(!disposal_code_exists("D16"));
+        assert!(!disposal_code_exists("D99"));
+        assert!(!disposal_code_exists("D99.1"));
+        assert!(!disposal_code_exists("R1"));
+        assert!(!disposal_code_exists("bla bla"));
+    }
+
+    /// Tests `recovery_code_exists`
+    #[pg_test]
+    fn test_recovery_code_exists() {
+        assert!(recovery_code_exists("R1"));
+        assert!(recovery_code_exists("r13"));
+        assert!(recovery_code_exists("R1.1"));
+        assert!(!recovery_code_exists("R14"));
+        assert!(!recovery_code_exists("R45"));
+        assert!(!recovery_code_exists("D1"));
+    }
+
+    /// Tests `waste_operation_code`
+    #[pg_test]
+    fn test_waste_operation_code() {
+        assert_eq!(Some("D1".to_string()), waste_operation_code(" d01 "));
+        assert_eq!(Some("R1.1".to_string()), waste_operation_code("r01.1"));
+        assert_eq!(Some("R13".to_string()), waste_operation_code("R13"));
+        assert_eq!(None, waste_operation_code("D99"));
+        assert_eq!(None, waste_operation_code("X1"));
+    }
+
+    /// Tests `waste_operation_description`
+    #[pg_test]
+    fn test_waste_operation_description() {
+        assert_eq!(
+            Some("Incineration on land".to_string()),
+            waste_operation_description("D10", "en")
+        );
+        assert_eq!(
+            Some("Regeneration of acids or bases".to_string()),
+            waste_operation_description("R6", "fr")
+        );
+        assert_eq!(
+            Some("Põletamine maismaal".to_string()),
+            waste_operation_description("D10.1", "et")
+        );
+        assert_eq!(None, waste_operation_description("R45", "en"));
+    }
+
+    /// Tests `waste_operation_info`
+    #[pg_test]
+    fn test_waste_operation_info() {
+        let info = waste_operation_info("r1.1", "en").unwrap();
+        assert_eq!(info.0["code"], "R1.1");
+        assert_eq!(info.0["operation"], "R1");
+        assert_eq!(info.0["subCode"], "1");
+        assert_eq!(info.0["operationType"], "recovery");
+        assert_eq!(info.0["annex"], "II");
+        let info = waste_operation_info("D5", "en").unwrap();
+        assert_eq!(info.0["subCode"], serde_json::Value::Null);
+        assert_eq!(info.0["annex"], "I");
+        assert!(waste_operation_info("D99", "en").is_none());
+    }
+}
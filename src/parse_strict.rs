@@ -0,0 +1,458 @@
+//! Error-reporting variants of the `parse_*` functions.
+//!
+//! The `*_strict` functions raise a PostgreSQL error with a specific SQLSTATE and a detail
+//! message explaining why the input was rejected. The `try_parse_*` functions return the
+//! explanation as a value instead, so import jobs can report rejected rows without aborting.
+use crate::{new_composite, parse_disposal_code, parse_low_code, parse_recovery_code};
+#[allow(unused_imports)]
+use pgrx::prelude::{
+    ereport, extension_sql, pg_extern, pg_schema, pg_test, IntoDatum, PgHeapTuple, PgSqlErrorCode,
+    Spi,
+};
+use pgrx::AllocatedByRust;
+
+/// Reason why a value was rejected.
+#[derive(Debug)]
+pub struct ParseError {
+    /// SQLSTATE reported by the strict functions.
+    pub errcode: PgSqlErrorCode,
+    /// Primary error message, naming the rejected value.
+    pub message: String,
+    /// Explanation why the value was rejected.
+    pub detail: String,
+}
+
+impl ParseError {
+    /// Creates an `invalid_text_representation` (`22P02`) error.
+    pub fn invalid(message: String, detail: String) -> ParseError {
+        ParseError {
+            errcode: PgSqlErrorCode::ERRCODE_INVALID_TEXT_REPRESENTATION,
+            message,
+            detail,
+        }
+    }
+
+    /// Raises the error in PostgreSQL.
+    pub fn report(self) -> ! {
+        ereport!(ERROR, self.errcode, self.message, self.detail);
+    }
+}
+
+/// Unwraps the parsed value or raises the error.
+fn unwrap_or_report<T>(result: Result<T, ParseError>) -> T {
+    result.unwrap_or_else(|e| e.report())
+}
+
+/// Converts the parse result into a `(value, error)` value of a composite type.
+fn into_result<T: IntoDatum>(
+    type_name: &str,
+    result: Result<T, ParseError>,
+) -> PgHeapTuple<'static, AllocatedByRust> {
+    let (value, error) = match result {
+        Ok(value) => (Some(value), None),
+        Err(e) => (None, Some(e.detail)),
+    };
+    let mut row = new_composite(type_name);
+    row.set_by_name("value", value).unwrap();
+    row.set_by_name("error", error).unwrap();
+    row
+}
+
+/// Checks a LoW code, explaining why it was rejected.
+pub fn check_low_code(value: &str) -> Result<String, ParseError> {
+    let invalid = |detail: String| {
+        Err(ParseError::invalid(
+            format!("invalid LoW code: \"{}\"", value),
+            detail,
+        ))
+    };
+    if let Some(c) = value
+        .chars()
+        .find(|c| !c.is_ascii_digit() && !c.is_whitespace() && !['*', '.', '-'].contains(c))
+    {
+        return invalid(format!(
+            "Unexpected character '{}'. A LoW code may only contain digits, spaces, dots, dashes and the hazardous marker *.",
+            c
+        ));
+    }
+    let digits = value.chars().filter(|c| c.is_ascii_digit()).count();
+    if digits == 0 {
+        return invalid("The value contains no digits.".to_string());
+    }
+    if ![2, 4, 6].contains(&digits) {
+        return invalid(format!(
+            "The code has {} digits. Chapters have 2, sub-chapters 4 and entries 6 digits.",
+            digits
+        ));
+    }
+    let marked = value.trim_end().strip_suffix('*').unwrap_or(value);
+    if marked.contains('*') || (digits != 6 && value.contains('*')) {
+        return invalid(
+            "The hazardous marker * is only allowed once, at the end of a 6-digit entry."
+                .to_string(),
+        );
+    }
+    parse_low_code(value).map_or_else(|| invalid("The code cannot be parsed.".to_string()), Ok)
+}
+
+/// Checks a disposal, recovery or LoW code, explaining why it was rejected.
+pub fn check_env_code(value: &str, code_type: &str) -> Result<String, ParseError> {
+    let c_type = code_type.to_lowercase();
+    let (name, parsed, format) = match c_type.as_str() {
+        "lowcode" => return check_low_code(value),
+        "disposalcode" => (
+            "disposal code",
+            parse_disposal_code(value),
+            "the letter D followed by 1 or 2 digits and an optional sub-code, e.g. D10 or D10.1",
+        ),
+        "recoverycode" => (
+            "recovery code",
+            parse_recovery_code(value),
+            "the letter R followed by 1 or 2 digits and an optional sub-code, e.g. R1 or R1.1",
+        ),
+        _ => {
+            return Err(ParseError {
+                errcode: PgSqlErrorCode::ERRCODE_INVALID_PARAMETER_VALUE,
+                message: format!("invalid code type: \"{}\"", code_type),
+                detail: "The code type must be disposalcode, recoverycode or lowcode.".to_string(),
+            })
+        }
+    };
+    parsed.ok_or_else(|| {
+        let detail = if value.trim().is_empty() {
+            "The value is empty.".to_string()
+        } else {
+            format!("A {} consists of {}.", name, format)
+        };
+        ParseError::invalid(format!("invalid {}: \"{}\"", name, value), detail)
+    })
+}
+
+/// Checks a 64-bit integer, explaining why it was rejected.
+pub fn check_i64(value: &str) -> Result<i64, ParseError> {
+    let san = value.trim();
+    let message = format!("invalid integer: \"{}\"", value);
+    if san.is_empty() {
+        return Err(ParseError::invalid(
+            message,
+            "The value is empty.".to_string(),
+        ));
+    }
+    let digits = san.strip_prefix(['+', '-']).unwrap_or(san);
+    if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return Err(ParseError::invalid(
+            message,
+            "Only digits with an optional leading sign are allowed.".to_string(),
+        ));
+    }
+    san.parse::<i64>().map_err(|_| ParseError {
+        errcode: PgSqlErrorCode::ERRCODE_NUMERIC_VALUE_OUT_OF_RANGE,
+        message: format!("value \"{}\" is out of range for type bigint", value),
+        detail: "The value is outside the range of a 64-bit integer.".to_string(),
+    })
+}
+
+/// Checks a boolean, explaining why it was rejected.
+pub fn check_bool(value: &str) -> Result<bool, ParseError> {
+    let truthy = [
+        "1", "+", "+1", "t", "tr", "tru", "true", "y", "ye", "yes", "on",
+    ];
+    let falsy = [
+        "0", "-", "-1", "f", "fa", "fal", "fals", "false", "n", "no", "off",
+    ];
+    let san = value.trim().to_lowercase();
+    if truthy.contains(&san.as_str()) {
+        return Ok(true);
+    }
+    if falsy.contains(&san.as_str()) {
+        return Ok(false);
+    }
+    let detail = if san.is_empty() {
+        "The value is empty.".to_string()
+    } else {
+        format!(
+            "Expected one of {} for true or {} for false.",
+            truthy.join(", "),
+            falsy.join(", ")
+        )
+    };
+    Err(ParseError::invalid(
+        format!("invalid boolean: \"{}\"", value),
+        detail,
+    ))
+}
+
+/// Parses a LoW code like `parse_low_code`, but raises an error instead of returning `null`.
+///
+/// # Parameters
+/// - `value`: A LoW code, digits optionally separated by spaces, dots or dashes.
+///
+/// # Returns
+/// The code in the form returned by `parse_low_code` (e.g. `170503*`).
+///
+/// # Errors
+/// - `22P02` (`invalid_text_representation`) if the value contains unexpected characters,
+///   has a wrong number of digits or a misplaced hazardous marker.
+#[pg_extern(create_or_replace)]
+pub fn parse_low_code_strict(value: &str) -> String {
+    unwrap_or_report(check_low_code(value))
+}
+
+/// Parses a disposal, recovery or LoW code like `parse_env_code`, but raises an error
+/// instead of returning `null`.
+///
+/// # Parameters
+/// - `value`: The code to parse.
+/// - `code_type`: The type of code to parse (`"disposalcode"`, `"recoverycode"`, or `"lowcode"`).
+///
+/// # Returns
+/// The parsed code in the form returned by `parse_env_code`.
+///
+/// # Errors
+/// - `22023` (`invalid_parameter_value`) if `code_type` is not one of the supported types.
+/// - `22P02` (`invalid_text_representation`) if `value` is not a valid code of the given type.
+#[pg_extern(create_or_replace)]
+pub fn parse_env_code_strict(value: &str, code_type: &str) -> String {
+    unwrap_or_report(check_env_code(value, code_type))
+}
+
+/// Parses a 64-bit integer, raising an error instead of returning `0` like `parse_i64`.
+///
+/// Unlike `parse_i64`, non-digit characters are not removed: `-5` is parsed as `-5`
+/// and `12 pcs` is rejected.
+///
+/// # Parameters
+/// - `value`: Digits with an optional leading sign, surrounding whitespace is ignored.
+///
+/// # Returns
+/// The parsed integer.
+///
+/// # Errors
+/// - `22P02` (`invalid_text_representation`) if the value is empty or not an integer.
+/// - `22003` (`numeric_value_out_of_range`) if the value does not fit into 64 bits.
+#[pg_extern(create_or_replace)]
+pub fn parse_i64_strict(value: &str) -> i64 {
+    unwrap_or_report(check_i64(value))
+}
+
+/// Parses a boolean, raising an error instead of returning `false` like `parse_bool`.
+///
+/// Unlike `parse_bool`, the whole value has to match, so `yellow` is rejected.
+///
+/// # Parameters
+/// - `value`: One of `1`, `+`, `+1`, `t`, `tr`, `tru`, `true`, `y`, `ye`, `yes`, `on` for `true`
+///   or `0`, `-`, `-1`, `f`, `fa`, `fal`, `fals`, `false`, `n`, `no`, `off` for `false`
+///   (case-insensitive, surrounding whitespace is ignored).
+///
+/// # Returns
+/// The parsed boolean.
+///
+/// # Errors
+/// - `22P02` (`invalid_text_representation`) if the value is not one of the above.
+#[pg_extern(create_or_replace)]
+pub fn parse_bool_strict(value: &str) -> bool {
+    unwrap_or_report(check_bool(value))
+}
+
+extension_sql!(
+    r#"
+CREATE TYPE parse_text_result AS (value text, error text);
+CREATE TYPE parse_bigint_result AS (value bigint, error text);
+CREATE TYPE parse_boolean_result AS (value boolean, error text);
+"#,
+    name = "parse_results"
+);
+
+/// Parses a LoW code, returning the reason instead of raising an error if it is rejected.
+///
+/// # Parameters
+/// - `value`: A LoW code, see `parse_low_code_strict`.
+///
+/// # Returns
+/// A `parse_text_result`:
+/// - `value`: The parsed code, or `null` if it was rejected.
+/// - `error`: The reason why the value was rejected, or `null` if it was parsed.
+#[pg_extern(create_or_replace, requires = ["parse_results"])]
+pub fn try_parse_low_code(value: &str) -> pgrx::composite_type!('static, "parse_text_result") {
+    into_result("parse_text_result", check_low_code(value))
+}
+
+/// Parses a disposal, recovery or LoW code, returning the reason instead of raising an error
+/// if it is rejected.
+///
+/// # Parameters
+/// - `value`: The code to parse.
+/// - `code_type`: The type of code to parse (`"disposalcode"`, `"recoverycode"`, or `"lowcode"`).
+///
+/// # Returns
+/// A `parse_text_result`:
+/// - `value`: The parsed code, or `null` if it was rejected.
+/// - `error`: The reason why the value was rejected, or `null` if it was parsed.
+#[pg_extern(create_or_replace, requires = ["parse_results"])]
+pub fn try_parse_env_code(
+    value: &str,
+    code_type: &str,
+) -> pgrx::composite_type!('static, "parse_text_result") {
+    into_result("parse_text_result", check_env_code(value, code_type))
+}
+
+/// Parses a 64-bit integer, returning the reason instead of raising an error if it is rejected.
+///
+/// # Parameters
+/// - `value`: Digits with an optional leading sign, see `parse_i64_strict`.
+///
+/// # Returns
+/// A `parse_bigint_result`:
+/// - `value`: The parsed integer, or `null` if it was rejected.
+/// - `error`: The reason why the value was rejected, or `null` if it was parsed.
+#[pg_extern(create_or_replace, requires = ["parse_results"])]
+pub fn try_parse_i64(value: &str) -> pgrx::composite_type!('static, "parse_bigint_result") {
+    into_result("parse_bigint_result", check_i64(value))
+}
+
+/// Parses a boolean, returning the reason instead of raising an error if it is rejected.
+///
+/// # Parameters
+/// - `value`: A boolean, see `parse_bool_strict`.
+///
+/// # Returns
+/// A `parse_boolean_result`:
+/// - `value`: The parsed boolean, or `null` if it was rejected.
+/// - `error`: The reason why the value was rejected, or `null` if it was parsed.
+#[pg_extern(create_or_replace, requires = ["parse_results"])]
+pub fn try_parse_bool(value: &str) -> pgrx::composite_type!('static, "parse_boolean_result") {
+    into_result("parse_boolean_result", check_bool(value))
+}
+
+#[cfg(any(test, feature = "pg_test"))]
+#[pg_schema]
+mod tests {
+    use super::*;
+
+    /// Tests `parse_low_code_strict`
+    #[pg_test]
+    fn test_parse_low_code_strict() {
+        assert_eq!("170503*", parse_low_code_strict(" 17 05 03* "));
+        assert_eq!("1705", parse_low_code_strict("17.05"));
+        assert_eq!("20", parse_low_code_strict("20"));
+    }
+
+    /// Tests `parse_low_code_strict` with an invalid code
+    #[pg_test(error = "invalid LoW code: \"17 05 0\"")]
+    fn test_parse_low_code_strict_error() {
+        parse_low_code_strict("17 05 0");
+    }
+
+    /// Tests `parse_env_code_strict`
+    #[pg_test]
+    fn test_parse_env_code_strict() {
+        assert_eq!("D10", parse_env_code_strict("d10", "disposalcode"));
+        assert_eq!("R1.1", parse_env_code_strict("r1.1", "RecoveryCode"));
+        assert_eq!("170504", parse_env_code_strict("17 05 04", "lowcode"));
+    }
+
+    /// Tests `parse_env_code_strict` with an unknown code type
+    #[pg_test(error = "invalid code type: \"wastecode\"")]
+    fn test_parse_env_code_strict_error() {
+        parse_env_code_strict("D10", "wastecode");
+    }
+
+    /// Tests `parse_i64_strict`
+    #[pg_test]
+    fn test_parse_i64_strict() {
+        assert_eq!(-5, parse_i64_strict(" -5 "));
+        assert_eq!(42, parse_i64_strict("+42"));
+        assert_eq!(i64::MAX, parse_i64_strict("9223372036854775807"));
+    }
+
+    /// Tests `parse_i64_strict` with an out of range value
+    #[pg_test(error = "value \"9223372036854775808\" is out of range for type bigint")]
+    fn test_parse_i64_strict_error() {
+        parse_i64_strict("9223372036854775808");
+    }
+
+    /// Tests `parse_bool_strict`
+    #[pg_test]
+    fn test_parse_bool_strict() {
+        assert!(parse_bool_strict("Yes"));
+        assert!(parse_bool_strict(" on "));
+        assert!(!parse_bool_strict("F"));
+        assert!(!parse_bool_strict("0"));
+    }
+
+    /// Tests `parse_bool_strict` with an invalid value
+    #[pg_test(error = "invalid boolean: \"yellow\"")]
+    fn test_parse_bool_strict_error() {
+        parse_bool_strict("yellow");
+    }
+
+    /// Returns the value of type `$t` and the error of a `try_parse_*` result.
+    macro_rules! fields {
+        ($row:expr, $t:ty) => {{
+            let row = $row;
+            (
+                row.get_by_name::<$t>("value").unwrap(),
+                row.get_by_name::<String>("error").unwrap(),
+            )
+        }};
+    }
+
+    /// Tests `try_parse_low_code`
+    #[pg_test]
+    fn test_try_parse_low_code() {
+        let (value, error) = fields!(try_parse_low_code("17 05 04"), String);
+        assert_eq!(Some("170504".to_string()), value);
+        assert_eq!(None, error);
+        let (value, error) = fields!(try_parse_low_code("17 05 04 1"), String);
+        assert_eq!(None, value);
+        assert!(error.unwrap().starts_with("The code has 7 digits."));
+        let (_, error) = fields!(try_parse_low_code("1705*"), String);
+        assert!(error.unwrap().contains("end of a 6-digit entry"));
+        let (_, error) = fields!(try_parse_low_code("17 05 04 x"), String);
+        assert!(error.unwrap().starts_with("Unexpected character 'x'."));
+    }
+
+    /// Tests `try_parse_env_code`
+    #[pg_test]
+    fn test_try_parse_env_code() {
+        let (value, error) = fields!(try_parse_env_code("R5", "recoverycode"), String);
+        assert_eq!(Some("R5".to_string()), value);
+        assert_eq!(None, error);
+        let (value, error) = fields!(try_parse_env_code("R100", "recoverycode"), String);
+        assert_eq!(None, value);
+        assert!(error.unwrap().starts_with("A recovery code consists of"));
+        let (_, error) = fields!(try_parse_env_code(" ", "disposalcode"), String);
+        assert_eq!(Some("The value is empty.".to_string()), error);
+    }
+
+    /// Tests `try_parse_i64`
+    #[pg_test]
+    fn test_try_parse_i64() {
+        assert_eq!((Some(7), None), fields!(try_parse_i64("7"), i64));
+        let (value, error) = fields!(try_parse_i64("12 pcs"), i64);
+        assert_eq!(None, value);
+        assert_eq!(
+            Some("Only digits with an optional leading sign are allowed.".to_string()),
+            error
+        );
+    }
+
+    /// Tests `try_parse_i64` in SQL
+    #[pg_test]
+    fn test_try_parse_i64_sql() {
+        let error = Spi::get_one::<String>("select (try_parse_i64('12 pcs')).error");
+        assert_eq!(
+            Some("Only digits with an optional leading sign are allowed.".to_string()),
+            error.unwrap()
+        );
+    }
+
+    /// Tests `try_parse_bool`
+    #[pg_test]
+    fn test_try_parse_bool() {
+        assert_eq!((Some(false), None), fields!(try_parse_bool("off"), bool));
+        let (value, error) = fields!(try_parse_bool(""), bool);
+        assert_eq!(None, value);
+        assert_eq!(Some("The value is empty.".to_string()), error);
+    }
+}
@@ -0,0 +1,270 @@
+//! Password hashing with Argon2id, stored as PHC strings.
+//!
+//! The cost of new hashes is set by three GUCs, e.g. per role or database:
+//!
+//! - `bfn.password_memory` (default 19 MiB, at most 1 GiB)
+//! - `bfn.password_iterations` (default 2)
+//! - `bfn.password_parallelism` (default 1)
+//!
+//! The defaults follow the OWASP recommendation for Argon2id. Hashes made with other costs stay
+//! verifiable, and `password_needs_rehash` finds them after the costs are raised:
+//!
+//! ```sql
+//! alter database app set bfn.password_memory = '64MB';
+//! update app_user set password = bfn.password_hash($1)
+//!  where id = $2 and bfn.password_verify($1, password) and bfn.password_needs_rehash(password);
+//! ```
+use crate::parse_strict::ParseError;
+use crate::tokens::random_bytes;
+use argon2::password_hash::SaltString;
+use argon2::{Algorithm, Argon2, Params, PasswordHash, PasswordHasher, PasswordVerifier, Version};
+#[allow(unused_imports)]
+use pgrx::prelude::{pg_extern, pg_schema, pg_test, PgSqlErrorCode, Spi};
+use pgrx::{GucContext, GucFlags, GucRegistry, GucSetting};
+
+/// Largest memory cost of hashes in KiB (1 GiB).
+///
+/// Any role may set the cost GUCs and pass any hash to `password_verify`, so the limit keeps a
+/// session from making a hash allocate more memory than a backend can afford.
+const MAX_PASSWORD_MEMORY: i32 = 1_048_576;
+
+/// Largest number of passes over the memory of hashes.
+const MAX_PASSWORD_ITERATIONS: i32 = 1000;
+
+/// Largest number of lanes of hashes.
+const MAX_PASSWORD_PARALLELISM: i32 = 255;
+
+/// Memory cost of new hashes in KiB.
+pub static PASSWORD_MEMORY: GucSetting<i32> = GucSetting::<i32>::new(19_456);
+
+/// Number of passes over the memory of new hashes.
+pub static PASSWORD_ITERATIONS: GucSetting<i32> = GucSetting::<i32>::new(2);
+
+/// Number of lanes of new hashes.
+pub static PASSWORD_PARALLELISM: GucSetting<i32> = GucSetting::<i32>::new(1);
+
+/// Length of the random salt of new hashes in bytes.
+const SALT_LENGTH: usize = 16;
+
+/// Registers the cost GUCs; called from `_PG_init`.
+pub fn init_gucs() {
+    GucRegistry::define_int_guc(
+        "bfn.password_memory",
+        "Memory cost of new Argon2id password hashes.",
+        "Memory used to hash a password with bfn.password_hash, at least 8 KiB per lane.",
+        &PASSWORD_MEMORY,
+        8,
+        MAX_PASSWORD_MEMORY,
+        GucContext::Userset,
+        GucFlags::UNIT_KB,
+    );
+    GucRegistry::define_int_guc(
+        "bfn.password_iterations",
+        "Time cost of new Argon2id password hashes.",
+        "Number of passes over the memory to hash a password with bfn.password_hash.",
+        &PASSWORD_ITERATIONS,
+        1,
+        MAX_PASSWORD_ITERATIONS,
+        GucContext::Userset,
+        GucFlags::default(),
+    );
+    GucRegistry::define_int_guc(
+        "bfn.password_parallelism",
+        "Parallelism of new Argon2id password hashes.",
+        "Number of lanes to hash a password with bfn.password_hash.",
+        &PASSWORD_PARALLELISM,
+        1,
+        MAX_PASSWORD_PARALLELISM,
+        GucContext::Userset,
+        GucFlags::default(),
+    );
+}
+
+/// Returns the Argon2 parameters of the cost GUCs.
+pub fn current_params() -> Result<Params, ParseError> {
+    let (memory, iterations, parallelism) = (
+        PASSWORD_MEMORY.get(),
+        PASSWORD_ITERATIONS.get(),
+        PASSWORD_PARALLELISM.get(),
+    );
+    Params::new(memory as u32, iterations as u32, parallelism as u32, None).map_err(|e| {
+        ParseError {
+            errcode: PgSqlErrorCode::ERRCODE_INVALID_PARAMETER_VALUE,
+            message: format!("invalid Argon2 parameters: {}", e),
+            detail: format!(
+                "bfn.password_memory is {} kB, but must be at least 8 kB per lane of \
+                 bfn.password_parallelism ({}).",
+                memory, parallelism
+            ),
+        }
+    })
+}
+
+/// Checks that the costs of a hash are within the limits of the cost GUCs.
+pub fn within_limits(params: &Params) -> bool {
+    params.m_cost() <= MAX_PASSWORD_MEMORY as u32
+        && params.t_cost() <= MAX_PASSWORD_ITERATIONS as u32
+        && params.p_cost() <= MAX_PASSWORD_PARALLELISM as u32
+}
+
+/// Hashes a password with Argon2id and the given parameters.
+pub fn hash(password: &str, params: Params) -> Result<String, ParseError> {
+    let to_error = |e: argon2::password_hash::Error| ParseError {
+        errcode: PgSqlErrorCode::ERRCODE_INVALID_PARAMETER_VALUE,
+        message: format!("could not hash password: {}", e),
+        detail: "Check the bfn.password_* settings.".to_string(),
+    };
+    let salt = SaltString::encode_b64(&random_bytes(SALT_LENGTH)?).map_err(to_error)?;
+    let argon2 = Argon2::new(Algorithm::Argon2id, Version::V0x13, params);
+    let hash = argon2
+        .hash_password(password.as_bytes(), &salt)
+        .map_err(to_error)?;
+    Ok(hash.to_string())
+}
+
+/// Hashes a password with Argon2id.
+///
+/// Every hash has its own random salt, so hashing the same password twice gives different
+/// hashes; use `password_verify` to check a password.
+///
+/// # Parameters
+/// - `password`: The password, as UTF-8.
+///
+/// # Returns
+/// - A PHC string with the algorithm, costs, salt and hash, e.g.
+///   `$argon2id$v=19$m=19456,t=2,p=1$<salt>$<hash>`.
+///
+/// # Errors
+/// - If the cost GUCs are inconsistent, i.e. `bfn.password_memory` is less than 8 kB per lane.
+#[pg_extern(create_or_replace)]
+pub fn password_hash(password: &str) -> String {
+    let params = current_params().unwrap_or_else(|e| e.report());
+    hash(password, params).unwrap_or_else(|e| e.report())
+}
+
+/// Verifies a password against a hash of `password_hash`.
+///
+/// The costs are read from the hash, so hashes made with other settings remain valid. The hash
+/// outputs are compared in constant time. Hashes with costs above the limits of the
+/// `bfn.password_*` settings (1 GiB, 1000 iterations, 255 lanes) are not computed.
+///
+/// # Parameters
+/// - `password`: The password to check.
+/// - `hash`: The PHC string of an Argon2 (id, i or d) hash.
+///
+/// # Returns
+/// - `true` if the password matches the hash.
+/// - `false` otherwise, also if the hash is not a valid Argon2 PHC string or its costs are above
+///   the limits.
+#[pg_extern(create_or_replace)]
+pub fn password_verify(password: &str, hash: &str) -> bool {
+    PasswordHash::new(hash).is_ok_and(|hash| {
+        Params::try_from(&hash).is_ok_and(|params| within_limits(&params))
+            && Argon2::default()
+                .verify_password(password.as_bytes(), &hash)
+                .is_ok()
+    })
+}
+
+/// Checks whether a password hash should be replaced by a new `password_hash` of the password.
+///
+/// Call it after a successful `password_verify`, when the password is at hand.
+///
+/// # Parameters
+/// - `hash`: The PHC string of a stored hash.
+///
+/// # Returns
+/// - `true` if the hash is not an Argon2id hash of version 19, or if its memory, iterations or
+///   parallelism differ from the current `bfn.password_*` settings, or if it is not a valid PHC
+///   string.
+/// - `false` otherwise.
+///
+/// # Errors
+/// - If the cost GUCs are inconsistent.
+#[pg_extern(create_or_replace)]
+pub fn password_needs_rehash(hash: &str) -> bool {
+    let current = current_params().unwrap_or_else(|e| e.report());
+    let Ok(hash) = PasswordHash::new(hash) else {
+        return true;
+    };
+    let Ok(params) = Params::try_from(&hash) else {
+        return true;
+    };
+    hash.algorithm != argon2::ARGON2ID_IDENT
+        || hash.version != Some(Version::V0x13.into())
+        || (params.m_cost(), params.t_cost(), params.p_cost())
+            != (current.m_cost(), current.t_cost(), current.p_cost())
+}
+
+#[cfg(any(test, feature = "pg_test"))]
+#[pg_schema]
+mod tests {
+    use super::*;
+
+    /// Tests `password_hash`
+    #[pg_test]
+    fn test_password_hash() {
+        let hash = password_hash("correct horse battery staple");
+        assert!(hash.starts_with("$argon2id$v=19$m=19456,t=2,p=1$"));
+        assert_ne!(hash, password_hash("correct horse battery staple"));
+    }
+
+    /// Tests `password_hash` with changed cost GUCs
+    #[pg_test]
+    fn test_password_hash_gucs() {
+        let default = password_hash("secret");
+        Spi::run("set local bfn.password_memory = '1MB'").unwrap();
+        Spi::run("set local bfn.password_iterations = 3").unwrap();
+        let hash = password_hash("secret");
+        assert!(hash.starts_with("$argon2id$v=19$m=1024,t=3,p=1$"));
+        assert!(password_verify("secret", &hash));
+        assert!(!password_needs_rehash(&hash));
+        assert!(password_needs_rehash(&default));
+    }
+
+    /// Tests `password_verify`
+    #[pg_test]
+    fn test_password_verify() {
+        let hash = password_hash("correct horse battery staple");
+        assert!(password_verify("correct horse battery staple", &hash));
+        assert!(!password_verify("correct horse battery stapler", &hash));
+        assert!(!password_verify("secret", "not a hash"));
+        // Hashes made with other costs remain valid
+        let cheap = hash_params("secret", 1024, 1, 1);
+        assert!(password_verify("secret", &cheap));
+    }
+
+    /// Tests `password_verify` with costs above the limits
+    #[pg_test]
+    fn test_password_verify_limits() {
+        let hash = hash_params("secret", 1024, 1, 1);
+        let costly = |costs: &str| hash.replace("m=1024,t=1,p=1", costs);
+        assert!(!password_verify("secret", &costly("m=4294967295,t=1,p=1")));
+        assert!(!password_verify("secret", &costly("m=1048577,t=1,p=1")));
+        assert!(!password_verify(
+            "secret",
+            &costly("m=1024,t=4294967295,p=1")
+        ));
+        assert!(!password_verify("secret", &costly("m=262144,t=1,p=256")));
+    }
+
+    /// Tests `password_needs_rehash`
+    #[pg_test]
+    fn test_password_needs_rehash() {
+        assert!(!password_needs_rehash(&password_hash("secret")));
+        assert!(password_needs_rehash(&hash_params("secret", 1024, 1, 1)));
+        assert!(password_needs_rehash(
+            "$2b$12$R9h/cIPz0gi.URNNX3kh2OPST9/PgBkqquzi.Ss7KIUgO2t0jWMUW"
+        ));
+        assert!(password_needs_rehash("not a hash"));
+    }
+
+    /// Hashes a password with explicit costs.
+    fn hash_params(password: &str, memory: u32, iterations: u32, parallelism: u32) -> String {
+        hash(
+            password,
+            Params::new(memory, iterations, parallelism, None).unwrap(),
+        )
+        .unwrap()
+    }
+}
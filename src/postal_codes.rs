@@ -0,0 +1,196 @@
+//! Postal code validation and normalization for the EU and EEA countries, Switzerland, the
+//! United Kingdom and the United States.
+//!
+//! Codes are compared without spaces and hyphens, in upper case, and an optional country prefix
+//! (e.g. `D-` or `EE`) is accepted:
+//!
+//! ```sql
+//! select bfn.normalize_postal_code('sw1a1aa', 'United Kingdom'); -- SW1A 1AA
+//! select bfn.is_valid_postal_code('00-950', 'POL'); -- true
+//! ```
+use crate::countries::find_country;
+#[allow(unused_imports)]
+use pgrx::prelude::{pg_extern, pg_schema, pg_test};
+use regex::Regex;
+use std::sync::OnceLock;
+
+/// Postal code formats: ISO 3166-1 alpha-2 code, pattern of the compact code and canonical form.
+///
+/// A country can have more than one format; the first matching one is used.
+const POSTAL_FORMATS: &[(&str, &str, &str)] = &[
+    ("AT", r"^(?:A|AT)?(\d{4})$", "$1"),
+    ("BE", r"^(?:B|BE)?([1-9]\d{3})$", "$1"),
+    ("BG", r"^(?:BG)?(\d{4})$", "$1"),
+    ("CH", r"^(?:CH)?([1-9]\d{3})$", "$1"),
+    ("CY", r"^(?:CY)?(\d{4})$", "$1"),
+    ("CZ", r"^(?:CZ)?([1-7]\d{2})(\d{2})$", "$1 $2"),
+    ("DE", r"^(?:D|DE)?(\d{5})$", "$1"),
+    ("DK", r"^(?:DK)?([1-9]\d{3})$", "$1"),
+    ("EE", r"^(?:EE)?([1-9]\d{4})$", "$1"),
+    ("ES", r"^(?:E|ES)?((?:0[1-9]|[1-4]\d|5[0-2])\d{3})$", "$1"),
+    ("FI", r"^(?:FI)?(\d{5})$", "$1"),
+    ("FR", r"^(?:F|FR)?(\d{5})$", "$1"),
+    ("GB", r"^([A-Z]{1,2}\d[A-Z\d]?|GIR)(\d[A-Z]{2})$", "$1 $2"),
+    ("GR", r"^(?:GR)?([1-8]\d{2})(\d{2})$", "$1 $2"),
+    ("HR", r"^(?:HR)?([1-5]\d{4})$", "$1"),
+    ("HU", r"^(?:H|HU)?([1-9]\d{3})$", "$1"),
+    (
+        "IE",
+        r"^([AC-FHKNPRTV-Y]\d{2}|D6W)([\dAC-FHKNPRTV-Y]{4})$",
+        "$1 $2",
+    ),
+    ("IS", r"^(?:IS)?([1-9]\d{2})$", "$1"),
+    ("IT", r"^(?:I|IT)?(\d{5})$", "$1"),
+    ("LI", r"^(?:FL|LI)?(94[89]\d)$", "$1"),
+    ("LT", r"^(?:LT)?(\d{5})$", "LT-$1"),
+    ("LU", r"^(?:L|LU)?(\d{4})$", "$1"),
+    ("LV", r"^(?:LV)?(\d{4})$", "LV-$1"),
+    ("MT", r"^([A-Z]{3})(\d{4})$", "$1 $2"),
+    ("NL", r"^(?:NL)?([1-9]\d{3})([A-Z]{2})$", "$1 $2"),
+    ("NO", r"^(?:N|NO)?(\d{4})$", "$1"),
+    ("PL", r"^(?:PL)?(\d{2})(\d{3})$", "$1-$2"),
+    ("PT", r"^(?:P|PT)?([1-9]\d{3})(\d{3})$", "$1-$2"),
+    ("RO", r"^(?:RO)?(\d{6})$", "$1"),
+    ("SE", r"^(?:S|SE)?([1-9]\d{2})(\d{2})$", "$1 $2"),
+    ("SI", r"^(?:SI)?([1-9]\d{3})$", "$1"),
+    ("SK", r"^(?:SK)?([089]\d{2})(\d{2})$", "$1 $2"),
+    ("US", r"^(\d{5})(\d{4})$", "$1-$2"),
+    ("US", r"^(\d{5})$", "$1"),
+];
+
+/// Returns the compiled postal code formats.
+fn postal_formats() -> &'static Vec<(&'static str, Regex, &'static str)> {
+    static FORMATS: OnceLock<Vec<(&str, Regex, &str)>> = OnceLock::new();
+    FORMATS.get_or_init(|| {
+        POSTAL_FORMATS
+            .iter()
+            .map(|(country, pattern, canonical)| {
+                (*country, Regex::new(pattern).unwrap(), *canonical)
+            })
+            .collect()
+    })
+}
+
+/// Returns the alpha-2 code of a country with known postal code formats.
+///
+/// The country is recognised as by `normalize_country`.
+pub fn postal_country(country: &str) -> Option<&'static str> {
+    let code = find_country(country)?.alpha2;
+    postal_formats()
+        .iter()
+        .any(|(c, ..)| *c == code)
+        .then_some(code)
+}
+
+/// Returns the canonical form of a postal code of a country with known formats.
+///
+/// Returns `None` if the code does not match the formats of the country.
+pub fn canonical_postal_code(code: &str, country: &str) -> Option<String> {
+    let compact: String = code
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != '-')
+        .collect::<String>()
+        .to_uppercase();
+    postal_formats()
+        .iter()
+        .filter(|(c, ..)| *c == country)
+        .find(|(_, pattern, _)| pattern.is_match(&compact))
+        .map(|(_, pattern, canonical)| pattern.replace(&compact, *canonical).to_string())
+}
+
+/// Normalizes a postal code to the canonical form of its country.
+///
+/// Spaces, hyphens and case are normalized, and an optional country prefix is removed (or added
+/// for Latvia and Lithuania, where it is part of the code).
+///
+/// # Parameters
+/// - `code`: The postal code, e.g. `sw1a1aa`.
+/// - `country`: The ISO 3166-1 alpha-2 or alpha-3 code, English or native name of the country,
+///   e.g. `GB`, `GBR` or `United Kingdom`.
+///
+/// # Returns
+/// - The canonical postal code, e.g. `SW1A 1AA`.
+/// - `null` if the code is not valid in the country.
+/// - The code in upper case with single spaces if the formats of the country are not known,
+///   or `null` if the code is empty.
+#[pg_extern(immutable, parallel_safe, create_or_replace)]
+pub fn normalize_postal_code(code: &str, country: &str) -> Option<String> {
+    match postal_country(country) {
+        Some(country) => canonical_postal_code(code, country),
+        None => {
+            let code = code.split_whitespace().collect::<Vec<_>>().join(" ");
+            (!code.is_empty()).then(|| code.to_uppercase())
+        }
+    }
+}
+
+/// Checks whether a postal code is valid in a country.
+///
+/// Spaces, hyphens and case are ignored, and an optional country prefix is accepted.
+///
+/// # Parameters
+/// - `code`: The postal code.
+/// - `country`: The ISO 3166-1 alpha-2 or alpha-3 code, English or native name of the country.
+///
+/// # Returns
+/// - `true` if the code matches a postal code format of the country, otherwise `false`.
+/// - `null` if the formats of the country are not known. Known are the EU and EEA countries,
+///   Switzerland, the United Kingdom and the United States.
+#[pg_extern(immutable, parallel_safe, create_or_replace)]
+pub fn is_valid_postal_code(code: &str, country: &str) -> Option<bool> {
+    postal_country(country).map(|country| canonical_postal_code(code, country).is_some())
+}
+
+#[cfg(any(test, feature = "pg_test"))]
+#[pg_schema]
+mod tests {
+    use super::*;
+
+    /// Tests `normalize_postal_code`
+    #[pg_test]
+    fn test_normalize_postal_code() {
+        for (code, country, expected) in [
+            ("sw1a1aa", "United Kingdom", "SW1A 1AA"),
+            (" ec1a 1bb ", "GB", "EC1A 1BB"),
+            ("10117", "Estonia", "10117"),
+            ("EE-10117", "EST", "10117"),
+            ("1050", "LV", "LV-1050"),
+            ("lt-01100", "Lietuva", "LT-01100"),
+            ("00950", "Polska", "00-950"),
+            ("1000001", "PT", "1000-001"),
+            ("1234ab", "NL", "1234 AB"),
+            ("D-10115", "Deutschland", "10115"),
+            ("11421", "Sweden", "114 21"),
+            ("d02x285", "IE", "D02 X285"),
+            ("vlt1117", "Malta", "VLT 1117"),
+            ("12345 6789", "USA", "12345-6789"),
+        ] {
+            assert_eq!(
+                Some(expected.to_string()),
+                normalize_postal_code(code, country),
+                "{} {}",
+                code,
+                country
+            );
+        }
+        assert_eq!(None, normalize_postal_code("1234", "DE"));
+        assert_eq!(None, normalize_postal_code("SW1A", "GB"));
+        assert_eq!(
+            Some("00100 NBO".to_string()),
+            normalize_postal_code(" 00100  nbo", "Kenya")
+        );
+        assert_eq!(None, normalize_postal_code("  ", "Kenya"));
+    }
+
+    /// Tests `is_valid_postal_code`
+    #[pg_test]
+    fn test_is_valid_postal_code() {
+        assert_eq!(Some(true), is_valid_postal_code("00-950", "POL"));
+        assert_eq!(Some(true), is_valid_postal_code("75001", "fr"));
+        assert_eq!(Some(true), is_valid_postal_code("9490", "Liechtenstein"));
+        assert_eq!(Some(false), is_valid_postal_code("0999", "Belgium"));
+        assert_eq!(Some(false), is_valid_postal_code("1234 AB", "DE"));
+        assert_eq!(Some(false), is_valid_postal_code("", "US"));
+        assert_eq!(None, is_valid_postal_code("00100", "Kenya"));
+    }
+}
@@ -0,0 +1,605 @@
+//! `quantity` type: a numeric value with a unit, e.g. of mass, volume or count.
+//!
+//! Units are the built-in units of `convert_unit`. Custom units of `bfn.unit_registry` are not
+//! supported: comparisons and hashes of stored quantities, and thus their indexes, must not
+//! change when the table is edited.
+//! Unlike `metric_scale_down` and `metric_scale_up`, conversions know the units involved
+//! and refuse to mix dimensions, unless a density is given for mass/volume conversion.
+//!
+//! ```sql
+//! select '1250 kg'::bfn.quantity + '2.5 t'::bfn.quantity;     -- 3750 kg
+//! select bfn.quantity_to('3 m3', 't', 0.8);                    -- 2.4 t
+//! select bfn.quantity_to(bfn.sum(amount), 't') from waste_report;
+//! ```
+#![allow(non_camel_case_types)]
+use crate::parse_strict::ParseError;
+use crate::units::{base_unit, find_builtin_unit, Unit};
+#[allow(unused_imports)]
+use pgrx::prelude::{
+    commutator, default, name, opname, pg_aggregate, pg_extern, pg_operator, pg_schema, pg_sys,
+    pg_test, Aggregate, AnyNumeric, InOutFuncs, PgSqlErrorCode, PostgresEq, PostgresHash,
+    PostgresOrd, PostgresType, Spi,
+};
+use pgrx::StringInfo;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::ffi::CStr;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::sync::OnceLock;
+
+/// Converts a decimal constant to numeric.
+fn numeric(value: &str) -> AnyNumeric {
+    AnyNumeric::try_from(value).unwrap()
+}
+
+/// Returns the regular expression of the text form of a quantity.
+fn quantity_pattern() -> &'static Regex {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    PATTERN.get_or_init(|| {
+        Regex::new(r"^\s*([+-]?\d+(?:[.,]\d+)?(?:[eE][+-]?\d+)?)\s*(\S.*?)\s*$").unwrap()
+    })
+}
+
+/// Looks up a built-in unit of a quantity.
+fn find_unit(name: &str) -> Result<Unit, ParseError> {
+    find_builtin_unit(name).ok_or_else(|| ParseError {
+        errcode: PgSqlErrorCode::ERRCODE_INVALID_PARAMETER_VALUE,
+        message: format!("unknown unit: \"{}\"", name),
+        detail: "Quantities take the built-in units of convert_unit, not custom units. Unit \
+            symbols are case-sensitive (e.g. mg and Mg)."
+            .to_string(),
+    })
+}
+
+/// Numeric value with a unit.
+#[derive(
+    Clone, Debug, Serialize, Deserialize, PostgresType, PostgresEq, PostgresOrd, PostgresHash,
+)]
+#[inoutfuncs]
+pub struct quantity {
+    value: AnyNumeric,
+    unit: String,
+}
+
+impl quantity {
+    /// Creates a quantity, normalising the unit to its canonical symbol.
+    pub fn new(value: AnyNumeric, unit: &str) -> Result<quantity, ParseError> {
+        let unit = find_unit(unit)?;
+        let value = numeric(value.normalize());
+        Ok(quantity {
+            value,
+            unit: unit.symbol,
+        })
+    }
+
+    /// Parses the text form, e.g. `12.5 kg` or `12,5kg`.
+    ///
+    /// A decimal comma followed by exactly three digits, e.g. `1,000 kg`, is refused, as it may
+    /// as well be a thousands separator.
+    pub fn parse(input: &str) -> Result<quantity, ParseError> {
+        let invalid = |detail: &str| {
+            ParseError::invalid(
+                format!("invalid input syntax for type quantity: \"{}\"", input),
+                detail.to_string(),
+            )
+        };
+        let caps = quantity_pattern().captures(input).ok_or_else(|| {
+            invalid("A quantity consists of a number followed by a unit, e.g. 12.5 kg.")
+        })?;
+        let number = &caps[1];
+        let thousands = number.split_once(',').is_some_and(|(_, fraction)| {
+            fraction.bytes().take_while(u8::is_ascii_digit).count() == 3
+        });
+        if thousands {
+            return Err(invalid(
+                "A comma followed by three digits is ambiguous. Write 1000 kg without a \
+                thousands separator, or 1.000 kg with a decimal point.",
+            ));
+        }
+        quantity::new(numeric(&number.replace(',', ".")), &caps[2])
+    }
+
+    /// Returns the unit of the quantity.
+    pub fn unit(&self) -> Unit {
+        find_unit(&self.unit).unwrap_or_else(|e| e.report())
+    }
+
+    /// Returns the value in the base unit of the dimension (e.g. `kg`, `m3`, `pcs`).
+    fn base_value(&self) -> AnyNumeric {
+        self.value.clone() * self.unit().factor
+    }
+
+    /// Converts the quantity to another unit.
+    ///
+    /// Mass and volume are converted into each other if `density` (t/m³, equal to kg/l) is given.
+    pub fn convert(&self, unit: &str, density: Option<AnyNumeric>) -> Result<quantity, ParseError> {
+        let from = self.unit();
+        let to = find_unit(unit)?;
+        let base = match (from.dimension.as_str(), to.dimension.as_str(), density) {
+            (a, b, _) if a == b => self.base_value(),
+            ("volume", "mass", Some(density)) => self.base_value() * density * numeric("1000"),
+            ("mass", "volume", Some(density)) => self.base_value() / (density * numeric("1000")),
+            _ => {
+                return Err(ParseError {
+                    errcode: PgSqlErrorCode::ERRCODE_INVALID_PARAMETER_VALUE,
+                    message: format!("cannot convert {} to {}", self, to.symbol),
+                    detail: format!(
+                        "Units of {} cannot be converted to {}{}.",
+                        from.dimension,
+                        to.dimension,
+                        if from.dimension == "count" || to.dimension == "count" {
+                            ""
+                        } else {
+                            " without a density"
+                        }
+                    ),
+                })
+            }
+        };
+        quantity::new(base / to.factor, &to.symbol)
+    }
+
+    /// Converts `other` to the unit of `self`, refusing other dimensions.
+    fn compatible(&self, other: &quantity, operation: &str) -> Result<AnyNumeric, ParseError> {
+        if self.unit().dimension != other.unit().dimension {
+            return Err(ParseError {
+                errcode: PgSqlErrorCode::ERRCODE_DATATYPE_MISMATCH,
+                message: format!("cannot {} {} and {}", operation, self, other),
+                detail: format!(
+                    "Quantities of {} and {} cannot be combined.",
+                    self.unit().dimension,
+                    other.unit().dimension
+                ),
+            });
+        }
+        Ok(other.convert(&self.unit, None)?.value)
+    }
+}
+
+impl fmt::Display for quantity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.value.normalize(), self.unit)
+    }
+}
+
+/// Quantities are equal if they have the same dimension and the same value in the base unit,
+/// so `1000 g` equals `1 kg`.
+impl PartialEq for quantity {
+    fn eq(&self, other: &quantity) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for quantity {}
+
+impl PartialOrd for quantity {
+    fn partial_cmp(&self, other: &quantity) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Orders by dimension, then by the value in the base unit.
+impl Ord for quantity {
+    fn cmp(&self, other: &quantity) -> Ordering {
+        self.unit()
+            .dimension
+            .cmp(&other.unit().dimension)
+            .then_with(|| self.base_value().cmp(&other.base_value()))
+    }
+}
+
+impl Hash for quantity {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.unit().dimension.hash(state);
+        self.base_value().hash(state);
+    }
+}
+
+impl InOutFuncs for quantity {
+    fn input(input: &CStr) -> quantity {
+        quantity::parse(&input.to_string_lossy()).unwrap_or_else(|e| e.report())
+    }
+
+    fn output(&self, buffer: &mut StringInfo) {
+        buffer.push_str(&self.to_string());
+    }
+}
+
+/// Creates a quantity from a value and a unit.
+///
+/// # Parameters
+/// - `value`: The numeric value.
+/// - `unit`: A built-in unit of `convert_unit`, e.g. `kg`, `t`, `lb`, `l`, `m3`, `pcs`, or a name
+///   such as `tonne` or `litre`. Symbols are case-sensitive, so `Mg` is a megagram and `mg` a
+///   milligram; names are not. Custom units of `bfn.unit_registry` are not supported.
+///
+/// # Returns
+/// The quantity with the unit normalised to its symbol.
+///
+/// # Errors
+/// - `22023` (`invalid_parameter_value`) if the unit is unknown or a custom unit.
+#[pg_extern(immutable, parallel_safe, create_or_replace)]
+pub fn to_quantity(value: AnyNumeric, unit: &str) -> quantity {
+    quantity::new(value, unit).unwrap_or_else(|e| e.report())
+}
+
+/// Returns the numeric value of a quantity.
+#[pg_extern(immutable, parallel_safe, create_or_replace)]
+pub fn quantity_value(value: quantity) -> AnyNumeric {
+    value.value
+}
+
+/// Returns the unit symbol of a quantity.
+#[pg_extern(immutable, parallel_safe, create_or_replace)]
+pub fn quantity_unit(value: quantity) -> String {
+    value.unit
+}
+
+/// Returns the dimension of a quantity, e.g. `mass`, `volume` or `count`.
+#[pg_extern(immutable, parallel_safe, create_or_replace)]
+pub fn quantity_dimension(value: quantity) -> String {
+    value.unit().dimension
+}
+
+/// Converts a quantity to another unit.
+///
+/// # Parameters
+/// - `value`: The quantity to convert.
+/// - `unit`: The target unit.
+/// - `density`: Density in t/m³ (equal to kg/l), required to convert between mass and volume.
+///   Defaults to `null`.
+///
+/// # Returns
+/// The quantity in the target unit, e.g. `2.5 t` for `2500 kg`.
+///
+/// # Errors
+/// - `22023` (`invalid_parameter_value`) if the unit is unknown or the dimensions differ
+///   and cannot be converted.
+#[pg_extern(immutable, parallel_safe, create_or_replace)]
+pub fn quantity_to(
+    value: quantity,
+    unit: &str,
+    density: default!(Option<AnyNumeric>, "NULL"),
+) -> quantity {
+    value.convert(unit, density).unwrap_or_else(|e| e.report())
+}
+
+/// Adds two quantities of the same dimension. Used as the `+` operator.
+///
+/// # Returns
+/// The sum in the unit of the left operand, e.g. `3750 kg` for `1250 kg + 2.5 t`.
+///
+/// # Errors
+/// - `42804` (`datatype_mismatch`) if the dimensions differ.
+#[pg_operator(immutable, parallel_safe, create_or_replace)]
+#[opname(+)]
+#[commutator(+)]
+pub fn quantity_add(left: quantity, right: quantity) -> quantity {
+    let value = left
+        .compatible(&right, "add")
+        .unwrap_or_else(|e| e.report());
+    quantity::new(left.value + value, &left.unit).unwrap_or_else(|e| e.report())
+}
+
+/// Subtracts a quantity of the same dimension. Used as the `-` operator.
+///
+/// # Returns
+/// The difference in the unit of the left operand.
+///
+/// # Errors
+/// - `42804` (`datatype_mismatch`) if the dimensions differ.
+#[pg_operator(immutable, parallel_safe, create_or_replace)]
+#[opname(-)]
+pub fn quantity_sub(left: quantity, right: quantity) -> quantity {
+    let value = left
+        .compatible(&right, "subtract")
+        .unwrap_or_else(|e| e.report());
+    quantity::new(left.value - value, &left.unit).unwrap_or_else(|e| e.report())
+}
+
+/// Multiplies a quantity by a number. Used as the `*` operator.
+#[pg_operator(immutable, parallel_safe, create_or_replace)]
+#[opname(*)]
+pub fn quantity_mul(left: quantity, right: AnyNumeric) -> quantity {
+    quantity::new(left.value * right, &left.unit).unwrap_or_else(|e| e.report())
+}
+
+/// Divides a quantity by a number. Used as the `/` operator.
+#[pg_operator(immutable, parallel_safe, create_or_replace)]
+#[opname(/)]
+pub fn quantity_div(left: quantity, right: AnyNumeric) -> quantity {
+    quantity::new(left.value / right, &left.unit).unwrap_or_else(|e| e.report())
+}
+
+/// State of the quantity `sum` and `avg` aggregates: dimension, total in the base unit and row
+/// count.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PostgresType)]
+pub struct quantity_agg_state {
+    dimension: Option<String>,
+    total: AnyNumeric,
+    count: i64,
+}
+
+impl quantity_agg_state {
+    /// Adds a quantity, refusing other dimensions than the ones added before.
+    fn add(mut self, value: quantity) -> quantity_agg_state {
+        let unit = value.unit();
+        if let Some(dimension) = &self.dimension {
+            if *dimension != unit.dimension {
+                ParseError {
+                    errcode: PgSqlErrorCode::ERRCODE_DATATYPE_MISMATCH,
+                    message: format!("cannot aggregate {} with {} quantities", value, dimension),
+                    detail: "All quantities of an aggregate must have the same dimension."
+                        .to_string(),
+                }
+                .report();
+            }
+        }
+        self.total += value.base_value();
+        self.count += 1;
+        self.dimension = Some(unit.dimension);
+        self
+    }
+
+    /// Returns the unit of the result, the base unit of the dimension.
+    fn result_unit(&self) -> Option<Unit> {
+        find_builtin_unit(base_unit(self.dimension.as_deref()?)?)
+    }
+}
+
+/// Aggregate summing quantities of one dimension.
+///
+/// # Returns
+/// The sum in the base unit of the dimension (e.g. `kg`, `m3` or `pcs`), or `null` if there are no
+/// rows. Use `quantity_to` to convert, e.g. to `t`.
+///
+/// # Errors
+/// - `42804` (`datatype_mismatch`) if the quantities have different dimensions.
+pub struct QuantitySum;
+
+#[pg_aggregate]
+impl Aggregate for QuantitySum {
+    const NAME: &'static str = "sum";
+    const INITIAL_CONDITION: Option<&'static str> =
+        Some(r#"{"dimension":null,"total":"0","count":0}"#);
+    type State = quantity_agg_state;
+    type Args = name!(value, quantity);
+    type Finalize = Option<quantity>;
+
+    fn state(
+        current: Self::State,
+        value: Self::Args,
+        _fcinfo: pg_sys::FunctionCallInfo,
+    ) -> Self::State {
+        current.add(value)
+    }
+
+    fn finalize(
+        current: Self::State,
+        _direct_args: Self::OrderedSetArgs,
+        _fcinfo: pg_sys::FunctionCallInfo,
+    ) -> Self::Finalize {
+        let unit = current.result_unit()?;
+        quantity::new(current.total / unit.factor, &unit.symbol).ok()
+    }
+}
+
+/// Aggregate averaging quantities of one dimension.
+///
+/// # Returns
+/// The average in the base unit of the dimension (e.g. `kg`, `m3` or `pcs`), or `null` if there
+/// are no rows.
+///
+/// # Errors
+/// - `42804` (`datatype_mismatch`) if the quantities have different dimensions.
+pub struct QuantityAvg;
+
+#[pg_aggregate]
+impl Aggregate for QuantityAvg {
+    const NAME: &'static str = "avg";
+    const INITIAL_CONDITION: Option<&'static str> =
+        Some(r#"{"dimension":null,"total":"0","count":0}"#);
+    type State = quantity_agg_state;
+    type Args = name!(value, quantity);
+    type Finalize = Option<quantity>;
+
+    fn state(
+        current: Self::State,
+        value: Self::Args,
+        _fcinfo: pg_sys::FunctionCallInfo,
+    ) -> Self::State {
+        current.add(value)
+    }
+
+    fn finalize(
+        current: Self::State,
+        _direct_args: Self::OrderedSetArgs,
+        _fcinfo: pg_sys::FunctionCallInfo,
+    ) -> Self::Finalize {
+        let unit = current.result_unit()?;
+        let average = current.total / AnyNumeric::from(current.count);
+        quantity::new(average / unit.factor, &unit.symbol).ok()
+    }
+}
+
+#[cfg(any(test, feature = "pg_test"))]
+#[pg_schema]
+mod tests {
+    use super::*;
+    use crate::units::unit_registry_table;
+
+    fn q(value: &str) -> quantity {
+        quantity::parse(value).unwrap()
+    }
+
+    /// Tests `quantity` text input and output
+    #[pg_test]
+    fn test_quantity() {
+        assert_eq!("12.5 kg", q(" 12,5 KG ").to_string());
+        assert_eq!("3 t", q("3 tonnes").to_string());
+        assert_eq!("1000 m3", q("1e3m³").to_string());
+        assert!(quantity::parse("12.5").is_err());
+        assert!(quantity::parse("kg").is_err());
+        assert!(quantity::parse("12 parsecs").is_err());
+        assert_eq!("1.5 kg", q("1,5 kg").to_string());
+        assert_eq!("1.0005 kg", q("1,0005 kg").to_string());
+        assert!(quantity::parse("1,000 kg").is_err());
+        assert!(quantity::parse("12,500e3 kg").is_err());
+        assert_eq!("1 kg", q("1.000 kg").to_string());
+        assert_eq!(q("1000 g"), q("1 kg"));
+        assert!(q("999 kg") < q("1 t"));
+        assert_ne!(q("1 kg"), q("1 l"));
+        assert_eq!("1 lb", q("1 lbs").to_string());
+        assert_eq!(q("1 lb"), q("453.59237 g"));
+    }
+
+    /// Tests `quantity` text input with a comma that may be a thousands separator
+    #[pg_test(error = "invalid input syntax for type quantity: \"1,000 kg\"")]
+    fn test_quantity_thousands_separator() {
+        quantity::input(&std::ffi::CString::new("1,000 kg").unwrap());
+    }
+
+    /// Tests that unit symbols of `quantity` are case-sensitive
+    #[pg_test]
+    fn test_quantity_unit_case() {
+        assert_eq!("5 Mg", q("5 Mg").to_string());
+        assert_eq!("5 mg", q("5 mg").to_string());
+        assert_eq!(q("5 Mg"), q("5 t"));
+        assert!(q("5 mg") < q("1 g"));
+        assert_eq!("5 kg", q("5 KG").to_string());
+    }
+
+    /// Tests `to_quantity`
+    #[pg_test]
+    fn test_to_quantity() {
+        assert_eq!("2.5 t", to_quantity(numeric("2.50"), "Tonne").to_string());
+        assert_eq!(
+            "4 pcs",
+            to_quantity(AnyNumeric::from(4), "pieces").to_string()
+        );
+    }
+
+    /// Tests `to_quantity` with an unknown unit
+    #[pg_test(error = "unknown unit: \"bag\"")]
+    fn test_to_quantity_error() {
+        to_quantity(AnyNumeric::from(1), "bag");
+    }
+
+    /// Tests that `to_quantity` ignores custom units of `bfn.unit_registry`
+    #[pg_test(error = "unknown unit: \"sack\"")]
+    fn test_to_quantity_custom_unit() {
+        Spi::run(&format!(
+            "insert into {} (symbol, dimension, factor) values ('sack', 'mass', 25)",
+            unit_registry_table().unwrap()
+        ))
+        .unwrap();
+        to_quantity(AnyNumeric::from(1), "sack");
+    }
+
+    /// Tests `quantity_value`
+    #[pg_test]
+    fn test_quantity_value() {
+        assert_eq!(numeric("12.5"), quantity_value(q("12.5 kg")));
+    }
+
+    /// Tests `quantity_unit`
+    #[pg_test]
+    fn test_quantity_unit() {
+        assert_eq!("l", quantity_unit(q("5 Litres")));
+    }
+
+    /// Tests `quantity_dimension`
+    #[pg_test]
+    fn test_quantity_dimension() {
+        assert_eq!("mass", quantity_dimension(q("5 g")));
+        assert_eq!("volume", quantity_dimension(q("5 m3")));
+        assert_eq!("count", quantity_dimension(q("5 pcs")));
+    }
+
+    /// Tests `quantity_to`
+    #[pg_test]
+    fn test_quantity_to() {
+        assert_eq!("2.5 t", quantity_to(q("2500 kg"), "t", None).to_string());
+        assert_eq!("1500 g", quantity_to(q("1.5 kg"), "g", None).to_string());
+        assert_eq!("750 l", quantity_to(q("0.75 m3"), "l", None).to_string());
+        assert_eq!(
+            "2.4 t",
+            quantity_to(q("3 m3"), "t", Some(numeric("0.8"))).to_string()
+        );
+        assert_eq!(
+            "2 m3",
+            quantity_to(q("1.6 t"), "m3", Some(numeric("0.8"))).to_string()
+        );
+    }
+
+    /// Tests `quantity_to` between mass and volume without a density
+    #[pg_test(error = "cannot convert 3 m3 to t")]
+    fn test_quantity_to_error() {
+        quantity_to(q("3 m3"), "t", None);
+    }
+
+    /// Tests `quantity_add`
+    #[pg_test]
+    fn test_quantity_add() {
+        assert_eq!(
+            "3750 kg",
+            quantity_add(q("1250 kg"), q("2.5 t")).to_string()
+        );
+        assert_eq!("2.5005 t", quantity_add(q("2.5 t"), q("500 g")).to_string());
+    }
+
+    /// Tests `quantity_add` with different dimensions
+    #[pg_test(error = "cannot add 1 kg and 1 l")]
+    fn test_quantity_add_error() {
+        quantity_add(q("1 kg"), q("1 l"));
+    }
+
+    /// Tests `quantity_sub`
+    #[pg_test]
+    fn test_quantity_sub() {
+        assert_eq!("0.5 t", quantity_sub(q("1 t"), q("500 kg")).to_string());
+    }
+
+    /// Tests `quantity_mul`
+    #[pg_test]
+    fn test_quantity_mul() {
+        assert_eq!(
+            "7.5 kg",
+            quantity_mul(q("2.5 kg"), AnyNumeric::from(3)).to_string()
+        );
+    }
+
+    /// Tests `quantity_div`
+    #[pg_test]
+    fn test_quantity_div() {
+        assert_eq!(
+            "1.25 t",
+            quantity_div(q("2.5 t"), AnyNumeric::from(2)).to_string()
+        );
+    }
+
+    /// Tests `sum` and `avg` over quantities
+    #[pg_test]
+    fn test_quantity_sum_avg() {
+        let fcinfo = std::ptr::null_mut();
+        let mut state = quantity_agg_state::default();
+        for value in ["1 t", "500 kg", "1500000 g"] {
+            state = QuantitySum::state(state, q(value), fcinfo);
+        }
+        assert_eq!(
+            "3000 kg",
+            QuantitySum::finalize(state.clone(), (), fcinfo)
+                .unwrap()
+                .to_string()
+        );
+        assert_eq!(
+            "1000 kg",
+            QuantityAvg::finalize(state, (), fcinfo)
+                .unwrap()
+                .to_string()
+        );
+        assert!(QuantitySum::finalize(quantity_agg_state::default(), (), fcinfo).is_none());
+    }
+}
@@ -0,0 +1,292 @@
+//! Secure random tokens and one-time codes.
+//!
+//! Random bytes come directly from the operating system CSPRNG (`getrandom`), not from a
+//! user-space generator:
+//!
+//! ```sql
+//! select bfn.random_token(32);                  -- URL-safe invite token
+//! select bfn.random_token(16, 'base32');        -- 26 characters, case-insensitive
+//! select bfn.random_code(6);                    -- printable pickup code, e.g. 482-913
+//! ```
+use crate::hashes::HashEncoding;
+use crate::parse_strict::ParseError;
+#[allow(unused_imports)]
+use pgrx::prelude::{default, pg_extern, pg_schema, pg_test, PgSqlErrorCode};
+
+/// Crockford base 32 alphabet: digits and upper case letters without I, L, O and U.
+const CROCKFORD_ALPHABET: &[u8] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+
+/// Bitcoin base 58 alphabet: digits and letters without 0, I, O and l.
+const BASE58_ALPHABET: &str = "123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
+/// Characters of one-time codes that cannot be mistaken for each other: digits and upper case
+/// letters without 0, 1, I, L and O.
+const CODE_ALPHABET: &str = "23456789ABCDEFGHJKMNPQRSTUVWXYZ";
+
+/// Largest number of random bytes of a token.
+const MAX_TOKEN_BYTES: i32 = 1024;
+
+/// Longest one-time code.
+const MAX_CODE_LENGTH: i32 = 64;
+
+/// Returns random bytes from the operating system CSPRNG.
+pub fn random_bytes(count: usize) -> Result<Vec<u8>, ParseError> {
+    let mut bytes = vec![0u8; count];
+    getrandom::fill(&mut bytes).map_err(|e| ParseError {
+        errcode: PgSqlErrorCode::ERRCODE_SYSTEM_ERROR,
+        message: "could not generate random bytes".to_string(),
+        detail: format!(
+            "The operating system random number generator failed: {}.",
+            e
+        ),
+    })?;
+    Ok(bytes)
+}
+
+/// Returns random characters of an alphabet of 2 to 256 characters.
+///
+/// Uses rejection sampling, so every character is equally likely.
+pub fn random_chars(alphabet: &[char], count: usize) -> Result<String, ParseError> {
+    let limit = 256 - 256 % alphabet.len();
+    let mut chars = String::with_capacity(count);
+    let mut pool = Vec::new();
+    let mut drawn = 0;
+    while drawn < count {
+        if pool.is_empty() {
+            pool = random_bytes(count.max(16))?;
+        }
+        let byte = pool.pop().unwrap_or_default() as usize;
+        if byte < limit {
+            chars.push(alphabet[byte % alphabet.len()]);
+            drawn += 1;
+        }
+    }
+    Ok(chars)
+}
+
+/// Encodes bytes as Crockford base 32, without padding.
+pub fn crockford_base32(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(5) * 8);
+    let (mut buffer, mut bits) = (0u16, 0);
+    for byte in bytes {
+        buffer = (buffer << 8) | *byte as u16;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            encoded.push(CROCKFORD_ALPHABET[(buffer >> bits) as usize & 31] as char);
+        }
+    }
+    if bits > 0 {
+        encoded.push(CROCKFORD_ALPHABET[(buffer << (5 - bits)) as usize & 31] as char);
+    }
+    encoded
+}
+
+/// Checks a custom token alphabet: 2 to 256 distinct characters.
+fn check_alphabet(alphabet: &str) -> Result<Vec<char>, ParseError> {
+    let chars: Vec<char> = alphabet.chars().collect();
+    let mut distinct = chars.clone();
+    distinct.sort_unstable();
+    distinct.dedup();
+    if !(2..=256).contains(&chars.len()) || distinct.len() != chars.len() {
+        return Err(ParseError {
+            errcode: PgSqlErrorCode::ERRCODE_INVALID_PARAMETER_VALUE,
+            message: format!("invalid token alphabet: \"{}\"", alphabet),
+            detail: "The alphabet must have 2 to 256 distinct characters.".to_string(),
+        });
+    }
+    Ok(chars)
+}
+
+/// Returns the number of characters of an alphabet needed for `bytes` bytes of entropy.
+fn entropy_length(bytes: usize, alphabet_size: usize) -> usize {
+    (bytes as f64 * 8.0 / (alphabet_size as f64).log2()).ceil() as usize
+}
+
+/// Generates a token of `bytes` random bytes in an encoding.
+pub fn token(bytes: i32, encoding: &str, alphabet: Option<&str>) -> Result<String, ParseError> {
+    if !(1..=MAX_TOKEN_BYTES).contains(&bytes) {
+        return Err(ParseError {
+            errcode: PgSqlErrorCode::ERRCODE_INVALID_PARAMETER_VALUE,
+            message: format!("invalid token length: {}", bytes),
+            detail: format!(
+                "The number of random bytes must be between 1 and {}.",
+                MAX_TOKEN_BYTES
+            ),
+        });
+    }
+    let count = bytes as usize;
+    match encoding.trim().to_lowercase().as_str() {
+        "hex" => Ok(HashEncoding::Hex.encode(&random_bytes(count)?)),
+        "base64url" => Ok(HashEncoding::Base64Url.encode(&random_bytes(count)?)),
+        "base32" => Ok(crockford_base32(&random_bytes(count)?)),
+        "base58" => {
+            let alphabet: Vec<char> = BASE58_ALPHABET.chars().collect();
+            random_chars(&alphabet, entropy_length(count, alphabet.len()))
+        }
+        "custom" => {
+            let alphabet = check_alphabet(alphabet.unwrap_or_default())?;
+            random_chars(&alphabet, entropy_length(count, alphabet.len()))
+        }
+        _ => Err(ParseError {
+            errcode: PgSqlErrorCode::ERRCODE_INVALID_PARAMETER_VALUE,
+            message: format!("unknown token encoding: \"{}\"", encoding),
+            detail: "Supported encodings are hex, base64url, base32, base58 and custom."
+                .to_string(),
+        }),
+    }
+}
+
+/// Generates a one-time code with hyphens between groups.
+pub fn code(length: i32, charset: &str, group_size: i32) -> Result<String, ParseError> {
+    if !(1..=MAX_CODE_LENGTH).contains(&length) || group_size < 0 {
+        return Err(ParseError {
+            errcode: PgSqlErrorCode::ERRCODE_INVALID_PARAMETER_VALUE,
+            message: format!(
+                "invalid code length: {} (group size {})",
+                length, group_size
+            ),
+            detail: format!(
+                "The length must be between 1 and {} and the group size 0 or more.",
+                MAX_CODE_LENGTH
+            ),
+        });
+    }
+    let alphabet: Vec<char> = match charset.trim().to_lowercase().as_str() {
+        "digits" => ('0'..='9').collect(),
+        "alphanumeric" => CODE_ALPHABET.chars().collect(),
+        _ => {
+            return Err(ParseError {
+                errcode: PgSqlErrorCode::ERRCODE_INVALID_PARAMETER_VALUE,
+                message: format!("unknown code charset: \"{}\"", charset),
+                detail: "Supported charsets are digits and alphanumeric.".to_string(),
+            })
+        }
+    };
+    let chars: Vec<char> = random_chars(&alphabet, length as usize)?.chars().collect();
+    if group_size == 0 {
+        return Ok(chars.into_iter().collect());
+    }
+    Ok(chars
+        .chunks(group_size as usize)
+        .map(|group| group.iter().collect::<String>())
+        .collect::<Vec<_>>()
+        .join("-"))
+}
+
+/// Generates a secure random token, e.g. for invitations or API keys.
+///
+/// # Parameters
+/// - `bytes`: The number of random bytes (entropy) of the token, 1 to 1024, e.g. 32.
+/// - `encoding`: One of:
+///   - `base64url` (default): URL-safe base64 without padding.
+///   - `hex`: Lower case hex.
+///   - `base32`: Crockford base 32, upper case, without padding.
+///   - `base58`: Bitcoin base 58, without look-alike characters.
+///   - `custom`: Characters of `alphabet`.
+/// - `alphabet`: The 2 to 256 distinct characters of a `custom` token; ignored otherwise.
+///
+/// # Returns
+/// - The token. `base58` and `custom` tokens have as many characters, each equally likely, as
+///   are needed for at least `bytes` bytes of entropy, e.g. 44 base58 characters for 32 bytes.
+///
+/// # Errors
+/// - If `bytes` is out of range, the encoding is unknown or the alphabet is invalid.
+/// - If the operating system cannot provide random bytes.
+#[pg_extern(create_or_replace)]
+pub fn random_token(
+    bytes: i32,
+    encoding: default!(&str, "'base64url'"),
+    alphabet: default!(Option<&str>, "NULL"),
+) -> String {
+    token(bytes, encoding, alphabet).unwrap_or_else(|e| e.report())
+}
+
+/// Generates a human-friendly one-time code, e.g. for printed pickup codes.
+///
+/// # Parameters
+/// - `length`: The number of characters without separators, 1 to 64, default 6.
+/// - `charset`: `digits` (default) for 0 to 9, or `alphanumeric` for digits and upper case
+///   letters without the look-alikes 0, 1, I, L and O.
+/// - `group_size`: The number of characters between hyphens, default 3; `0` for no hyphens.
+///
+/// # Returns
+/// - The code, e.g. `482-913` or, with `alphanumeric` and length 8, `K7QM-4XRD`.
+///
+/// # Errors
+/// - If the length or the group size is out of range, or the charset is unknown.
+/// - If the operating system cannot provide random bytes.
+#[pg_extern(create_or_replace)]
+pub fn random_code(
+    length: default!(i32, 6),
+    charset: default!(&str, "'digits'"),
+    group_size: default!(i32, 3),
+) -> String {
+    code(length, charset, group_size).unwrap_or_else(|e| e.report())
+}
+
+#[cfg(any(test, feature = "pg_test"))]
+#[pg_schema]
+mod tests {
+    use super::*;
+
+    /// Tests `random_token`
+    #[pg_test]
+    fn test_random_token() {
+        let token = random_token(32, "base64url", None);
+        assert_eq!(43, token.len());
+        assert!(token
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'));
+        assert_ne!(token, random_token(32, "base64url", None));
+        let hex = random_token(16, "HEX", None);
+        assert_eq!(32, hex.len());
+        assert!(hex
+            .chars()
+            .all(|c| c.is_ascii_hexdigit() && !c.is_uppercase()));
+        let base32 = random_token(16, "base32", None);
+        assert_eq!(26, base32.len());
+        assert!(base32.bytes().all(|c| CROCKFORD_ALPHABET.contains(&c)));
+        let base58 = random_token(32, "base58", None);
+        assert_eq!(44, base58.len());
+        assert!(base58.chars().all(|c| BASE58_ALPHABET.contains(c)));
+        let custom = random_token(4, "custom", Some("ab"));
+        assert_eq!(32, custom.len());
+        assert!(custom.chars().all(|c| c == 'a' || c == 'b'));
+        assert_eq!("04106", crockford_base32(&[1, 2, 3]));
+    }
+
+    /// Tests `random_token`
+    #[pg_test(error = "invalid token alphabet: \"abca\"")]
+    fn test_random_token_alphabet() {
+        random_token(16, "custom", Some("abca"));
+    }
+
+    /// Tests `random_token`
+    #[pg_test(error = "invalid token length: 0")]
+    fn test_random_token_length() {
+        random_token(0, "hex", None);
+    }
+
+    /// Tests `random_code`
+    #[pg_test]
+    fn test_random_code() {
+        let code = random_code(6, "digits", 3);
+        assert_eq!(7, code.len());
+        assert_eq!(Some('-'), code.chars().nth(3));
+        assert!(code.replace('-', "").chars().all(|c| c.is_ascii_digit()));
+        let code = random_code(10, "alphanumeric", 4);
+        assert_eq!(3, code.split('-').count());
+        assert!(code
+            .replace('-', "")
+            .chars()
+            .all(|c| CODE_ALPHABET.contains(c)));
+        assert_eq!(8, random_code(8, "digits", 0).len());
+    }
+
+    /// Tests `random_code`
+    #[pg_test(error = "unknown code charset: \"emoji\"")]
+    fn test_random_code_charset() {
+        random_code(6, "emoji", 3);
+    }
+}
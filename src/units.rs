@@ -0,0 +1,646 @@
+//! Unit conversion with a built-in registry of SI and imperial/US units of mass, volume, area,
+//! length and energy, extended by custom units in the `bfn.unit_registry` table.
+//!
+//! Unlike `metric_scale_down` and `metric_scale_up`, `convert_unit` knows the units involved
+//! and raises an error for unknown units and conversions between different dimensions.
+//!
+//! ```sql
+//! select bfn.convert_unit(2.5, 't', 'kg');                  -- 2500
+//! select bfn.convert_unit(1, 'MWh', 'GJ');                  -- 3.6
+//! insert into bfn.unit_registry (symbol, dimension, factor) values ('bag', 'mass', 25);
+//! select bfn.convert_unit(40, 'bag', 't');                  -- 1
+//! ```
+use crate::extension_schema;
+use crate::parse_strict::ParseError;
+#[allow(unused_imports)]
+use pgrx::prelude::{
+    extension_sql, pg_extern, pg_schema, pg_test, AnyNumeric, IntoDatum, PgBuiltInOids,
+    PgSqlErrorCode, Spi,
+};
+use pgrx::spi::quote_identifier;
+
+/// Unit prefixes: symbol, name and factor.
+const PREFIXES: [(&str, &str, &str); 4] = [
+    ("m", "milli", "0.001"),
+    ("k", "kilo", "1000"),
+    ("M", "mega", "1000000"),
+    ("G", "giga", "1000000000"),
+];
+
+/// Built-in unit.
+struct BuiltinUnit {
+    /// Symbols, the first one canonical. Matched case-sensitively.
+    symbols: &'static [&'static str],
+    /// Names, matched case-insensitively.
+    names: &'static [&'static str],
+    dimension: &'static str,
+    /// Size of the unit in the base unit of the dimension.
+    factor: &'static str,
+    /// Symbols of the prefixes the unit can be combined with.
+    prefixes: &'static [&'static str],
+}
+
+/// Built-in units.
+const BUILTIN_UNITS: [BuiltinUnit; 38] = [
+    // Mass
+    BuiltinUnit {
+        symbols: &["g"],
+        names: &["gram", "grams", "gramme", "grammes"],
+        dimension: "mass",
+        factor: "0.001",
+        prefixes: &["m", "k", "M", "G"],
+    },
+    BuiltinUnit {
+        symbols: &["t"],
+        names: &["tonne", "tonnes", "metric ton", "metric tons"],
+        dimension: "mass",
+        factor: "1000",
+        prefixes: &["k", "M", "G"],
+    },
+    BuiltinUnit {
+        symbols: &["lb", "lbs"],
+        names: &["pound", "pounds"],
+        dimension: "mass",
+        factor: "0.45359237",
+        prefixes: &[],
+    },
+    BuiltinUnit {
+        symbols: &["oz"],
+        names: &["ounce", "ounces"],
+        dimension: "mass",
+        factor: "0.028349523125",
+        prefixes: &[],
+    },
+    BuiltinUnit {
+        symbols: &["st"],
+        names: &["stone", "stones"],
+        dimension: "mass",
+        factor: "6.35029318",
+        prefixes: &[],
+    },
+    BuiltinUnit {
+        symbols: &["ton_us"],
+        names: &["short ton", "short tons", "us ton", "us tons"],
+        dimension: "mass",
+        factor: "907.18474",
+        prefixes: &[],
+    },
+    BuiltinUnit {
+        symbols: &["ton_uk"],
+        names: &["long ton", "long tons", "imperial ton", "imperial tons"],
+        dimension: "mass",
+        factor: "1016.0469088",
+        prefixes: &[],
+    },
+    // Volume
+    BuiltinUnit {
+        symbols: &["m3", "m³"],
+        names: &["cubic metre", "cubic metres", "cubic meter", "cubic meters"],
+        dimension: "volume",
+        factor: "1",
+        prefixes: &[],
+    },
+    BuiltinUnit {
+        symbols: &["dm3", "dm³"],
+        names: &[],
+        dimension: "volume",
+        factor: "0.001",
+        prefixes: &[],
+    },
+    BuiltinUnit {
+        symbols: &["cm3", "cm³", "cc"],
+        names: &[],
+        dimension: "volume",
+        factor: "0.000001",
+        prefixes: &[],
+    },
+    BuiltinUnit {
+        symbols: &["l", "L"],
+        names: &["litre", "litres", "liter", "liters"],
+        dimension: "volume",
+        factor: "0.001",
+        prefixes: &["m", "k", "M"],
+    },
+    BuiltinUnit {
+        symbols: &["gal", "gal_us"],
+        names: &["gallon", "gallons", "us gallon", "us gallons"],
+        dimension: "volume",
+        factor: "0.003785411784",
+        prefixes: &[],
+    },
+    BuiltinUnit {
+        symbols: &["gal_uk"],
+        names: &["imperial gallon", "imperial gallons"],
+        dimension: "volume",
+        factor: "0.00454609",
+        prefixes: &[],
+    },
+    BuiltinUnit {
+        symbols: &["bbl"],
+        names: &["barrel", "barrels"],
+        dimension: "volume",
+        factor: "0.158987294928",
+        prefixes: &[],
+    },
+    BuiltinUnit {
+        symbols: &["ft3", "ft³"],
+        names: &["cubic foot", "cubic feet"],
+        dimension: "volume",
+        factor: "0.028316846592",
+        prefixes: &[],
+    },
+    BuiltinUnit {
+        symbols: &["yd3", "yd³"],
+        names: &["cubic yard", "cubic yards"],
+        dimension: "volume",
+        factor: "0.764554857984",
+        prefixes: &[],
+    },
+    // Area
+    BuiltinUnit {
+        symbols: &["m2", "m²"],
+        names: &[
+            "square metre",
+            "square metres",
+            "square meter",
+            "square meters",
+        ],
+        dimension: "area",
+        factor: "1",
+        prefixes: &[],
+    },
+    BuiltinUnit {
+        symbols: &["cm2", "cm²"],
+        names: &[],
+        dimension: "area",
+        factor: "0.0001",
+        prefixes: &[],
+    },
+    BuiltinUnit {
+        symbols: &["km2", "km²"],
+        names: &[
+            "square kilometre",
+            "square kilometres",
+            "square kilometer",
+            "square kilometers",
+        ],
+        dimension: "area",
+        factor: "1000000",
+        prefixes: &[],
+    },
+    BuiltinUnit {
+        symbols: &["a"],
+        names: &["are", "ares"],
+        dimension: "area",
+        factor: "100",
+        prefixes: &[],
+    },
+    BuiltinUnit {
+        symbols: &["ha"],
+        names: &["hectare", "hectares"],
+        dimension: "area",
+        factor: "10000",
+        prefixes: &[],
+    },
+    BuiltinUnit {
+        symbols: &["ft2", "ft²", "sq ft"],
+        names: &["square foot", "square feet"],
+        dimension: "area",
+        factor: "0.09290304",
+        prefixes: &[],
+    },
+    BuiltinUnit {
+        symbols: &["yd2", "yd²"],
+        names: &["square yard", "square yards"],
+        dimension: "area",
+        factor: "0.83612736",
+        prefixes: &[],
+    },
+    BuiltinUnit {
+        symbols: &["ac"],
+        names: &["acre", "acres"],
+        dimension: "area",
+        factor: "4046.8564224",
+        prefixes: &[],
+    },
+    BuiltinUnit {
+        symbols: &["mi2", "mi²"],
+        names: &["square mile", "square miles"],
+        dimension: "area",
+        factor: "2589988.110336",
+        prefixes: &[],
+    },
+    // Length
+    BuiltinUnit {
+        symbols: &["m"],
+        names: &["metre", "metres", "meter", "meters"],
+        dimension: "length",
+        factor: "1",
+        prefixes: &["m", "k"],
+    },
+    BuiltinUnit {
+        symbols: &["cm"],
+        names: &["centimetre", "centimetres", "centimeter", "centimeters"],
+        dimension: "length",
+        factor: "0.01",
+        prefixes: &[],
+    },
+    BuiltinUnit {
+        symbols: &["in"],
+        names: &["inch", "inches"],
+        dimension: "length",
+        factor: "0.0254",
+        prefixes: &[],
+    },
+    BuiltinUnit {
+        symbols: &["ft"],
+        names: &["foot", "feet"],
+        dimension: "length",
+        factor: "0.3048",
+        prefixes: &[],
+    },
+    BuiltinUnit {
+        symbols: &["yd"],
+        names: &["yard", "yards"],
+        dimension: "length",
+        factor: "0.9144",
+        prefixes: &[],
+    },
+    BuiltinUnit {
+        symbols: &["mi"],
+        names: &["mile", "miles"],
+        dimension: "length",
+        factor: "1609.344",
+        prefixes: &[],
+    },
+    BuiltinUnit {
+        symbols: &["nmi"],
+        names: &["nautical mile", "nautical miles"],
+        dimension: "length",
+        factor: "1852",
+        prefixes: &[],
+    },
+    // Energy
+    BuiltinUnit {
+        symbols: &["J"],
+        names: &["joule", "joules"],
+        dimension: "energy",
+        factor: "1",
+        prefixes: &["m", "k", "M", "G"],
+    },
+    BuiltinUnit {
+        symbols: &["Wh"],
+        names: &["watt hour", "watt hours", "watt-hour", "watt-hours"],
+        dimension: "energy",
+        factor: "3600",
+        prefixes: &["m", "k", "M", "G"],
+    },
+    BuiltinUnit {
+        symbols: &["cal"],
+        names: &["calorie", "calories"],
+        dimension: "energy",
+        factor: "4.184",
+        prefixes: &["k"],
+    },
+    BuiltinUnit {
+        symbols: &["BTU", "Btu"],
+        names: &["british thermal unit", "british thermal units"],
+        dimension: "energy",
+        factor: "1055.05585262",
+        prefixes: &[],
+    },
+    BuiltinUnit {
+        symbols: &["thm"],
+        names: &["therm", "therms"],
+        dimension: "energy",
+        factor: "105480400",
+        prefixes: &[],
+    },
+    // Count
+    BuiltinUnit {
+        symbols: &["pcs", "pc"],
+        names: &["piece", "pieces"],
+        dimension: "count",
+        factor: "1",
+        prefixes: &[],
+    },
+];
+
+/// Base units of the built-in dimensions, of factor 1.
+const BASE_UNITS: [(&str, &str); 6] = [
+    ("mass", "kg"),
+    ("volume", "m3"),
+    ("area", "m2"),
+    ("length", "m"),
+    ("energy", "J"),
+    ("count", "pcs"),
+];
+
+/// Unit resolved from the built-in registry or `bfn.unit_registry`.
+#[derive(Clone, Debug)]
+pub struct Unit {
+    /// Canonical symbol, e.g. `kWh` for `kilowatt hours`.
+    pub symbol: String,
+    /// `mass`, `volume`, `area`, `length`, `energy`, `count` or a custom dimension.
+    pub dimension: String,
+    /// Size of the unit in the base unit of the dimension.
+    pub factor: AnyNumeric,
+}
+
+/// Converts a decimal constant to numeric.
+fn numeric(value: &str) -> AnyNumeric {
+    AnyNumeric::try_from(value).unwrap()
+}
+
+/// Looks up a built-in unit by symbol or name, with an optional prefix (e.g. `kWh`, `kilogram`).
+///
+/// Symbols are case-sensitive, as `mWh` and `MWh` differ by a factor of a billion. Only if no
+/// symbol matches exactly, symbols without the `m`/`M` prefixes match case-insensitively,
+/// so `KG` is found, but `MT` is not.
+pub fn find_builtin_unit(name: &str) -> Option<Unit> {
+    let name = name.trim();
+    let lower = name.to_lowercase();
+    let unit = |u: &BuiltinUnit, prefix: Option<&(&str, &str, &str)>| Unit {
+        symbol: format!("{}{}", prefix.map_or("", |p| p.0), u.symbols[0]),
+        dimension: u.dimension.to_string(),
+        factor: match prefix {
+            Some(p) => numeric(u.factor) * numeric(p.2),
+            None => numeric(u.factor),
+        },
+    };
+    // Exact symbol or name
+    for u in BUILTIN_UNITS.iter() {
+        if u.symbols.contains(&name) || u.names.contains(&lower.as_str()) {
+            return Some(unit(u, None));
+        }
+    }
+    // Prefixed symbol or name
+    for u in BUILTIN_UNITS.iter() {
+        for p in PREFIXES.iter().filter(|p| u.prefixes.contains(&p.0)) {
+            let symbol = name
+                .strip_prefix(p.0)
+                .is_some_and(|s| u.symbols.contains(&s));
+            let word = lower
+                .strip_prefix(p.1)
+                .is_some_and(|s| u.names.contains(&s));
+            if symbol || word {
+                return Some(unit(u, Some(p)));
+            }
+        }
+    }
+    // Symbol in other case, unless the case decides between milli and mega
+    for u in BUILTIN_UNITS.iter() {
+        if u.symbols.iter().any(|s| s.eq_ignore_ascii_case(name)) {
+            return Some(unit(u, None));
+        }
+        for p in PREFIXES
+            .iter()
+            .filter(|p| u.prefixes.contains(&p.0) && !p.0.eq_ignore_ascii_case("m"))
+        {
+            if u.symbols
+                .iter()
+                .any(|s| format!("{}{}", p.0, s).eq_ignore_ascii_case(name))
+            {
+                return Some(unit(u, Some(p)));
+            }
+        }
+    }
+    None
+}
+
+/// Returns the base unit of a built-in dimension, e.g. `kg` for `mass`.
+pub fn base_unit(dimension: &str) -> Option<&'static str> {
+    BASE_UNITS
+        .iter()
+        .find(|(d, _)| *d == dimension)
+        .map(|(_, symbol)| *symbol)
+}
+
+/// Returns the qualified name of the `unit_registry` table, in the schema of the extension.
+pub(crate) fn unit_registry_table() -> Option<String> {
+    let schema = extension_schema()?;
+    Some(format!("{}.unit_registry", quote_identifier(schema)))
+}
+
+/// Looks up a custom unit in the `unit_registry` table.
+///
+/// Returns `None` if the table has no such unit, and an error if the table cannot be read.
+fn find_custom_unit(name: &str) -> Result<Option<Unit>, ParseError> {
+    let Some(table) = unit_registry_table() else {
+        return Ok(None);
+    };
+    let query = format!("select dimension, factor from {} where symbol = $1", table);
+    let row = Spi::connect(|client| {
+        let rows = client.select(
+            &query,
+            Some(1),
+            Some(vec![(
+                PgBuiltInOids::TEXTOID.oid(),
+                name.trim().into_datum(),
+            )]),
+        )?;
+        if rows.is_empty() {
+            return Ok(None);
+        }
+        rows.first().get_two::<String, AnyNumeric>().map(Some)
+    })
+    .map_err(|e| ParseError {
+        errcode: PgSqlErrorCode::ERRCODE_INTERNAL_ERROR,
+        message: format!("could not look up unit: \"{}\"", name),
+        detail: format!("Reading {} failed: {}.", table, e),
+    })?;
+    Ok(match row {
+        Some((Some(dimension), Some(factor))) => Some(Unit {
+            symbol: name.trim().to_string(),
+            dimension,
+            factor,
+        }),
+        _ => None,
+    })
+}
+
+/// Looks up a unit, built-in units first, then `bfn.unit_registry`.
+pub fn find_unit(name: &str) -> Result<Unit, ParseError> {
+    if let Some(unit) = find_builtin_unit(name) {
+        return Ok(unit);
+    }
+    find_custom_unit(name)?.ok_or_else(|| ParseError {
+        errcode: PgSqlErrorCode::ERRCODE_INVALID_PARAMETER_VALUE,
+        message: format!("unknown unit: \"{}\"", name),
+        detail: format!(
+            "Unit symbols are case-sensitive (e.g. mWh and MWh). \
+            Register custom units in {}.",
+            unit_registry_table().unwrap_or_else(|| "unit_registry".to_string())
+        ),
+    })
+}
+
+/// Converts a value between units of the same dimension.
+pub fn convert(value: AnyNumeric, from: &str, to: &str) -> Result<AnyNumeric, ParseError> {
+    let from = find_unit(from)?;
+    let to = find_unit(to)?;
+    if from.dimension != to.dimension {
+        return Err(ParseError {
+            errcode: PgSqlErrorCode::ERRCODE_INVALID_PARAMETER_VALUE,
+            message: format!("cannot convert {} to {}", from.symbol, to.symbol),
+            detail: format!(
+                "{} is a unit of {} and {} is a unit of {}.",
+                from.symbol, from.dimension, to.symbol, to.dimension
+            ),
+        });
+    }
+    let value = value * from.factor / to.factor;
+    Ok(numeric(value.normalize()))
+}
+
+extension_sql!(
+    r#"
+CREATE TABLE unit_registry (
+    symbol text PRIMARY KEY,
+    dimension text NOT NULL,
+    factor numeric NOT NULL CHECK (factor > 0),
+    description text
+);
+COMMENT ON TABLE unit_registry IS 'Custom units for convert_unit. factor is the size of the unit '
+    'in the base unit of the dimension (mass: kg, volume: m3, area: m2, length: m, energy: J, '
+    'count: pcs).';
+SELECT pg_catalog.pg_extension_config_dump('unit_registry', '');
+"#,
+    name = "unit_registry"
+);
+
+/// Converts a value from one unit to another.
+///
+/// Built-in units cover mass (`g`, `t`, `lb`, `oz`, `st`, `ton_us`, `ton_uk`),
+/// volume (`m3`, `dm3`, `cm3`, `l`, `gal`, `gal_uk`, `bbl`, `ft3`, `yd3`),
+/// area (`m2`, `cm2`, `km2`, `a`, `ha`, `ft2`, `yd2`, `ac`, `mi2`),
+/// length (`m`, `cm`, `in`, `ft`, `yd`, `mi`, `nmi`),
+/// energy (`J`, `Wh`, `cal`, `BTU`, `thm`) and count (`pcs`),
+/// also by name (e.g. `tonnes`, `square feet`).
+/// `g`, `t`, `l`, `m`, `J`, `Wh` and `cal` take the prefixes `m`/`milli`, `k`/`kilo`,
+/// `M`/`mega` and `G`/`giga` where meaningful, e.g. `kg`, `Mt`, `ml`, `km`, `GJ`, `kWh`, `kcal`.
+///
+/// Custom units are added to the table `bfn.unit_registry` with their dimension and their size
+/// in the base unit of the dimension (`kg`, `m3`, `m2`, `m`, `J`, `pcs`). Custom dimensions are
+/// allowed. Built-in units take precedence over custom units with the same symbol.
+///
+/// # Parameters
+/// - `value`: The value to convert.
+/// - `from_unit`: The unit of `value`.
+/// - `to_unit`: The unit to convert to.
+///
+/// # Returns
+/// The converted value, e.g. `2500` for `2.5`, `t`, `kg`.
+///
+/// # Errors
+/// - `22023` (`invalid_parameter_value`) if a unit is unknown or the units are of different
+///   dimensions.
+/// - `XX000` (`internal_error`) if `bfn.unit_registry` cannot be read.
+#[pg_extern(stable, parallel_safe, create_or_replace)]
+pub fn convert_unit(value: AnyNumeric, from_unit: &str, to_unit: &str) -> AnyNumeric {
+    convert(value, from_unit, to_unit).unwrap_or_else(|e| e.report())
+}
+
+/// Returns the dimension of a unit.
+///
+/// # Parameters
+/// - `unit`: A built-in unit or a unit in `bfn.unit_registry`.
+///
+/// # Returns
+/// - `mass`, `volume`, `area`, `length`, `energy`, `count` or the dimension of a custom unit.
+/// - `null` if the unit is unknown.
+///
+/// # Errors
+/// - `XX000` (`internal_error`) if `bfn.unit_registry` cannot be read.
+#[pg_extern(stable, parallel_safe, create_or_replace)]
+pub fn unit_dimension(unit: &str) -> Option<String> {
+    let unit = match find_builtin_unit(unit) {
+        Some(unit) => Some(unit),
+        None => find_custom_unit(unit).unwrap_or_else(|e| e.report()),
+    };
+    unit.map(|u| u.dimension)
+}
+
+#[cfg(any(test, feature = "pg_test"))]
+#[pg_schema]
+mod tests {
+    use super::*;
+
+    fn convert_str(value: &str, from_unit: &str, to_unit: &str) -> String {
+        convert_unit(numeric(value), from_unit, to_unit).to_string()
+    }
+
+    /// Tests `find_builtin_unit`
+    #[pg_test]
+    fn test_find_builtin_unit() {
+        assert_eq!("kg", find_builtin_unit("kilogram").unwrap().symbol);
+        assert_eq!("kWh", find_builtin_unit("KWH").unwrap().symbol);
+        assert_eq!("Mt", find_builtin_unit("Mt").unwrap().symbol);
+        assert_eq!("l", find_builtin_unit("Litres").unwrap().symbol);
+        assert!(find_builtin_unit("MT").is_none());
+        assert!(find_builtin_unit("mt").is_none());
+        assert!(find_builtin_unit("kft").is_none());
+    }
+
+    /// Tests `convert_unit`
+    #[pg_test]
+    fn test_convert_unit() {
+        assert_eq!("2500", convert_str("2.5", "t", "kg"));
+        assert_eq!("0.5", convert_str("500", "g", "kg"));
+        assert_eq!("3.6", convert_str("1", "MWh", "GJ"));
+        assert_eq!("1000", convert_str("1", "m3", "L"));
+        assert_eq!("0.45359237", convert_str("1", "lb", "kg"));
+        assert_eq!("2.54", convert_str("1", "in", "cm"));
+        assert_eq!("1", convert_str("10000", "m²", "hectares"));
+        assert_eq!("3.785411784", convert_str("1", "gallon", "l"));
+        assert_eq!("4.184", convert_str("1", "kcal", "kJ"));
+    }
+
+    /// Tests `convert_unit` with an unknown unit
+    #[pg_test(error = "unknown unit: \"parsec\"")]
+    fn test_convert_unit_unknown() {
+        convert_str("1", "parsec", "m");
+    }
+
+    /// Tests `convert_unit` between dimensions
+    #[pg_test(error = "cannot convert kg to l")]
+    fn test_convert_unit_dimension() {
+        convert_str("1", "kg", "l");
+    }
+
+    /// Tests `convert_unit` with a custom unit
+    #[pg_test]
+    fn test_convert_unit_custom() {
+        Spi::run(&format!(
+            "insert into {} (symbol, dimension, factor)
+            values ('bag', 'mass', 25), ('box', 'count', 40)",
+            unit_registry_table().unwrap()
+        ))
+        .unwrap();
+        assert_eq!("1", convert_str("40", "bag", "t"));
+        assert_eq!("2", convert_str("80", "pcs", "box"));
+        assert_eq!(Some("count".to_string()), unit_dimension("box"));
+    }
+
+    /// Tests `convert_unit` with a `unit_registry` table that cannot be read
+    #[pg_test(error = "could not look up unit: \"sack\"")]
+    fn test_convert_unit_custom_error() {
+        let table = unit_registry_table().unwrap();
+        Spi::run(&format!(
+            "alter table {0} rename column factor to old_factor;
+            alter table {0} add column factor text;
+            insert into {0} (symbol, dimension, old_factor, factor) values ('sack', 'mass', 25, 'x')",
+            table
+        ))
+        .unwrap();
+        convert_str("1", "sack", "kg");
+    }
+
+    /// Tests `unit_dimension`
+    #[pg_test]
+    fn test_unit_dimension() {
+        assert_eq!(Some("energy".to_string()), unit_dimension("BTU"));
+        assert_eq!(Some("area".to_string()), unit_dimension("acres"));
+        assert_eq!(None, unit_dimension("parsec"));
+    }
+}
@@ -0,0 +1,488 @@
+//! UUID generation and introspection: time-ordered (v7) ids for backfilling and bulk loading,
+//! and name-based (v3, v5 and v8) ids derived from stable names of external entities.
+//!
+//! `new_uuid_at` embeds a given time, so historical rows get ids that sort by their original
+//! creation time. `new_uuid_monotonic` guarantees that ids created by one backend are strictly
+//! increasing, even when many are created in the same millisecond:
+//!
+//! ```sql
+//! update waste_transfer set id = bfn.new_uuid_at(created_at) where id is null;
+//! insert into waste_transfer (id, ...) select bfn.new_uuid_monotonic(), ... from staging;
+//! ```
+//!
+//! The creation time of v1, v6 and v7 UUIDs, and of ULIDs stored as uuid, can be read back:
+//!
+//! ```sql
+//! select bfn.uuid_to_tstz('c232ab00-9414-11ec-b3c8-9f6bdeced846'); -- 2022-02-22 19:22:22+00
+//! select bfn.uuid_version(id), bfn.uuid_variant(id) from waste_transfer;
+//! ```
+//!
+//! Name-based UUIDs are the same for the same namespace and name in every system that follows
+//! RFC 9562:
+//!
+//! ```sql
+//! select bfn.uuid_v5('dns', 'www.example.com'); -- 2ed6657d-e927-568b-95e1-2665a8aea6a2
+//! select bfn.uuid_v5(bfn.uuid_v5('url', 'https://example.com/registry'), 'EE-12345');
+//! ```
+use crate::parse_strict::ParseError;
+#[allow(unused_imports)]
+use pgrx::prelude::{
+    default, pg_extern, pg_schema, pg_test, PgSqlErrorCode, TimestampWithTimeZone,
+};
+use rand::Rng;
+use sha2::{Digest, Sha256};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Milliseconds between the Unix epoch and the PostgreSQL epoch (2000-01-01).
+const PG_EPOCH_MS: i64 = 946_684_800_000;
+
+/// Microseconds between the Unix epoch and the Gregorian epoch of UUIDv1 and v6 (1582-10-15).
+const GREGORIAN_OFFSET_MICROS: i64 = 12_219_292_800_000_000;
+
+/// Largest Unix timestamp a UUIDv7 can hold: 48 bits of milliseconds.
+const MAX_UNIX_MS: i64 = (1 << 48) - 1;
+
+/// Length of the monotonic counter in rand_a and rand_b, the maximum allowed by RFC 9562.
+const COUNTER_BITS: u32 = 42;
+
+/// Last timestamp and counter of the monotonic generator of this backend.
+static MONOTONIC_STATE: Mutex<(u64, u64)> = Mutex::new((0, 0));
+
+/// Converts a timestamp to milliseconds since the Unix epoch, as stored in a UUIDv7.
+pub fn unix_millis(ts: TimestampWithTimeZone) -> Result<u64, ParseError> {
+    let micros = ts.into_inner();
+    let millis = micros.div_euclid(1000).checked_add(PG_EPOCH_MS);
+    match millis {
+        Some(ms) if ts.is_finite() && (0..=MAX_UNIX_MS).contains(&ms) => Ok(ms as u64),
+        _ => Err(ParseError {
+            errcode: PgSqlErrorCode::ERRCODE_DATETIME_FIELD_OVERFLOW,
+            message: "timestamp out of range for UUIDv7".to_string(),
+            detail: "The timestamp must be between 1970-01-01 and 10889-08-02 UTC.".to_string(),
+        }),
+    }
+}
+
+/// Extracts the creation time of a UUID in microseconds since the Unix epoch.
+///
+/// UUIDv1 and v6 hold 100 nanosecond intervals since 1582-10-15, which are truncated to
+/// microseconds. UUIDv7 and ULIDs hold milliseconds in their leading 48 bits; ULIDs have no
+/// version bits, so they are only recognised when `ulid` is set.
+pub fn uuid_unix_micros(bytes: &[u8; 16], ulid: bool) -> Option<i64> {
+    let be = |range: std::ops::Range<usize>| {
+        bytes[range]
+            .iter()
+            .fold(0i64, |acc, b| (acc << 8) | *b as i64)
+    };
+    let version = bytes[6] >> 4;
+    if ulid || version == 7 {
+        return Some(be(0..6) * 1000);
+    }
+    let ticks = match version {
+        1 => ((be(6..8) & 0x0FFF) << 48) | (be(4..6) << 32) | be(0..4),
+        6 => (be(0..4) << 28) | (be(4..6) << 12) | (be(6..8) & 0x0FFF),
+        _ => return None,
+    };
+    Some(ticks / 10 - GREGORIAN_OFFSET_MICROS)
+}
+
+/// Resolves a namespace of name-based UUIDs.
+///
+/// Accepts the standard namespaces `dns`, `url`, `oid` and `x500` (case-insensitive), or any
+/// UUID as a custom namespace.
+pub fn namespace(value: &str) -> Result<uuid::Uuid, ParseError> {
+    match value.trim().to_lowercase().as_str() {
+        "dns" => Ok(uuid::Uuid::NAMESPACE_DNS),
+        "url" => Ok(uuid::Uuid::NAMESPACE_URL),
+        "oid" => Ok(uuid::Uuid::NAMESPACE_OID),
+        "x500" => Ok(uuid::Uuid::NAMESPACE_X500),
+        custom => uuid::Uuid::parse_str(custom).map_err(|_| ParseError {
+            errcode: PgSqlErrorCode::ERRCODE_INVALID_PARAMETER_VALUE,
+            message: format!("unknown UUID namespace: \"{}\"", value),
+            detail: "Use dns, url, oid, x500 or a UUID as a custom namespace.".to_string(),
+        }),
+    }
+}
+
+/// Builds a name-based UUIDv8 from the SHA-256 hash of a namespace and a name.
+///
+/// Follows the example of RFC 9562, appendix B.2: the leading 128 bits of the hash, with the
+/// version and variant bits set.
+pub fn uuid_v8_sha256(namespace: &uuid::Uuid, name: &[u8]) -> uuid::Uuid {
+    let hash = Sha256::new()
+        .chain_update(namespace.as_bytes())
+        .chain_update(name)
+        .finalize();
+    let mut bytes = [0u8; 16];
+    bytes.copy_from_slice(&hash[..16]);
+    uuid::Builder::from_custom_bytes(bytes).into_uuid()
+}
+
+/// Builds a UUIDv7 from a Unix timestamp, a 42 bit counter and 32 random bits.
+///
+/// The counter takes the 12 bits of rand_a and the leading 30 bits of rand_b, as in method 1
+/// ("fixed bit-length dedicated counter") of RFC 9562, section 6.2.
+pub fn uuid_v7_with_counter(millis: u64, counter: u64, random: u32) -> uuid::Uuid {
+    let mut bytes = [0u8; 10];
+    bytes[0] = (counter >> 38) as u8 & 0x0F;
+    bytes[1] = (counter >> 30) as u8;
+    bytes[2] = (counter >> 24) as u8 & 0x3F;
+    bytes[3] = (counter >> 16) as u8;
+    bytes[4] = (counter >> 8) as u8;
+    bytes[5] = counter as u8;
+    bytes[6..].copy_from_slice(&random.to_be_bytes());
+    uuid::Builder::from_unix_timestamp_millis(millis, &bytes).into_uuid()
+}
+
+/// Returns the next timestamp and counter of the monotonic generator.
+///
+/// A new millisecond starts the counter at a random value with the leading bit cleared, leaving
+/// room for at least 2^41 increments. Within the same millisecond, or when the clock goes
+/// backwards, the counter is incremented; if it overflows, the timestamp is advanced by one
+/// millisecond instead.
+pub fn next_monotonic(last: (u64, u64), now_ms: u64, seed: u64) -> (u64, u64) {
+    let (last_ms, counter) = last;
+    let seed = seed & ((1 << (COUNTER_BITS - 1)) - 1);
+    if now_ms > last_ms {
+        (now_ms, seed)
+    } else if counter < (1 << COUNTER_BITS) - 1 {
+        (last_ms, counter + 1)
+    } else {
+        (last_ms + 1, seed)
+    }
+}
+
+/// Generates a Version 7 UUID (time-ordered) for a given time.
+///
+/// Useful to backfill ids of historical rows, so that they sort by their original creation time.
+///
+/// # Parameters
+/// - `ts`: The time to embed, with millisecond precision.
+///
+/// # Returns
+/// - A UUIDv7 with the timestamp of `ts` and random bits, e.g. `uuid_to_ts` returns `ts`
+///   truncated to milliseconds.
+///
+/// # Errors
+/// - If `ts` is before 1970-01-01, infinite or beyond the 48 bit range of UUIDv7.
+#[pg_extern(create_or_replace)]
+pub fn new_uuid_at(ts: TimestampWithTimeZone) -> pgrx::Uuid {
+    let millis = unix_millis(ts).unwrap_or_else(|e| e.report());
+    let mut bytes = [0u8; 10];
+    rand::rng().fill(&mut bytes);
+    let uuid_v7 = uuid::Builder::from_unix_timestamp_millis(millis, &bytes).into_uuid();
+    pgrx::Uuid::from_bytes(*uuid_v7.as_bytes())
+}
+
+/// Generates a Version 7 UUID (time-ordered) that is strictly greater than the previous one
+/// generated by the same backend.
+///
+/// Uses a 42 bit counter seeded randomly every millisecond (RFC 9562, section 6.2, method 1), so
+/// ids of rows inserted by one session sort exactly in insertion order. If the counter overflows
+/// or the clock goes backwards, the embedded time runs ahead of the clock until it catches up.
+///
+/// # Returns
+/// - A UUIDv7, greater than any UUIDv7 returned before by this function in the same backend.
+#[pg_extern(create_or_replace)]
+pub fn new_uuid_monotonic() -> pgrx::Uuid {
+    let now_ms = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64);
+    let mut rng = rand::rng();
+    let (millis, counter) = {
+        let mut state = MONOTONIC_STATE.lock().unwrap_or_else(|e| e.into_inner());
+        *state = next_monotonic(*state, now_ms, rng.random());
+        *state
+    };
+    let uuid_v7 = uuid_v7_with_counter(millis, counter, rng.random());
+    pgrx::Uuid::from_bytes(*uuid_v7.as_bytes())
+}
+
+/// Converts a time-based UUID or a ULID stored as uuid into a timestamp with time zone.
+///
+/// # Parameters
+/// - `uuid`: A UUID input.
+/// - `ulid`: Whether to read the UUID as a ULID, i.e. take its leading 48 bits as Unix
+///   milliseconds regardless of the version bits.
+///
+/// # Returns
+/// - The creation time of a v1, v6 or v7 UUID, or of a ULID. v1 and v6 times are truncated to
+///   microseconds.
+/// - `null` if the UUID is of another version and `ulid` is not set.
+#[pg_extern(immutable, parallel_safe, create_or_replace)]
+pub fn uuid_to_tstz(
+    uuid: pgrx::Uuid,
+    ulid: default!(bool, false),
+) -> Option<TimestampWithTimeZone> {
+    let micros = uuid_unix_micros(uuid.as_bytes(), ulid)?;
+    TimestampWithTimeZone::try_from(micros - PG_EPOCH_MS * 1000).ok()
+}
+
+/// Returns the version of a UUID.
+///
+/// # Parameters
+/// - `uuid`: A UUID input.
+///
+/// # Returns
+/// - The version number, e.g. `4` for a random and `7` for a time-ordered UUID.
+/// - `null` if the UUID is not of the RFC 9562 variant, e.g. the nil UUID or a ULID.
+#[pg_extern(immutable, parallel_safe, create_or_replace)]
+pub fn uuid_version(uuid: pgrx::Uuid) -> Option<i32> {
+    let bytes = uuid.as_bytes();
+    (bytes[8] & 0xC0 == 0x80).then_some((bytes[6] >> 4) as i32)
+}
+
+/// Returns the variant (layout) of a UUID.
+///
+/// # Parameters
+/// - `uuid`: A UUID input.
+///
+/// # Returns
+/// - `rfc9562` for the standard layout of RFC 9562 (formerly RFC 4122), which is used by all
+///   versions that `uuid_version` reports.
+/// - `ncs` for the reserved NCS backward compatible layout, which includes the nil UUID.
+/// - `microsoft` for the reserved Microsoft backward compatible layout.
+/// - `future` for the layout reserved for future use, which includes the max UUID.
+#[pg_extern(immutable, parallel_safe, create_or_replace)]
+pub fn uuid_variant(uuid: pgrx::Uuid) -> &'static str {
+    let bits = uuid.as_bytes()[8];
+    if bits & 0x80 == 0 {
+        "ncs"
+    } else if bits & 0x40 == 0 {
+        "rfc9562"
+    } else if bits & 0x20 == 0 {
+        "microsoft"
+    } else {
+        "future"
+    }
+}
+
+/// Generates a name-based Version 5 UUID (SHA-1).
+///
+/// The same namespace and name always give the same UUID, in any system that follows RFC 9562,
+/// e.g. `uuid_generate_v5` of the `uuid-ossp` extension.
+///
+/// # Parameters
+/// - `namespace`: A standard namespace `dns`, `url`, `oid` or `x500`, or a UUID as a custom
+///   namespace.
+/// - `name`: The name to derive the UUID from, e.g. a domain name for `dns`.
+///
+/// # Returns
+/// - A UUIDv5, e.g. `2ed6657d-e927-568b-95e1-2665a8aea6a2` for `www.example.com` in `dns`.
+///
+/// # Errors
+/// - If the namespace is neither a standard namespace nor a UUID.
+#[pg_extern(immutable, parallel_safe, create_or_replace)]
+pub fn uuid_v5(namespace: &str, name: &str) -> pgrx::Uuid {
+    let ns = self::namespace(namespace).unwrap_or_else(|e| e.report());
+    pgrx::Uuid::from_bytes(*uuid::Uuid::new_v5(&ns, name.as_bytes()).as_bytes())
+}
+
+/// Generates a name-based Version 3 UUID (MD5).
+///
+/// Prefer `uuid_v5` for new ids; v3 is for interoperability with systems that use it.
+///
+/// # Parameters
+/// - `namespace`: A standard namespace `dns`, `url`, `oid` or `x500`, or a UUID as a custom
+///   namespace.
+/// - `name`: The name to derive the UUID from.
+///
+/// # Returns
+/// - A UUIDv3, e.g. `5df41881-3aed-3515-88a7-2f4a814cf09e` for `www.example.com` in `dns`.
+///
+/// # Errors
+/// - If the namespace is neither a standard namespace nor a UUID.
+#[pg_extern(immutable, parallel_safe, create_or_replace)]
+pub fn uuid_v3(namespace: &str, name: &str) -> pgrx::Uuid {
+    let ns = self::namespace(namespace).unwrap_or_else(|e| e.report());
+    pgrx::Uuid::from_bytes(*uuid::Uuid::new_v3(&ns, name.as_bytes()).as_bytes())
+}
+
+/// Generates a name-based Version 8 UUID from a SHA-256 hash.
+///
+/// Like `uuid_v5`, but with SHA-256 instead of SHA-1, as in RFC 9562, appendix B.2.
+///
+/// # Parameters
+/// - `namespace`: A standard namespace `dns`, `url`, `oid` or `x500`, or a UUID as a custom
+///   namespace.
+/// - `name`: The name to derive the UUID from.
+///
+/// # Returns
+/// - A UUIDv8, e.g. `5c146b14-3c52-8afd-938a-375d0df1fbf6` for `www.example.com` in `dns`.
+///
+/// # Errors
+/// - If the namespace is neither a standard namespace nor a UUID.
+#[pg_extern(immutable, parallel_safe, create_or_replace)]
+pub fn uuid_v8(namespace: &str, name: &str) -> pgrx::Uuid {
+    let ns = self::namespace(namespace).unwrap_or_else(|e| e.report());
+    pgrx::Uuid::from_bytes(*uuid_v8_sha256(&ns, name.as_bytes()).as_bytes())
+}
+
+#[cfg(any(test, feature = "pg_test"))]
+#[pg_schema]
+mod tests {
+    use super::*;
+
+    /// Tests `new_uuid_at`
+    #[pg_test]
+    fn test_new_uuid_at() {
+        let ts = TimestampWithTimeZone::with_timezone(2023, 11, 26, 16, 48, 29.952, "UTC").unwrap();
+        let id = uuid::Uuid::from_bytes(*new_uuid_at(ts).as_bytes());
+        assert_eq!(7, id.get_version_num());
+        assert_eq!(
+            Some((1_701_017_309, 952_000_000)),
+            id.get_timestamp().map(|t| t.to_unix())
+        );
+        assert_ne!(new_uuid_at(ts), new_uuid_at(ts));
+        let epoch = TimestampWithTimeZone::with_timezone(1970, 1, 1, 0, 0, 0.0, "UTC").unwrap();
+        assert_eq!(&[0; 6], &new_uuid_at(epoch).as_bytes()[..6]);
+    }
+
+    /// Tests `new_uuid_at`
+    #[pg_test(error = "timestamp out of range for UUIDv7")]
+    fn test_new_uuid_at_before_epoch() {
+        let ts = TimestampWithTimeZone::with_timezone(1969, 12, 31, 23, 59, 59.0, "UTC").unwrap();
+        new_uuid_at(ts);
+    }
+
+    /// Tests `new_uuid_monotonic`
+    #[pg_test]
+    fn test_new_uuid_monotonic() {
+        let ids: Vec<_> = (0..10_000).map(|_| new_uuid_monotonic()).collect();
+        for pair in ids.windows(2) {
+            assert!(pair[0].as_bytes() < pair[1].as_bytes());
+        }
+        let id = uuid::Uuid::from_bytes(*ids[0].as_bytes());
+        assert_eq!(7, id.get_version_num());
+        assert_eq!(uuid::Variant::RFC4122, id.get_variant());
+        // Counter overflow and a clock going backwards advance the timestamp
+        let max = (1 << COUNTER_BITS) - 1;
+        assert_eq!((1001, 5), next_monotonic((1000, max), 1000, 5));
+        assert_eq!((1000, 8), next_monotonic((1000, 7), 999, 5));
+        assert_eq!((1002, 5), next_monotonic((1000, 7), 1002, 5 | 1 << 41));
+        let low = uuid_v7_with_counter(1000, max - 1, u32::MAX);
+        let high = uuid_v7_with_counter(1000, max, 0);
+        assert!(low < high);
+    }
+
+    /// Parses a UUID for the tests.
+    fn id(value: &str) -> pgrx::Uuid {
+        pgrx::Uuid::from_bytes(*uuid::Uuid::parse_str(value).unwrap().as_bytes())
+    }
+
+    /// Tests `uuid_to_tstz`
+    #[pg_test]
+    fn test_uuid_to_tstz() {
+        let micros =
+            |value: &str, ulid: bool| uuid_to_tstz(id(value), ulid).map(|t| t.into_inner());
+        // RFC 9562 test vectors of 2022-02-22 19:22:22 UTC
+        let expected = TimestampWithTimeZone::with_timezone(2022, 2, 22, 19, 22, 22.0, "UTC")
+            .unwrap()
+            .into_inner();
+        for value in [
+            "c232ab00-9414-11ec-b3c8-9f6bdeced846",
+            "1ec9414c-232a-6b00-b3c8-9f6bdeced846",
+            "017f22e2-79b0-7cc3-98c4-dc0c0c07398f",
+        ] {
+            assert_eq!(Some(expected), micros(value, false), "{}", value);
+        }
+        // ULID 01ARZ3NDEKTSV4RRFFQ69G5FAV
+        let ulid = "01563e3a-b5d3-d676-4c61-efb99302bd5b";
+        let expected = TimestampWithTimeZone::with_timezone(2016, 7, 30, 23, 54, 10.259, "UTC")
+            .unwrap()
+            .into_inner();
+        assert_eq!(Some(expected), micros(ulid, true));
+        assert_eq!(None, micros(ulid, false));
+        assert_eq!(None, micros("550e8400-e29b-41d4-a716-446655440000", false));
+        // Before the Unix epoch
+        let expected = TimestampWithTimeZone::with_timezone(1582, 10, 15, 0, 0, 0.0, "UTC")
+            .unwrap()
+            .into_inner();
+        assert_eq!(
+            Some(expected),
+            micros("00000000-0000-1000-8000-000000000000", false)
+        );
+    }
+
+    /// Tests `uuid_version`
+    #[pg_test]
+    fn test_uuid_version() {
+        assert_eq!(
+            Some(1),
+            uuid_version(id("c232ab00-9414-11ec-b3c8-9f6bdeced846"))
+        );
+        assert_eq!(
+            Some(4),
+            uuid_version(id("550e8400-e29b-41d4-a716-446655440000"))
+        );
+        assert_eq!(Some(7), uuid_version(new_uuid_monotonic()));
+        assert_eq!(
+            None,
+            uuid_version(id("00000000-0000-0000-0000-000000000000"))
+        );
+        assert_eq!(
+            None,
+            uuid_version(id("01563e3a-b5d3-d676-4c61-efb99302bd5b"))
+        );
+    }
+
+    /// Tests `uuid_variant`
+    #[pg_test]
+    fn test_uuid_variant() {
+        assert_eq!(
+            "rfc9562",
+            uuid_variant(id("017f22e2-79b0-7cc3-98c4-dc0c0c07398f"))
+        );
+        assert_eq!(
+            "ncs",
+            uuid_variant(id("00000000-0000-0000-0000-000000000000"))
+        );
+        assert_eq!(
+            "microsoft",
+            uuid_variant(id("00000000-0000-0000-c000-000000000000"))
+        );
+        assert_eq!(
+            "future",
+            uuid_variant(id("ffffffff-ffff-ffff-ffff-ffffffffffff"))
+        );
+    }
+
+    /// Tests `uuid_v5`
+    #[pg_test]
+    fn test_uuid_v5() {
+        let expected = id("2ed6657d-e927-568b-95e1-2665a8aea6a2");
+        assert_eq!(expected, uuid_v5("dns", "www.example.com"));
+        assert_eq!(expected, uuid_v5(" DNS ", "www.example.com"));
+        assert_eq!(
+            expected,
+            uuid_v5("6ba7b810-9dad-11d1-80b4-00c04fd430c8", "www.example.com")
+        );
+        assert_ne!(expected, uuid_v5("url", "www.example.com"));
+        assert_eq!(Some(5), uuid_version(uuid_v5("oid", "1.3.6.1")));
+    }
+
+    /// Tests `uuid_v5`
+    #[pg_test(error = "unknown UUID namespace: \"isbn\"")]
+    fn test_uuid_v5_unknown_namespace() {
+        uuid_v5("isbn", "978-3-16-148410-0");
+    }
+
+    /// Tests `uuid_v3`
+    #[pg_test]
+    fn test_uuid_v3() {
+        let expected = id("5df41881-3aed-3515-88a7-2f4a814cf09e");
+        assert_eq!(expected, uuid_v3("dns", "www.example.com"));
+        assert_eq!(Some(3), uuid_version(uuid_v3("x500", "CN=example")));
+    }
+
+    /// Tests `uuid_v8`
+    #[pg_test]
+    fn test_uuid_v8() {
+        let expected = id("5c146b14-3c52-8afd-938a-375d0df1fbf6");
+        assert_eq!(expected, uuid_v8("dns", "www.example.com"));
+        assert_eq!(
+            "rfc9562",
+            uuid_variant(uuid_v8("url", "https://example.com"))
+        );
+    }
+}
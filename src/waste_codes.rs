@@ -0,0 +1,561 @@
+//! `low_code`, `disposal_code` and `recovery_code` types.
+//!
+//! The types normalise their text input with [`parse_low_code`], [`parse_disposal_code`] and
+//! [`parse_recovery_code`], so columns can be typed instead of being `text` with check constraints.
+//! Each type has btree and hash operator classes, binary send/receive functions and the
+//! prefix operators `<@` ("is in chapter"/"is a sub-code of") and `@>`.
+//!
+//! ```sql
+//! select '17 05 03 *'::bfn.low_code;                        -- 170503*
+//! select '170503*'::bfn.low_code <@ '17'::bfn.low_code;     -- true
+//! select 'r01.1'::bfn.recovery_code <@ 'R1'::bfn.recovery_code; -- true
+//! ```
+#![allow(non_camel_case_types)]
+use crate::operations::split_operation_code;
+use crate::{parse_disposal_code, parse_low_code, parse_recovery_code};
+#[allow(unused_imports)]
+use pgrx::prelude::{
+    commutator, ereport, extension_sql, join, opname, pg_extern, pg_operator, pg_schema, pg_sys,
+    pg_test, restrict, InOutFuncs, PgSqlErrorCode, PostgresEq, PostgresHash, PostgresOrd,
+    PostgresType,
+};
+use pgrx::{Internal, StringInfo};
+use serde::{Deserialize, Serialize};
+use std::ffi::CStr;
+use std::fmt;
+
+/// LoW code in the form returned by `parse_low_code`, e.g. `17`, `1705` or `170503*`.
+#[derive(
+    Clone,
+    Debug,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Serialize,
+    Deserialize,
+    PostgresType,
+    PostgresEq,
+    PostgresOrd,
+    PostgresHash,
+)]
+#[inoutfuncs]
+pub struct low_code {
+    code: String,
+}
+
+impl low_code {
+    /// Parses any input accepted by [`parse_low_code`].
+    pub fn parse(value: &str) -> Option<low_code> {
+        Some(low_code {
+            code: parse_low_code(value)?,
+        })
+    }
+
+    /// Returns the code digits without the hazardous marker.
+    pub fn digits(&self) -> &str {
+        self.code.trim_end_matches('*')
+    }
+
+    /// Checks whether the code lies within `parent` (chapter, sub-chapter or the code itself).
+    pub fn is_within(&self, parent: &low_code) -> bool {
+        self.digits().starts_with(parent.digits())
+            && (self.code == parent.code || !parent.code.ends_with('*'))
+    }
+}
+
+impl fmt::Display for low_code {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.code)
+    }
+}
+
+impl InOutFuncs for low_code {
+    fn input(input: &CStr) -> low_code {
+        let value = input.to_string_lossy();
+        low_code::parse(&value).unwrap_or_else(|| invalid_input("low_code", &value))
+    }
+
+    fn output(&self, buffer: &mut StringInfo) {
+        buffer.push_str(&self.code);
+    }
+}
+
+/// Disposal operation code in the form returned by `parse_disposal_code`, without leading zeros
+/// in the operation number. National sub-codes are kept as written, e.g. `D10.02`.
+///
+/// Codes are ordered by operation number, so `D2` sorts before `D10`.
+#[derive(
+    Clone,
+    Debug,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Serialize,
+    Deserialize,
+    PostgresType,
+    PostgresEq,
+    PostgresOrd,
+    PostgresHash,
+)]
+#[inoutfuncs]
+pub struct disposal_code {
+    number: u8,
+    sub_code: Option<String>,
+}
+
+impl disposal_code {
+    /// Parses any input accepted by [`parse_disposal_code`].
+    pub fn parse(value: &str) -> Option<disposal_code> {
+        let (number, sub_code) = split_operation_code(&parse_disposal_code(value)?)?;
+        Some(disposal_code { number, sub_code })
+    }
+
+    /// Checks whether the code is `parent` itself or one of its national sub-codes.
+    pub fn is_within(&self, parent: &disposal_code) -> bool {
+        self.number == parent.number
+            && (parent.sub_code.is_none() || self.sub_code == parent.sub_code)
+    }
+}
+
+impl fmt::Display for disposal_code {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.sub_code {
+            Some(sub_code) => write!(f, "D{}.{}", self.number, sub_code),
+            None => write!(f, "D{}", self.number),
+        }
+    }
+}
+
+impl InOutFuncs for disposal_code {
+    fn input(input: &CStr) -> disposal_code {
+        let value = input.to_string_lossy();
+        disposal_code::parse(&value).unwrap_or_else(|| invalid_input("disposal_code", &value))
+    }
+
+    fn output(&self, buffer: &mut StringInfo) {
+        buffer.push_str(&self.to_string());
+    }
+}
+
+/// Recovery operation code in the form returned by `parse_recovery_code`, without leading zeros
+/// in the operation number. National sub-codes are kept as written, e.g. `R1.01`.
+///
+/// Codes are ordered by operation number, so `R2` sorts before `R10`.
+#[derive(
+    Clone,
+    Debug,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Serialize,
+    Deserialize,
+    PostgresType,
+    PostgresEq,
+    PostgresOrd,
+    PostgresHash,
+)]
+#[inoutfuncs]
+pub struct recovery_code {
+    number: u8,
+    sub_code: Option<String>,
+}
+
+impl recovery_code {
+    /// Parses any input accepted by [`parse_recovery_code`].
+    pub fn parse(value: &str) -> Option<recovery_code> {
+        let (number, sub_code) = split_operation_code(&parse_recovery_code(value)?)?;
+        Some(recovery_code { number, sub_code })
+    }
+
+    /// Checks whether the code is `parent` itself or one of its national sub-codes.
+    pub fn is_within(&self, parent: &recovery_code) -> bool {
+        self.number == parent.number
+            && (parent.sub_code.is_none() || self.sub_code == parent.sub_code)
+    }
+}
+
+impl fmt::Display for recovery_code {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.sub_code {
+            Some(sub_code) => write!(f, "R{}.{}", self.number, sub_code),
+            None => write!(f, "R{}", self.number),
+        }
+    }
+}
+
+impl InOutFuncs for recovery_code {
+    fn input(input: &CStr) -> recovery_code {
+        let value = input.to_string_lossy();
+        recovery_code::parse(&value).unwrap_or_else(|| invalid_input("recovery_code", &value))
+    }
+
+    fn output(&self, buffer: &mut StringInfo) {
+        buffer.push_str(&self.to_string());
+    }
+}
+
+/// Raises the PostgreSQL error for text that is not a valid value of the given type.
+fn invalid_input(type_name: &str, value: &str) -> ! {
+    ereport!(
+        ERROR,
+        PgSqlErrorCode::ERRCODE_INVALID_TEXT_REPRESENTATION,
+        format!("invalid input syntax for type {}: \"{}\"", type_name, value)
+    );
+}
+
+/// Reads the remaining bytes of a binary receive buffer as text.
+fn recv_text(buf: Internal) -> String {
+    // SAFETY: PostgreSQL passes the message buffer of a receive function as a `StringInfo`.
+    let buf = unsafe { buf.get_mut::<pg_sys::StringInfoData>() }
+        .expect("receive buffer must not be null");
+    let bytes = unsafe {
+        std::slice::from_raw_parts(
+            buf.data.add(buf.cursor as usize) as *const u8,
+            (buf.len - buf.cursor) as usize,
+        )
+    };
+    buf.cursor = buf.len;
+    String::from_utf8_lossy(bytes).to_string()
+}
+
+/// Binary input of `low_code`. The binary form is the text form, normalised like text input.
+#[pg_extern(immutable, parallel_safe, create_or_replace)]
+pub fn low_code_recv(buf: Internal) -> low_code {
+    let value = recv_text(buf);
+    low_code::parse(&value).unwrap_or_else(|| invalid_input("low_code", &value))
+}
+
+/// Binary output of `low_code`.
+#[pg_extern(immutable, parallel_safe, create_or_replace)]
+pub fn low_code_send(value: low_code) -> Vec<u8> {
+    value.code.into_bytes()
+}
+
+/// Binary input of `disposal_code`. The binary form is the text form, normalised like text input.
+#[pg_extern(immutable, parallel_safe, create_or_replace)]
+pub fn disposal_code_recv(buf: Internal) -> disposal_code {
+    let value = recv_text(buf);
+    disposal_code::parse(&value).unwrap_or_else(|| invalid_input("disposal_code", &value))
+}
+
+/// Binary output of `disposal_code`.
+#[pg_extern(immutable, parallel_safe, create_or_replace)]
+pub fn disposal_code_send(value: disposal_code) -> Vec<u8> {
+    value.to_string().into_bytes()
+}
+
+/// Binary input of `recovery_code`. The binary form is the text form, normalised like text input.
+#[pg_extern(immutable, parallel_safe, create_or_replace)]
+pub fn recovery_code_recv(buf: Internal) -> recovery_code {
+    let value = recv_text(buf);
+    recovery_code::parse(&value).unwrap_or_else(|| invalid_input("recovery_code", &value))
+}
+
+/// Binary output of `recovery_code`.
+#[pg_extern(immutable, parallel_safe, create_or_replace)]
+pub fn recovery_code_send(value: recovery_code) -> Vec<u8> {
+    value.to_string().into_bytes()
+}
+
+extension_sql!(
+    r#"
+ALTER TYPE low_code SET (SEND = low_code_send, RECEIVE = low_code_recv);
+ALTER TYPE disposal_code SET (SEND = disposal_code_send, RECEIVE = disposal_code_recv);
+ALTER TYPE recovery_code SET (SEND = recovery_code_send, RECEIVE = recovery_code_recv);
+"#,
+    name = "waste_codes_binary_io",
+    requires = [
+        low_code_send,
+        low_code_recv,
+        disposal_code_send,
+        disposal_code_recv,
+        recovery_code_send,
+        recovery_code_recv
+    ]
+);
+
+/// Checks whether a LoW code lies within another code.
+///
+/// Used as the `<@` operator, e.g. `'170503*'::bfn.low_code <@ '17'::bfn.low_code`.
+///
+/// # Parameters
+/// - `code`: The code to check.
+/// - `parent`: A chapter, sub-chapter or entry.
+///
+/// # Returns
+/// - `true` if `code` is `parent` itself or belongs to the chapter or sub-chapter `parent`.
+/// - `false` otherwise.
+#[pg_operator(immutable, parallel_safe, create_or_replace)]
+#[opname(<@)]
+#[commutator(@>)]
+#[restrict(contsel)]
+#[join(contjoinsel)]
+pub fn low_code_contained_by(code: low_code, parent: low_code) -> bool {
+    code.is_within(&parent)
+}
+
+/// Checks whether a LoW code contains another code. Used as the `@>` operator.
+///
+/// # Parameters
+/// - `parent`: A chapter, sub-chapter or entry.
+/// - `code`: The code to check.
+///
+/// # Returns
+/// - `true` if `code` is `parent` itself or belongs to the chapter or sub-chapter `parent`.
+/// - `false` otherwise.
+#[pg_operator(immutable, parallel_safe, create_or_replace)]
+#[opname(@>)]
+#[commutator(<@)]
+#[restrict(contsel)]
+#[join(contjoinsel)]
+pub fn low_code_contains(parent: low_code, code: low_code) -> bool {
+    code.is_within(&parent)
+}
+
+/// Checks whether a disposal code is another code or one of its national sub-codes.
+///
+/// Used as the `<@` operator, e.g. `'D10.2'::bfn.disposal_code <@ 'D10'::bfn.disposal_code`.
+///
+/// # Parameters
+/// - `code`: The code to check.
+/// - `parent`: The operation or sub-code.
+///
+/// # Returns
+/// - `true` if `code` is `parent` itself or one of its sub-codes.
+/// - `false` otherwise, e.g. `D1` is not within `D10`.
+#[pg_operator(immutable, parallel_safe, create_or_replace)]
+#[opname(<@)]
+#[commutator(@>)]
+#[restrict(contsel)]
+#[join(contjoinsel)]
+pub fn disposal_code_contained_by(code: disposal_code, parent: disposal_code) -> bool {
+    code.is_within(&parent)
+}
+
+/// Checks whether a disposal code contains another code. Used as the `@>` operator.
+///
+/// # Parameters
+/// - `parent`: The operation or sub-code.
+/// - `code`: The code to check.
+///
+/// # Returns
+/// - `true` if `code` is `parent` itself or one of its sub-codes.
+/// - `false` otherwise.
+#[pg_operator(immutable, parallel_safe, create_or_replace)]
+#[opname(@>)]
+#[commutator(<@)]
+#[restrict(contsel)]
+#[join(contjoinsel)]
+pub fn disposal_code_contains(parent: disposal_code, code: disposal_code) -> bool {
+    code.is_within(&parent)
+}
+
+/// Checks whether a recovery code is another code or one of its national sub-codes.
+///
+/// Used as the `<@` operator, e.g. `'R1.1'::bfn.recovery_code <@ 'R1'::bfn.recovery_code`.
+///
+/// # Parameters
+/// - `code`: The code to check.
+/// - `parent`: The operation or sub-code.
+///
+/// # Returns
+/// - `true` if `code` is `parent` itself or one of its sub-codes.
+/// - `false` otherwise, e.g. `R1` is not within `R10`.
+#[pg_operator(immutable, parallel_safe, create_or_replace)]
+#[opname(<@)]
+#[commutator(@>)]
+#[restrict(contsel)]
+#[join(contjoinsel)]
+pub fn recovery_code_contained_by(code: recovery_code, parent: recovery_code) -> bool {
+    code.is_within(&parent)
+}
+
+/// Checks whether a recovery code contains another code. Used as the `@>` operator.
+///
+/// # Parameters
+/// - `parent`: The operation or sub-code.
+/// - `code`: The code to check.
+///
+/// # Returns
+/// - `true` if `code` is `parent` itself or one of its sub-codes.
+/// - `false` otherwise.
+#[pg_operator(immutable, parallel_safe, create_or_replace)]
+#[opname(@>)]
+#[commutator(<@)]
+#[restrict(contsel)]
+#[join(contjoinsel)]
+pub fn recovery_code_contains(parent: recovery_code, code: recovery_code) -> bool {
+    code.is_within(&parent)
+}
+
+#[cfg(any(test, feature = "pg_test"))]
+#[pg_schema]
+mod tests {
+    use super::*;
+
+    /// Tests `low_code` text input normalisation and ordering
+    #[pg_test]
+    fn test_low_code() {
+        assert_eq!(
+            "170503*",
+            low_code::parse(" 17 05 03 * ").unwrap().to_string()
+        );
+        assert_eq!(low_code::parse("17-05-04"), low_code::parse("170504"));
+        assert!(low_code::parse("1705031").is_none());
+        assert!(low_code::parse("170503*").unwrap() < low_code::parse("170504").unwrap());
+    }
+
+    /// Tests `disposal_code` text input normalisation and ordering
+    #[pg_test]
+    fn test_disposal_code() {
+        assert_eq!("D1", disposal_code::parse(" d01 ").unwrap().to_string());
+        assert_eq!(
+            "D10.02",
+            disposal_code::parse("d10.02").unwrap().to_string()
+        );
+        assert_ne!(
+            disposal_code::parse("D10.02"),
+            disposal_code::parse("D10.2")
+        );
+        assert!(disposal_code::parse("R1").is_none());
+        assert!(disposal_code::parse("D2").unwrap() < disposal_code::parse("D10").unwrap());
+        assert!(disposal_code::parse("D10").unwrap() < disposal_code::parse("D10.1").unwrap());
+    }
+
+    /// Tests `recovery_code` text input normalisation and ordering
+    #[pg_test]
+    fn test_recovery_code() {
+        assert_eq!("R13", recovery_code::parse("r13").unwrap().to_string());
+        assert_eq!("R1.1", recovery_code::parse("R01.1").unwrap().to_string());
+        assert!(recovery_code::parse("D1").is_none());
+        assert!(recovery_code::parse("R3").unwrap() < recovery_code::parse("R12").unwrap());
+    }
+
+    /// Tests `low_code_send`
+    #[pg_test]
+    fn test_low_code_send() {
+        assert_eq!(
+            b"170503*".to_vec(),
+            low_code_send(low_code::parse("17 05 03*").unwrap())
+        );
+    }
+
+    /// Tests `disposal_code_send`
+    #[pg_test]
+    fn test_disposal_code_send() {
+        assert_eq!(
+            b"D10.02".to_vec(),
+            disposal_code_send(disposal_code::parse("d10.02").unwrap())
+        );
+    }
+
+    /// Tests `recovery_code_send`
+    #[pg_test]
+    fn test_recovery_code_send() {
+        assert_eq!(
+            b"R1".to_vec(),
+            recovery_code_send(recovery_code::parse("R01").unwrap())
+        );
+    }
+
+    /// Tests `low_code_contained_by`
+    #[pg_test]
+    fn test_low_code_contained_by() {
+        let code = low_code::parse("170503*").unwrap();
+        assert!(low_code_contained_by(
+            code.clone(),
+            low_code::parse("17").unwrap()
+        ));
+        assert!(low_code_contained_by(
+            code.clone(),
+            low_code::parse("1705").unwrap()
+        ));
+        assert!(low_code_contained_by(code.clone(), code.clone()));
+        assert!(!low_code_contained_by(
+            code.clone(),
+            low_code::parse("1709").unwrap()
+        ));
+        assert!(!low_code_contained_by(low_code::parse("17").unwrap(), code));
+    }
+
+    /// Tests `low_code_contains`
+    #[pg_test]
+    fn test_low_code_contains() {
+        let chapter = low_code::parse("20").unwrap();
+        assert!(low_code_contains(
+            chapter.clone(),
+            low_code::parse("200301").unwrap()
+        ));
+        assert!(!low_code_contains(
+            chapter,
+            low_code::parse("170504").unwrap()
+        ));
+    }
+
+    /// Tests `disposal_code_contained_by`
+    #[pg_test]
+    fn test_disposal_code_contained_by() {
+        let d10 = disposal_code::parse("D10").unwrap();
+        assert!(disposal_code_contained_by(
+            disposal_code::parse("D10.2").unwrap(),
+            d10.clone()
+        ));
+        assert!(disposal_code_contained_by(d10.clone(), d10.clone()));
+        assert!(!disposal_code_contained_by(
+            disposal_code::parse("D1").unwrap(),
+            d10.clone()
+        ));
+        assert!(!disposal_code_contained_by(
+            d10,
+            disposal_code::parse("D10.2").unwrap()
+        ));
+    }
+
+    /// Tests `disposal_code_contains`
+    #[pg_test]
+    fn test_disposal_code_contains() {
+        let d1 = disposal_code::parse("D1").unwrap();
+        assert!(disposal_code_contains(
+            d1.clone(),
+            disposal_code::parse("D1.5").unwrap()
+        ));
+        assert!(!disposal_code_contains(
+            d1,
+            disposal_code::parse("D11").unwrap()
+        ));
+    }
+
+    /// Tests `recovery_code_contained_by`
+    #[pg_test]
+    fn test_recovery_code_contained_by() {
+        let r1 = recovery_code::parse("R1").unwrap();
+        assert!(recovery_code_contained_by(
+            recovery_code::parse("R1.1").unwrap(),
+            r1.clone()
+        ));
+        assert!(!recovery_code_contained_by(
+            recovery_code::parse("R10").unwrap(),
+            r1
+        ));
+    }
+
+    /// Tests `recovery_code_contains`
+    #[pg_test]
+    fn test_recovery_code_contains() {
+        let r5 = recovery_code::parse("R5").unwrap();
+        assert!(recovery_code_contains(
+            r5.clone(),
+            recovery_code::parse("r5.2").unwrap()
+        ));
+        assert!(!recovery_code_contains(
+            r5,
+            recovery_code::parse("R4.2").unwrap()
+        ));
+    }
+}